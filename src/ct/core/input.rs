@@ -36,10 +36,19 @@ impl Input {
 		self.code[self.index as usize]
 	}
 
+	// One character of lookahead past `peek`, used to tell `1.5` apart from `1..5`
+	pub fn peek_next(&self) -> Option<char> {
+		self.code.get(self.index as usize + 1).cloned()
+	}
+
 	pub fn eof(&self) -> bool {
 		self.index as usize >= self.code.len()
 	}
 
+	pub fn warn(&self, message: &str) {
+		eprintln!("Warning at (line: {}, col: {}): \n{}\n", self.line, self.col, message);
+	}
+
 	pub fn error(&self, message: &str) {
 		panic!(format!("Error at (line: {}, col: {}): \n{}\n", self.line, self.col, message));
 	}
@@ -1,16 +1,44 @@
 use ct::core::parser::Parser;
 use ct::env::Environment;
+use ct::modules::iostream;
 use ct::syntax::std::*;
+use ct::utils::iter::{Frame, Generator, Iter, SharedIter};
+use ct::utils::limits::{self, Interrupt, Limits, Reason};
 use ct::utils::module::Module;
 use ct::utils::predefs::Predefs;
 use ct::utils::structure::Struct;
 use ct::utils::structure::Structures;
 
+use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+// Natives implemented by the interpreter itself, because they call back into scripts or need frozen values intact
+const INTRINSICS: [&str; 18] = [
+  "freeze", "is_frozen", "map", "filter", "reduce", "any", "all", "find", "sort_by", "range", "iter",
+  "next", "collect", "enumerate", "take", "skip", "zip", "chain",
+];
+
+// Calls nested deeper than this return an error instead of overflowing the stack, see main.rs
+pub const MAX_CALL_DEPTH: usize = 1000;
+
+// Natives whose output counts against the output limit
+const OUTPUT_NATIVES: [&str; 3] = ["print", "input", "flush"];
+
 pub struct Interpreter {
   parser: Parser,
   environments: Vec<Environment>,
-  structs: Structures,
+  // Scripts declare structs while running, next to the native ones registered up front
+  structs: RefCell<Structures>,
   predefs: Predefs,
+  calls: Cell<usize>,
+  max_calls: usize,
+  limits: Limits,
+  steps: Cell<u64>,
+  written: Cell<usize>,
+  // Why the script stopped, nothing evaluates anymore once a limit is hit or it is interrupted
+  stopped: RefCell<Option<String>>,
 }
 
 impl Interpreter {
@@ -18,21 +46,76 @@ impl Interpreter {
     Interpreter {
       parser: Parser::new(code),
       environments: Vec::new(),
-      structs: Structures::new(),
+      structs: RefCell::new(Structures::new()),
       predefs: Predefs::new(),
+      calls: Cell::new(0),
+      max_calls: MAX_CALL_DEPTH,
+      limits: Limits::default(),
+      steps: Cell::new(0),
+      written: Cell::new(0),
+      stopped: RefCell::new(None),
+    }
+  }
+
+  pub fn set_max_call_depth(&mut self, depth: usize) {
+    self.max_calls = depth;
+  }
+
+  pub fn set_limits(&mut self, limits: Limits) {
+    self.limits = limits;
+  }
+
+  // Another thread can stop the script through this while it runs
+  pub fn interrupt_handle(&self) -> Interrupt {
+    self.predefs.interrupt()
+  }
+
+  // The code a script passed to `exit`, the host decides what to do with it
+  pub fn exit_code(&self) -> Option<i32> {
+    match self.predefs.interrupt().reason() {
+      Some(Reason::Exit(code)) => Some(code),
+      _ => None,
     }
   }
 
-  pub fn execute(&mut self) {
+  // Sandboxes swap out natives a script must not reach, like `exit`, `sleep` or `run`, after the
+  // modules are appended
+  pub fn replace_native(&mut self, name: &str, function: Box<dyn Fn(Vec<CtToken>) -> CtToken>) {
+    self.predefs.insert(String::from(name), function);
+  }
+
+  // An uncaught error stops the script, as does a limit or an interrupt. A script that calls
+  // `exit` ends without an error
+  pub fn execute(&mut self) -> Result<(), String> {
+    // The timer stops once it is dropped, when the script ends early or is aborted
+    let interrupt = self.predefs.interrupt();
+    let _timer = self.limits.time.map(|time| interrupt.after(time));
+
     let mut token = self.parser.next();
     let mut environments = vec![Environment::new()];
 
     while token.is_some() {
-      self.evaluate(token, &mut environments);
+      // A `return` between the statements of the script still runs what it returns
+      match token {
+        Some(Token::Return(value)) => self.returned(*value, &mut environments),
+        token => {
+          let is_call = Interpreter::is_call(&token);
+          let result = self.statement(token, &mut environments);
+          self.discard(is_call, result)
+        }
+      };
+      if let Some(reason) = self.stopped.borrow().clone() {
+        self.environments = environments;
+        return match interrupt.reason() {
+          Some(Reason::Exit(_)) => Ok(()),
+          _ => Err(reason),
+        };
+      }
       token = self.parser.next();
     }
 
     self.environments = environments;
+    Ok(())
   }
 
   pub fn append_module(&mut self, module: impl Module) {
@@ -40,21 +123,175 @@ impl Interpreter {
   }
 
   pub fn append_struct(&mut self, name: &str, structure: Struct) {
-    self.structs.append(name, structure);
+    if let Err(e) = self.structs.get_mut().append(name, structure) {
+      self.error(&e);
+    }
   }
 
+  // Values of expressions, which cannot end the function or loop around them like a statement can
   fn evaluate(&self, raw: CtToken, environments: &mut Vec<Environment>) -> CtToken {
+    match self.statement(raw, environments) {
+      Some(Token::Return(_)) => {
+        self.error("return can only be used as a statement, not as part of an expression")
+      }
+      Some(Token::Break) | Some(Token::Continue) => self
+        .error("break and continue can only be used as statements, not as part of an expression"),
+      value => value,
+    }
+  }
+
+  // Runs a statement of a block or a function body, whose `return`, `break` or `continue` comes
+  // back as a signal for the enclosing block to act on. Each evaluation is a step towards the
+  // limits, and each value it gives is checked against them
+  fn statement(&self, raw: CtToken, environments: &mut Vec<Environment>) -> CtToken {
+    if let Err(stopped) = self.step() {
+      return stopped;
+    }
+    let value = self.evaluate_step(raw, environments);
+    match self.check_memory(&value, environments) {
+      Ok(()) => value,
+      Err(stopped) => stopped,
+    }
+  }
+
+  // A value may not take more than the limit on its own, nor may the variables of all running
+  // scopes hold more than it together
+  fn check_memory(&self, value: &CtToken, environments: &[Environment]) -> Result<(), CtToken> {
+    let memory = match self.limits.memory {
+      Some(memory) => memory,
+      None => return Ok(()),
+    };
+
+    let size = value.as_ref().map_or(0, limits::size_of);
+    if size > memory {
+      return Err(self.stop(format!(
+        "Memory limit of {} bytes exceeded by a {} of {} bytes",
+        memory,
+        get_type(value),
+        size
+      )));
+    }
+    let held = environments
+      .iter()
+      .map(Environment::size)
+      .fold(0, usize::saturating_add);
+    if held > memory {
+      return Err(self.stop(format!(
+        "Memory limit of {} bytes exceeded, variables hold {} bytes",
+        memory, held
+      )));
+    }
+    Ok(())
+  }
+
+  // Gives back the error the script stopped with, when it has to stop
+  fn step(&self) -> Result<(), CtToken> {
+    self.interrupted()?;
+
+    if let Some(fuel) = self.limits.fuel {
+      let steps = self.steps.get() + 1;
+      if steps > fuel {
+        return Err(self.stop(format!("Fuel limit of {} evaluation steps exceeded", fuel)));
+      }
+      self.steps.set(steps);
+    }
+    Ok(())
+  }
+
+  fn interrupted(&self) -> Result<(), CtToken> {
+    if let Some(reason) = self.stopped.borrow().clone() {
+      return Err(Some(Token::Error(reason)));
+    }
+
+    let interrupt = self.predefs.interrupt();
+    if interrupt.is_interrupted() {
+      return Err(self.stop(match interrupt.reason() {
+        Some(Reason::TimedOut) => format!(
+          "Time limit of {}ms exceeded",
+          self.limits.time.map_or(0, |time| time.as_millis())
+        ),
+        Some(Reason::Exit(code)) => format!("Exited with code {}", code),
+        _ => String::from("Execution interrupted"),
+      }));
+    }
+    Ok(())
+  }
+
+  fn write(&self, output: &[CtToken]) -> Result<(), CtToken> {
+    if let Some(limit) = self.limits.output {
+      let written = self.written.get() + iostream::format(output).len();
+      if written > limit {
+        return Err(self.stop(format!("Output limit of {} bytes exceeded", limit)));
+      }
+      self.written.set(written);
+    }
+    Ok(())
+  }
+
+  // Stops the script, every evaluation after this gives the same error until `execute` returns it
+  fn stop(&self, reason: String) -> CtToken {
+    let mut stopped = self.stopped.borrow_mut();
+    Some(Token::Error(stopped.get_or_insert(reason).clone()))
+  }
+
+  // Script errors abort through the parser, unless the script is already stopping, in which case
+  // the values it gets are the stop error and not what the script is mistaken about
+  fn error(&self, message: &str) -> CtToken {
+    let stopped = self.stopped.borrow().clone();
+    match stopped {
+      Some(reason) => Some(Token::Error(reason)),
+      None => self.parser.error(message),
+    }
+  }
+
+  fn evaluate_step(&self, raw: CtToken, environments: &mut Vec<Environment>) -> CtToken {
     match raw {
       Some(token) => match token {
         Token::Integer(_)
         | Token::Float(_)
         | Token::Boolean(_)
         | Token::String(_)
+        | Token::Null
+        | Token::Structure { .. }
+        | Token::EnumValue { .. }
+        | Token::Frozen(_)
+        | Token::Closure { .. }
+        | Token::Native(_)
+        | Token::TailCall(_)
+        | Token::Iterator(_)
         | Token::Return(_)
         | Token::Break
         | Token::Continue => Some(token),
+        Token::Array(items) => {
+          let mut values = Vec::new();
+          for item in items {
+            values.push(match item {
+              None => None,
+              Some(value) => self.evaluate(Some(value), environments),
+            });
+          }
+
+          Some(Token::Array(values))
+        }
+
+        Token::Map(entries) => {
+          let mut values = BTreeMap::new();
+          for (key, value) in entries {
+            values.insert(
+              key,
+              match value {
+                None => None,
+                Some(value) => self.evaluate(Some(value), environments),
+              },
+            );
+          }
+
+          Some(Token::Map(values))
+        }
+
         Token::VariableCall(name) => match Environment::lookup(environments, &name) {
           Some(environment) => environment.get(&name),
+          None if self.is_native(&name) => Some(Token::Native(name)),
           None => self
             .parser
             .error(&format!("Unable to find variable: {}", name)),
@@ -66,7 +303,7 @@ impl Interpreter {
           right,
         } => {
           if left.is_none() || right.is_none() {
-            return self.parser.error(&format!(
+            return self.error(&format!(
               "Unable to parse binary expression: {} {} {}",
               left.unwrap(),
               operator,
@@ -74,37 +311,176 @@ impl Interpreter {
             ));
           }
 
+          // The right hand side is only evaluated when the left hand side is null
+          if operator == "??" {
+            let left = self.evaluate(*left, environments);
+            return match left {
+              None | Some(Token::Null) => self.evaluate(*right, environments),
+              value => value,
+            };
+          }
+
+          // `value is Type` compares type names, counting what a struct extends or implements, and
+          // `value is Enum.Variant` also checks the variant
+          if operator == types::IS || operator == types::INSTANCEOF {
+            let value = self.evaluate(*left, environments);
+            return Some(Token::Boolean(match right.unwrap() {
+              Token::VariableCall(name) => {
+                let type_name = get_type(&value);
+                type_name == name
+                  || self
+                    .structs
+                    .borrow()
+                    .get(&type_name)
+                    .is_some_and(|structure| structure.is_a(&name))
+              }
+              Token::Null => matches!(value, None | Some(Token::Null)),
+              Token::MemberAccess {
+                target,
+                attribute,
+                args: None,
+                ..
+              } => match *target {
+                Some(Token::VariableCall(name)) => matches!(
+                  &value,
+                  Some(Token::EnumValue { name: enum_name, variant, .. }) if *enum_name == name && *variant == attribute
+                ),
+                t => {
+                  return self.error(&format!(
+                    "Expected an enum variant after 'is', but received: {}",
+                    t.map_or(String::from(types::NULL), |t| t.to_string())
+                  ))
+                }
+              },
+              t => {
+                return self
+                  .parser
+                  .error(&format!("Expected a type after 'is', but received: {}", t))
+              }
+            }));
+          }
+
           if operator == "=" {
             let left = left.unwrap();
             return match left {
-              Token::Variable {
-                name,
-                return_type: _,
-              } => {
+              Token::Variable { ref name, .. } | Token::Constant(ref name) => {
+                // Shadowing a constant in an inner scope is fine, redeclaring it in its own scope is not
+                if environments.last().unwrap().is_constant(name) {
+                  return self
+                    .parser
+                    .error(&format!("Cannot redeclare constant: {}", name));
+                }
+
                 let right = self.evaluate(*right, environments);
-                environments.last_mut().unwrap().define(name, right)
+                let scope = environments.last_mut().unwrap();
+                match left {
+                  Token::Constant(name) => scope.define_constant(name, right),
+                  Token::Variable { name, .. } => scope.define(name, right),
+                  _ => None,
+                }
               }
 
-              Token::VariableCall(name) => {
+              Token::VariableCall(_)
+              | Token::MemberAccess { args: None, .. }
+              | Token::Index { .. } => {
+                let right = self.evaluate(*right, environments);
+                self.assign(left, right, environments)
+              }
+
+              Token::Destructure { pattern, constant } => {
                 let right = self.evaluate(*right, environments);
-                match Environment::lookup(environments, &name) {
-                  Some(mut environment) => environment.set(&name, right),
-                  None => self.parser.error(&format!("Unknown variable: {}", name)),
+                let mut bindings = Vec::new();
+                if !Interpreter::match_pattern(&pattern, &right, &mut bindings) {
+                  return self.error(&Interpreter::shape_mismatch(&pattern, &right));
+                }
+
+                for (name, value) in bindings {
+                  let scope = environments.last_mut().unwrap();
+                  if scope.is_constant(&name) {
+                    return self
+                      .parser
+                      .error(&format!("Cannot redeclare constant: {}", name));
+                  }
+                  if constant {
+                    scope.define_constant(name, value);
+                  } else {
+                    scope.define(name, value);
+                  }
                 }
+                None
+              }
+
+              // `[a, b] = [b, a]`, the right hand side is evaluated before anything is assigned
+              Token::Array(targets) => {
+                let right = self.evaluate(*right, environments);
+                self.assign_all(targets, right, environments);
+                None
               }
 
-              _ => self.parser.error(&format!(
+              _ => self.error(&format!(
                 "Unable to assign right hand value to left hand variable: {} = {}",
                 left,
                 right.unwrap()
               )),
             };
+          }
+
+          // `a += b` is `a = a + b`, for variables and fields alike
+          if let Some(op) = operator
+            .strip_suffix('=')
+            .filter(|op| ["+", "-", "*", "/", "%"].contains(op))
+          {
+            let target = left.unwrap();
+            if !matches!(
+              target,
+              Token::VariableCall(_) | Token::MemberAccess { args: None, .. } | Token::Index { .. }
+            ) {
+              return self.error(&format!(
+                "Unable to apply {} to left hand value: {}",
+                operator, target
+              ));
+            }
+
+            let current = self.evaluate(Some(target.clone()), environments);
+            let right = self.evaluate(*right, environments);
+            let value = self.binary(String::from(op), current, right, environments);
+            return self.assign(target, value, environments);
+          }
+
+          let left = self.evaluate(*left, environments);
+          let right = self.evaluate(*right, environments);
+          self.binary(operator, left, right, environments)
+        }
+
+        Token::Constant(name) => self
+          .parser
+          .error(&format!("Constant {} must be given a value", name)),
+
+        Token::Destructure { .. } => self
+          .parser
+          .error("A destructuring declaration must be given a value"),
+
+        Token::Conditional {
+          condition,
+          then,
+          otherwise,
+        } => match self.evaluate(*condition, environments) {
+          Some(Token::Boolean(true)) => self.evaluate(*then, environments),
+          Some(Token::Boolean(false)) => self.evaluate(*otherwise, environments),
+          value => self.error(&format!(
+            "Expected boolean expression inside conditional, but received: {}",
+            get_type(&value)
+          )),
+        },
+
+        Token::Enum { name, variants } => {
+          if Environment::lookup(environments, &name).is_some() {
+            self.error(&format!("Enum already exists: {}", name))
           } else {
-            self.apply_binary(
-              operator,
-              self.evaluate(*left, environments),
-              self.evaluate(*right, environments),
-            )
+            environments
+              .last_mut()
+              .unwrap()
+              .define(name.clone(), Some(Token::Enum { name, variants }))
           }
         }
 
@@ -114,67 +490,127 @@ impl Interpreter {
               .parser
               .error(&format!("Function already exists: {}", header.name))
           } else {
+            let captured = Interpreter::capture(environments);
             environments.last_mut().unwrap().define(
               header.name.clone(),
-              Some(Token::Function(Function { header, body })),
+              Some(Token::Closure {
+                function: Function { header, body },
+                captured,
+              }),
             )
           }
         }
 
+        Token::StructDeclaration {
+          name,
+          parent,
+          interfaces,
+          fields,
+          methods,
+        } => {
+          if self.structs.borrow().contains(&name) {
+            return self
+              .parser
+              .error(&format!("Struct already exists: {}", name));
+          }
+
+          let parent = match parent {
+            Some(parent) => match self.structs.borrow().get(&parent) {
+              Some(structure) => Some(structure.clone()),
+              None => {
+                return self
+                  .parser
+                  .error(&format!("Unknown struct {} extended by {}", parent, name))
+              }
+            },
+            None => None,
+          };
+
+          let structure = Struct::declare(&name, parent, interfaces, fields, methods);
+          let appended = self.structs.borrow_mut().append(&name, structure);
+          match appended {
+            Ok(()) => None,
+            Err(e) => self.error(&e),
+          }
+        }
+
+        Token::InterfaceDeclaration { name, methods } => {
+          let appended = self.structs.borrow_mut().append_interface(&name, methods);
+          match appended {
+            Ok(()) => None,
+            Err(e) => self.error(&e),
+          }
+        }
+
+        Token::Index { target, index } => {
+          // Items of a variable are read in place, copying the whole array out would make every
+          // `a[i]` linear in its length
+          if let Some(Token::VariableCall(name)) = &*target {
+            if Environment::lookup(environments, name).is_some() {
+              let index = self.evaluate(*index, environments);
+              if let Some(item) = Interpreter::index_in_place(name, &index, environments) {
+                return item;
+              }
+              let target = self.evaluate(*target, environments);
+              return self.index(target, index, environments);
+            }
+          }
+
+          let target = self.evaluate(*target, environments);
+          let index = self.evaluate(*index, environments);
+          self.index(target, index, environments)
+        }
+
+        Token::Lambda(function) => Some(Token::Closure {
+          function,
+          captured: Interpreter::capture(environments),
+        }),
+
         Token::FunctionCall { name, args } => {
-          if self.predefs.contains(&name) {
-            let mut eval_args = Vec::new();
-            for arg in args {
-              eval_args.push(match arg {
-                None => None,
-                Some(value) => self.evaluate(Some(value), environments),
-              });
+          // Callable variables and declared structs win over natives of the same name, so scripts
+          // may reuse names such as `freeze` or `range`
+          let callee = match Environment::lookup(environments, &name).map(|e| e.get(&name)) {
+            Some(value @ Some(Token::Closure { .. }))
+            | Some(value @ Some(Token::Function(_)))
+            | Some(value @ Some(Token::Native(_))) => value,
+            None if self.is_declared(&name) => return self.construct(&name, args, environments),
+            _ if self.is_native(&name) => Some(Token::Native(name.clone())),
+            Some(value) => {
+              return self.error(&format!(
+                "Unable to call {}, it is not a function: {}",
+                name,
+                get_type(&value)
+              ))
             }
+            None => return self.error(&format!("Unknown function: {}", name)),
+          };
 
-            self.predefs.execute(&name, eval_args)
-          } else {
-            let function = match Environment::lookup(environments, &name) {
-              Some(e) => e.get(&name),
-              None => self.parser.error(&format!("Unknown function: {}", name)),
-            };
+          let (positional, named) = self.evaluate_arguments(args, environments);
+          self.call(&name, callee, positional, named, environments)
+        }
 
-            self.execute_function(&name, function, &args, environments)
-          }
+        Token::Invoke { callee, args } => {
+          let callee = self.evaluate(*callee, environments);
+          let name = match &callee {
+            Some(Token::Native(name)) => name.clone(),
+            _ => String::from(types::ANONYMOUS),
+          };
+          let (positional, named) = self.evaluate_arguments(args, environments);
+          self.call(&name, callee, positional, named, environments)
         }
 
         Token::If(ifs) => {
-          for i in ifs.iter() {
-            let condition = self.evaluate((*i.condition).clone(), environments);
-            if let Some(value) = condition {
-              if let Token::Boolean(b) = value {
-                if b {
-                  let mut scope = Environment::new();
-                  environments.push(scope);
-                  for raw in i.body.iter() {
-                    let t = self.evaluate((*raw).clone(), environments);
-                    if t.is_none() {
-                      continue;
-                    }
-                    let t = t.unwrap();
-                    if let Token::Break = &t {
-                      return Some(Token::Break);
-                    } else if let Token::Continue = &t {
-                      return Some(Token::Continue);
-                    }
-                    let maybe_return = self.unwrap_return(Some(t), environments);
-                    if maybe_return.is_some() {
-                      return maybe_return;
-                    }
-                  }
-                  break;
-                } else {
-                  continue;
-                }
-              } else {
-                return self.parser.error(&format!(
+          for i in ifs {
+            match self.evaluate(*i.condition, environments) {
+              Some(Token::Boolean(true)) => {
+                return self.block(&i.body, Environment::new(), environments)
+              }
+              Some(Token::Boolean(false)) | None => (),
+              Some(value) => {
+                return self.error(&format!(
                   "Expected boolean expression inside if statement, but received: {}",
                   value
-                ));
+                ))
               }
             }
           }
@@ -182,246 +618,3214 @@ impl Interpreter {
           None
         }
 
-        Token::While { condition, body } => {
-          let cond = self.evaluate((*condition).clone(), environments);
-          if cond.is_some() {
-            let mut cond = cond.unwrap();
-            while let Token::Boolean(true) = cond {
-              match {
-                let scope = Environment::new();
-                environments.push(scope);
-                for raw in body.iter() {
-                  let t = self.evaluate((*raw).clone(), environments);
-                  if t.is_none() {
-                    continue;
-                  }
-                  let t = t.unwrap();
-                  if let Token::Break = t {
-                    return None;
-                  } else if let Token::Continue = t {
-                    break;
-                  }
-                }
+        Token::Match { value, arms } => {
+          let value = self.evaluate(*value, environments);
 
-                None
-              } {
-                Some(value) => return Some(value),
-                None => (),
+          for arm in arms {
+            let mut bindings = Vec::new();
+            if !Interpreter::match_pattern(&arm.pattern, &value, &mut bindings) {
+              continue;
+            }
+
+            let mut scope = Environment::new();
+            for (name, bound) in bindings {
+              scope.define(name, bound);
+            }
+            environments.push(scope);
+
+            if arm.guard.is_some() {
+              match self.evaluate(*arm.guard, environments) {
+                Some(Token::Boolean(true)) => (),
+                Some(Token::Boolean(false)) => {
+                  environments.pop();
+                  continue;
+                }
+                guard => {
+                  return self.error(&format!(
+                    "Expected boolean expression as match guard, but received: {}",
+                    get_type(&guard)
+                  ))
+                }
               }
+            }
+
+            // The arm evaluates to its last statement; `return`, `break` and `continue` reach the enclosing block
+            let mut result = None;
+            let last = arm.body.len().saturating_sub(1);
+            for (i, raw) in arm.body.into_iter().enumerate() {
+              let discarded = i < last && Interpreter::is_call(&raw);
+              let value = self.statement(raw, environments);
+              result = match self.discard(discarded, value) {
+                Some(Token::Return(value)) => {
+                  let value = self.returned(*value, environments);
+                  environments.pop();
+                  return Some(Token::Return(Box::new(value)));
+                }
+                Some(Token::Break) => {
+                  environments.pop();
+                  return Some(Token::Break);
+                }
+                Some(Token::Continue) => {
+                  environments.pop();
+                  return Some(Token::Continue);
+                }
+                value => value,
+              };
+            }
+
+            environments.pop();
+            return result;
+          }
+
+          Some(Token::Null)
+        }
+
+        Token::For {
+          pattern,
+          iterable,
+          body,
+        } => {
+          let iterable = self.evaluate(*iterable, environments);
+          let iter = match self.to_iterator(iterable, environments) {
+            Ok(iter) => iter,
+            Err(Some(Token::Error(e))) => return self.error(&e),
+            Err(e) => return e,
+          };
+
+          loop {
+            // A failing `next()` stops the script like a value that cannot be iterated at all
+            let item = match self.advance(&iter, environments) {
+              Ok(Some(item)) => item,
+              Ok(None) => break,
+              Err(Some(Token::Error(e))) => return self.error(&e),
+              Err(e) => return e,
+            };
+
+            let mut bindings = Vec::new();
+            if !Interpreter::match_pattern(&pattern, &item, &mut bindings) {
+              return self.error(&format!(
+                "For loop pattern does not match: {}",
+                item.map_or(String::from(types::NULL), |t| t.to_string())
+              ));
+            }
+
+            let mut scope = Environment::new();
+            for (name, value) in bindings {
+              scope.define(name, value);
+            }
 
-              cond = self.evaluate((*condition).clone(), environments).unwrap();
+            match self.block(&body, scope, environments) {
+              Some(Token::Break) => break,
+              Some(signal @ Token::Return(_)) => return Some(signal),
+              _ => (),
             }
           }
 
           None
         }
 
-        Token::StructureCall {
-          name,
+        Token::Yield(_) => self.error(
+          "yield can only be used as a statement of a generator, or inside its if, while and for blocks",
+        ),
+
+        Token::While { condition, body } => {
+          while let Some(Token::Boolean(true)) = self.evaluate((*condition).clone(), environments) {
+            match self.block(&body, Environment::new(), environments) {
+              Some(Token::Break) => break,
+              Some(signal @ Token::Return(_)) => return Some(signal),
+              _ => (),
+            }
+          }
+
+          None
+        }
+
+        Token::MemberAccess {
+          target,
           attribute,
           args,
+          optional,
         } => {
-          let mut var = None;
-          let structure = self
-            .structs
-            .get(&match Environment::lookup(environments, &name) {
-              Some(environment) => {
-                var = environment.get(&name);
-                get_type(&var)
-              }
+          let is_super =
+            matches!(&*target, Some(Token::VariableCall(name)) if name == types::SUPER);
+          if is_super && Environment::lookup(environments, &String::from(types::SUPER)).is_none() {
+            return self
+              .parser
+              .error("super can only be used in methods of a struct that extends another");
+          }
+          let var = self.evaluate((*target).clone(), environments);
+
+          if let Some(Token::Super { parent, receiver }) = var {
+            return self.call_super(&parent, &receiver, attribute, args, environments);
+          }
+
+          if optional && matches!(var, None | Some(Token::Null)) {
+            return Some(Token::Null);
+          }
+
+          // An error passes through untouched, `regex("(").matches(s)` gives the compile error
+          if let Some(Token::Error(_)) = var {
+            return var;
+          }
+
+          let frozen = matches!(var, Some(Token::Frozen(_)));
+          let var = thaw(var);
+
+          // `Shape.Circle(1)` constructs a variant, unit variants need no parentheses
+          if let Some(Token::Enum { name, variants }) = &var {
+            let field_names = match variants.iter().find(|(variant, _)| *variant == attribute) {
+              Some((_, field_names)) => field_names,
               None => {
-                self
+                return self
                   .parser
-                  .error(&format!("Unable to find variable: {}", name));
-                String::from(types::NULL)
+                  .error(&format!("Unknown variant {} of enum {}", attribute, name))
               }
+            };
+
+            let args = args.unwrap_or_default();
+            if args.len() != field_names.len() {
+              return self.error(&format!(
+                "Expected {} arguments for {}.{}, but received {}",
+                field_names.len(),
+                name,
+                attribute,
+                args.len()
+              ));
+            }
+
+            let mut fields = Vec::new();
+            for (field, arg) in field_names.iter().zip(args) {
+              let value = match arg {
+                None => None,
+                Some(value) => self.evaluate(Some(value), environments),
+              };
+              fields.push((field.clone(), value));
+            }
+
+            return Some(Token::EnumValue {
+              name: name.clone(),
+              variant: attribute,
+              fields,
             });
+          }
+
+          if args.is_none() {
+            let field = match &var {
+              Some(Token::Structure { name: _, fields }) => fields.get(&attribute).cloned(),
+              Some(Token::Map(entries)) => Some(entries.get(&attribute).cloned().unwrap_or(None)),
+              Some(Token::EnumValue { fields, .. }) => fields
+                .iter()
+                .find(|(field, _)| *field == attribute)
+                .map(|(_, value)| value.clone()),
+              _ => None,
+            };
+
+            // Fields read from a frozen value are frozen as well
+            if let Some(value) = field {
+              return if frozen {
+                Interpreter::freeze(value)
+              } else {
+                value
+              };
+            }
+          }
 
-          let value = structure.get(&attribute);
-          if value.is_some() {
-            if args.is_some() {
-              let mut real_args = vec![var];
-              real_args.extend_from_slice(&args.unwrap());
-              self.execute_function(&attribute, value, &real_args, environments)
-            } else {
-              value
+          let value = self.method(&var, &attribute);
+          let args = match args {
+            Some(args) if value.is_some() => args,
+            Some(_) => {
+              return Some(Token::Error(format!(
+                "{} has no method {}",
+                get_type(&var),
+                attribute
+              )))
             }
-          } else {
-            None
+            None => return value,
+          };
+
+          let (mut positional, named) = self.evaluate_arguments(args, environments);
+          positional.insert(0, var.clone());
+
+          // Changes a method makes to a struct or map stick to the variable it was called on
+          let mut receiver = var.clone();
+          let owner = get_type(&var);
+          let arguments = (positional, named);
+          let result =
+            self.invoke_method(&owner, &attribute, value, arguments, &mut receiver, environments);
+
+          // Temporaries such as `make().push(1)` have nowhere to write back to
+          let assignable = matches!(
+            &*target,
+            Some(Token::VariableCall(_))
+              | Some(Token::MemberAccess { args: None, .. })
+              | Some(Token::Index { .. })
+          );
+          if assignable
+            && !frozen
+            && matches!(var, Some(Token::Structure { .. }) | Some(Token::Map(_)))
+            && !values_equal(&var, &receiver)
+          {
+            self.assign(target.unwrap(), receiver, environments);
           }
+          result
         }
 
-        _ => self.parser.error(&format!("Unable to evaluate: {}", token)),
+        _ => self.error(&format!("Unable to evaluate: {}", token)),
       },
-      None => self.parser.error("Unable to evaluate: None"),
+      None => self.error("Unable to evaluate: None"),
     }
   }
 
-  fn execute_function(
+  // Runs the statements of an if, while or for body in a scope of their own, which is gone again
+  // however the block ends. Gives back the `return`, `break` or `continue` that ended it, if any,
+  // with the returned value already evaluated while the block's variables were still in scope
+  fn block(
     &self,
-    name: &String,
-    function: CtToken,
-    args: &Vec<CtToken>,
+    body: &[CtToken],
+    scope: Environment,
     environments: &mut Vec<Environment>,
   ) -> CtToken {
-    match function.unwrap() {
-      Token::Function(Function { header, body }) => {
-        let mut scope = Environment::new();
-        if args.len() != header.args.len() {
-          self.parser.error("Expected the amount of function parameters to be the same as the amount of given arguments");
+    let depth = environments.len();
+    environments.push(scope);
+
+    let mut signal = None;
+    for raw in body {
+      let result = self.statement(raw.clone(), environments);
+      match self.discard(Interpreter::is_call(raw), result) {
+        Some(Token::Return(value)) => {
+          let value = self.returned(*value, environments);
+          signal = Some(Token::Return(Box::new(value)));
+          break;
+        }
+        Some(token @ Token::Break) | Some(token @ Token::Continue) => {
+          signal = Some(token);
+          break;
         }
+        _ => (),
+      }
+    }
 
-        for i in 0..args.len() {
-          let t = self.evaluate(args[i].clone(), environments);
+    environments.truncate(depth);
+    signal
+  }
 
-          // TODO: Handle possible type differences
-          let name = String::from(
-            if let Token::Variable {
-              name,
-              return_type: _,
-            } = &header.args[i].as_ref().unwrap()
-            {
-              name
-            } else {
-              ""
-            },
-          );
-          scope.define(name, t);
-        }
+  fn is_call(raw: &CtToken) -> bool {
+    matches!(
+      raw,
+      Some(Token::FunctionCall { .. }) | Some(Token::MemberAccess { .. }) | Some(Token::Invoke { .. })
+    )
+  }
 
-        environments.push(scope);
-        for raw in body {
-          let t = self.evaluate(raw, environments);
-          if t.is_none() {
-            continue;
-          }
-          let maybe_return = self.unwrap_return(t, environments);
-          if maybe_return.is_some() {
-            return maybe_return;
-          }
+  // A call statement whose error result is discarded counts as uncaught and stops the script,
+  // wherever the statement is
+  fn discard(&self, is_call: bool, result: CtToken) -> CtToken {
+    match result {
+      Some(Token::Error(e)) if is_call => self.stop(format!("Uncaught error: {}", e)),
+      result => result,
+    }
+  }
+
+  fn is_native(&self, name: &String) -> bool {
+    self.predefs.contains(name) || INTRINSICS.contains(&&name[..])
+  }
+
+  // Hands the arguments back when the name is not an intrinsic, so the caller can try the predefs
+  fn intrinsic(
+    &self,
+    name: &str,
+    args: Vec<CtToken>,
+    environments: &mut Vec<Environment>,
+  ) -> Result<CtToken, Vec<CtToken>> {
+    match name {
+      "freeze" | "is_frozen" => {
+        if args.len() != 1 {
+          return Ok(Some(Token::Error(format!(
+            "Expected a single argument for {}",
+            name
+          ))));
         }
 
-        None
+        let value = args.into_iter().next().unwrap();
+        Ok(if name == "freeze" {
+          Interpreter::freeze(value)
+        } else {
+          Some(Token::Boolean(matches!(value, Some(Token::Frozen(_)))))
+        })
       }
-      _ => {
-        return self
-          .parser
-          .error(&format!("Unable to execute function: {}", name));
+      "map" | "filter" | "reduce" | "any" | "all" | "find" | "sort_by" => {
+        Ok(self.array_intrinsic(name, args, environments))
+      }
+      "range" | "iter" | "next" | "collect" | "enumerate" | "take" | "skip" | "zip" | "chain" => {
+        Ok(self.iterator_intrinsic(name, args, environments))
       }
+      _ => Err(args),
     }
   }
 
-  fn apply_binary(&self, operator: String, left: CtToken, right: CtToken) -> CtToken {
-    if left.is_none() || right.is_none() {
-      return self.parser.error(&format!(
-        "Unable to parse binary expression: {} {} {}",
-        left.unwrap(),
-        operator,
-        right.unwrap()
-      ));
+  // map(xs, f), filter(xs, f), reduce(xs, f, initial), any(xs, f), all(xs, f), find(xs, f) and
+  // sort_by(xs, compare), where compare returns a negative, zero or positive number like a comparator.
+  // Any iterable is accepted; map and filter stay eager on arrays and are lazy for everything else
+  fn array_intrinsic(
+    &self,
+    name: &str,
+    args: Vec<CtToken>,
+    environments: &mut Vec<Environment>,
+  ) -> CtToken {
+    let expected = if name == "reduce" { 3 } else { 2 };
+    if args.len() != expected {
+      return Some(Token::Error(format!(
+        "Expected {} arguments for {}, but received {}",
+        expected,
+        name,
+        args.len()
+      )));
     }
-    let left = left.as_ref().unwrap();
-    let right = right.as_ref().unwrap();
 
-    if let (Token::Integer(num1), Token::Integer(num2)) = (left, right) {
-      match operator.as_ref() {
-        "<" => return Some(Token::Boolean(num1 < num2)),
-        ">" => return Some(Token::Boolean(num1 > num2)),
-        "<=" => return Some(Token::Boolean(num1 <= num2)),
+    let mut args = args.into_iter();
+    let iterable = thaw(args.next().unwrap());
+    let eager = matches!(iterable, Some(Token::Array(_)));
+    let items = match self.to_iterator(iterable, environments) {
+      Ok(items) => items,
+      Err(e) => return e,
+    };
+
+    let callback = args.next().unwrap();
+    if get_type(&callback) != types::FUNCTION {
+      return Some(Token::Error(format!(
+        "Expected a function for {}, but received: {}",
+        name,
+        get_type(&callback)
+      )));
+    }
+
+    if !eager && (name == "map" || name == "filter") {
+      let adapter = if name == "map" {
+        Iter::Map(items, callback)
+      } else {
+        Iter::Filter(items, callback)
+      };
+      return Some(Token::Iterator(adapter.shared()));
+    }
+
+    match name {
+      "map" => {
+        let mut mapped = Vec::new();
+        loop {
+          let item = match self.advance(&items, environments) {
+            Ok(Some(item)) => item,
+            Ok(None) => break,
+            Err(e) => return e,
+          };
+          let value = self.call(name, callback.clone(), vec![item], Vec::new(), environments);
+          if let Some(Token::Error(_)) = value {
+            return value;
+          }
+          mapped.push(value);
+        }
+
+        Some(Token::Array(mapped))
+      }
+      "reduce" => {
+        let mut accumulator = args.next().unwrap();
+        loop {
+          let item = match self.advance(&items, environments) {
+            Ok(Some(item)) => item,
+            Ok(None) => break,
+            Err(e) => return e,
+          };
+          accumulator = self.call(
+            name,
+            callback.clone(),
+            vec![accumulator, item],
+            Vec::new(),
+            environments,
+          );
+          if let Some(Token::Error(_)) = accumulator {
+            return accumulator;
+          }
+        }
+
+        accumulator
+      }
+      "sort_by" => {
+        let items = match self.collect(&items, environments) {
+          Ok(items) => items,
+          Err(e) => return e,
+        };
+        let mut compare = |a: &CtToken, b: &CtToken| match self.call(
+          name,
+          callback.clone(),
+          vec![a.clone(), b.clone()],
+          Vec::new(),
+          environments,
+        ) {
+          Some(Token::Integer(n)) => Ok(n.cmp(&0)),
+          Some(Token::Float(n)) => Ok(n.partial_cmp(&0.0).unwrap_or(Ordering::Equal)),
+          Some(Token::Error(e)) => Err(Some(Token::Error(e))),
+          value => Err(Some(Token::Error(format!(
+            "Expected the sort_by comparator to return a number, but received: {}",
+            get_type(&value)
+          )))),
+        };
+
+        match Interpreter::merge_sort(items, &mut compare) {
+          Ok(sorted) => Some(Token::Array(sorted)),
+          Err(e) => e,
+        }
+      }
+      _ => {
+        let mut kept = Vec::new();
+        loop {
+          let item = match self.advance(&items, environments) {
+            Ok(Some(item)) => item,
+            Ok(None) => break,
+            Err(e) => return e,
+          };
+          let value = self.call(name, callback.clone(), vec![item.clone()], Vec::new(), environments);
+          let keep = match value {
+            Some(Token::Boolean(keep)) => keep,
+            Some(Token::Error(e)) => return Some(Token::Error(e)),
+            value => {
+              return Some(Token::Error(format!(
+                "Expected the {} callback to return a bool, but received: {}",
+                name,
+                get_type(&value)
+              )))
+            }
+          };
+
+          match (name, keep) {
+            ("any", true) => return Some(Token::Boolean(true)),
+            ("all", false) => return Some(Token::Boolean(false)),
+            ("find", true) => return item,
+            ("filter", true) => kept.push(item),
+            _ => (),
+          }
+        }
+
+        match name {
+          "any" => Some(Token::Boolean(false)),
+          "all" => Some(Token::Boolean(true)),
+          "find" => Some(Token::Null),
+          _ => Some(Token::Array(kept)),
+        }
+      }
+    }
+  }
+
+  // range(end), range(start, end) or range(start, end, step), iter(xs), next(it), collect(xs),
+  // enumerate(xs), take(xs, n), skip(xs, n), zip(xs, ys) and chain(xs, ys). Adapters are lazy,
+  // nothing is materialised until the result is iterated or collected
+  fn iterator_intrinsic(
+    &self,
+    name: &str,
+    args: Vec<CtToken>,
+    environments: &mut Vec<Environment>,
+  ) -> CtToken {
+    let expected = match name {
+      "range" => 1..=3,
+      "take" | "skip" | "zip" | "chain" => 2..=2,
+      _ => 1..=1,
+    };
+    if !expected.contains(&args.len()) {
+      return Some(Token::Error(format!(
+        "Expected {} arguments for {}, but received {}",
+        expected.end(),
+        name,
+        args.len()
+      )));
+    }
+
+    if name == "range" {
+      let mut bounds = Vec::new();
+      for arg in args.iter() {
+        match arg {
+          Some(Token::Integer(n)) => bounds.push(*n),
+          value => {
+            return Some(Token::Error(format!(
+              "Expected range bounds to be of type int, but received: {}",
+              get_type(value)
+            )))
+          }
+        }
+      }
+
+      let (start, end) = if bounds.len() == 1 { (0, bounds[0]) } else { (bounds[0], bounds[1]) };
+      let step = bounds.get(2).cloned().unwrap_or(1);
+      return match Iter::range(start, end, step, false) {
+        Ok(range) => Some(Token::Iterator(range.shared())),
+        Err(e) => Some(Token::Error(e)),
+      };
+    }
+
+    let mut args = args.into_iter();
+    let first = args.next().unwrap();
+    if name == "next" {
+      let iter = match first {
+        Some(Token::Iterator(iter)) => iter,
+        value => {
+          return Some(Token::Error(format!(
+            "Expected an iterator for next, but received: {}",
+            get_type(&value)
+          )))
+        }
+      };
+
+      let mut result = BTreeMap::new();
+      match self.advance(&iter, environments) {
+        Ok(Some(value)) => {
+          result.insert(String::from("done"), Some(Token::Boolean(false)));
+          result.insert(String::from("value"), value);
+        }
+        Ok(None) => {
+          result.insert(String::from("done"), Some(Token::Boolean(true)));
+          result.insert(String::from("value"), Some(Token::Null));
+        }
+        Err(e) => return e,
+      }
+      return Some(Token::Map(result));
+    }
+
+    let items = match self.to_iterator(first, environments) {
+      Ok(items) => items,
+      Err(e) => return e,
+    };
+
+    let adapter = match name {
+      "iter" => return Some(Token::Iterator(items)),
+      "collect" => {
+        return match self.collect(&items, environments) {
+          Ok(items) => Some(Token::Array(items)),
+          Err(e) => e,
+        }
+      }
+      "enumerate" => Iter::Enumerate(items, 0),
+      "take" | "skip" => {
+        let count = match args.next().unwrap() {
+          Some(Token::Integer(n)) if n >= 0 => n,
+          value => {
+            return Some(Token::Error(format!(
+              "Expected a non-negative int for {}, but received: {}",
+              name,
+              value.map_or(String::from(types::NULL), |t| t.to_string())
+            )))
+          }
+        };
+
+        if name == "take" {
+          Iter::Take(items, count)
+        } else {
+          Iter::Skip(items, count)
+        }
+      }
+      _ => {
+        let other = match self.to_iterator(args.next().unwrap(), environments) {
+          Ok(other) => other,
+          Err(e) => return e,
+        };
+
+        if name == "zip" {
+          Iter::Zip(items, other)
+        } else {
+          Iter::Chain(items, other)
+        }
+      }
+    };
+
+    Some(Token::Iterator(adapter.shared()))
+  }
+
+  // Runs a generator until its next `yield`, with its scopes on top of the caller's while it runs
+  fn resume(
+    &self,
+    generator: &mut Generator,
+    environments: &mut Vec<Environment>,
+  ) -> Result<Option<CtToken>, CtToken> {
+    let depth = environments.len();
+    environments.append(&mut generator.scopes);
+
+    let item = self.run_frames(&mut generator.frames, environments);
+    if let Ok(Some(_)) = item {
+      generator.scopes = environments.split_off(depth);
+    } else {
+      generator.frames.clear();
+    }
+
+    environments.truncate(depth);
+    item
+  }
+
+  fn run_frames(
+    &self,
+    frames: &mut Vec<Frame>,
+    environments: &mut Vec<Environment>,
+  ) -> Result<Option<CtToken>, CtToken> {
+    loop {
+      let statement = match frames.last_mut() {
+        None => return Ok(None),
+        Some(Frame::Block { body, next, scoped }) => {
+          if *next < body.len() {
+            *next += 1;
+            body[*next - 1].clone()
+          } else {
+            if *scoped {
+              environments.pop();
+            }
+            frames.pop();
+            continue;
+          }
+        }
+        Some(Frame::While { condition, body }) => {
+          match self.evaluate(condition.clone(), environments) {
+            Some(Token::Boolean(true)) => {
+              let body = body.clone();
+              Interpreter::enter_block(frames, body, Environment::new(), environments);
+            }
+            Some(Token::Boolean(false)) => {
+              frames.pop();
+            }
+            value => {
+              return Err(Some(Token::Error(format!(
+                "Expected boolean expression inside while loop, but received: {}",
+                get_type(&value)
+              ))))
+            }
+          }
+          continue;
+        }
+        Some(Frame::For {
+          pattern,
+          items,
+          body,
+        }) => {
+          let (pattern, items, body) = (pattern.clone(), items.clone(), body.clone());
+          match self.advance(&items, environments)? {
+            Some(item) => {
+              let mut bindings = Vec::new();
+              if !Interpreter::match_pattern(&pattern, &item, &mut bindings) {
+                return Err(Some(Token::Error(format!(
+                  "For loop pattern does not match: {}",
+                  item.map_or(String::from(types::NULL), |t| t.to_string())
+                ))));
+              }
+
+              let mut scope = Environment::new();
+              for (name, value) in bindings {
+                scope.define(name, value);
+              }
+              Interpreter::enter_block(frames, body, scope, environments);
+            }
+            None => {
+              frames.pop();
+            }
+          }
+          continue;
+        }
+      };
+
+      match statement {
+        Some(Token::Yield(value)) => {
+          return Ok(Some(match self.evaluate(*value, environments) {
+            None => Some(Token::Null),
+            value => value,
+          }))
+        }
+        // Blocks that yield are stepped through frame by frame, everything else runs as usual
+        Some(Token::If(ifs)) if ifs.iter().any(|i| Interpreter::yields(&i.body)) => {
+          for i in ifs {
+            match self.evaluate(*i.condition, environments) {
+              Some(Token::Boolean(true)) => {
+                Interpreter::enter_block(frames, Rc::new(i.body), Environment::new(), environments);
+                break;
+              }
+              Some(Token::Boolean(false)) => (),
+              value => {
+                return Err(Some(Token::Error(format!(
+                  "Expected boolean expression inside if statement, but received: {}",
+                  get_type(&value)
+                ))))
+              }
+            }
+          }
+        }
+        Some(Token::While { condition, body }) if Interpreter::yields(&body) => {
+          frames.push(Frame::While {
+            condition: *condition,
+            body: Rc::new(body),
+          });
+        }
+        Some(Token::For {
+          pattern,
+          iterable,
+          body,
+        }) if Interpreter::yields(&body) => {
+          let iterable = self.evaluate(*iterable, environments);
+          frames.push(Frame::For {
+            pattern: *pattern,
+            items: self.to_iterator(iterable, environments)?,
+            body: Rc::new(body),
+          });
+        }
+        statement => {
+          let is_call = Interpreter::is_call(&statement);
+          let result = self.statement(statement, environments);
+          match self.discard(is_call, result) {
+            // What a generator returns ends it, it is not yielded
+            Some(Token::Return(value)) => {
+              if let Some(Token::TailCall(call)) = self.evaluate(*value, environments) {
+                let Call {
+                  function,
+                  captured,
+                  positional,
+                  named,
+                } = *call;
+                self.call_function(&function, captured, positional, named, None, environments);
+              }
+              return Ok(None);
+            }
+            Some(Token::Break) => {
+              while let Some(frame) = frames.pop() {
+                match frame {
+                  Frame::Block { scoped: true, .. } => {
+                    environments.pop();
+                  }
+                  Frame::Block { .. } => (),
+                  _ => break,
+                }
+              }
+            }
+            Some(Token::Continue) => {
+              while let Some(Frame::Block { scoped, .. }) = frames.last() {
+                if *scoped {
+                  environments.pop();
+                }
+                frames.pop();
+              }
+            }
+            _ => (),
+          }
+        }
+      }
+    }
+  }
+
+  fn enter_block(
+    frames: &mut Vec<Frame>,
+    body: Rc<Vec<CtToken>>,
+    scope: Environment,
+    environments: &mut Vec<Environment>,
+  ) {
+    environments.push(scope);
+    frames.push(Frame::Block {
+      body,
+      next: 0,
+      scoped: true,
+    });
+  }
+
+  // Whether a block yields directly or from one of its if, while and for blocks
+  fn yields(body: &[CtToken]) -> bool {
+    body.iter().any(|statement| match statement {
+      Some(Token::Yield(_)) => true,
+      Some(Token::If(ifs)) => ifs.iter().any(|i| Interpreter::yields(&i.body)),
+      Some(Token::While { body, .. }) | Some(Token::For { body, .. }) => Interpreter::yields(body),
+      _ => false,
+    })
+  }
+
+  fn collect(
+    &self,
+    iter: &SharedIter,
+    environments: &mut Vec<Environment>,
+  ) -> Result<Vec<CtToken>, CtToken> {
+    let mut items = Vec::new();
+    while let Some(item) = self.advance(iter, environments)? {
+      items.push(item);
+    }
+
+    Ok(items)
+  }
+
+  // Stable, and unlike `slice::sort_by` it stops at the first comparator error
+  fn merge_sort(
+    items: Vec<CtToken>,
+    compare: &mut dyn FnMut(&CtToken, &CtToken) -> Result<Ordering, CtToken>,
+  ) -> Result<Vec<CtToken>, CtToken> {
+    if items.len() <= 1 {
+      return Ok(items);
+    }
+
+    let mut left = items;
+    let right = left.split_off(left.len() / 2);
+    let left = Interpreter::merge_sort(left, compare)?;
+    let right = Interpreter::merge_sort(right, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+      if compare(a, b)? == Ordering::Greater {
+        merged.push(right.next().unwrap());
+      } else {
+        merged.push(left.next().unwrap());
+      }
+    }
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+  }
+
+  fn evaluate_arguments(
+    &self,
+    args: Vec<CtToken>,
+    environments: &mut Vec<Environment>,
+  ) -> (Vec<CtToken>, Vec<(String, CtToken)>) {
+    let mut positional = Vec::new();
+    let mut named = Vec::new();
+    for arg in args {
+      match arg {
+        Some(Token::NamedArgument { name, value }) => {
+          let value = self.evaluate(*value, environments);
+          named.push((name, value));
+        }
+        None => positional.push(None),
+        arg => {
+          if !named.is_empty() {
+            self
+              .parser
+              .error("Positional arguments must come before named arguments");
+          }
+          positional.push(self.evaluate(arg, environments));
+        }
+      }
+    }
+
+    (positional, named)
+  }
+
+  // Calls any callable value with already evaluated arguments
+  fn call(
+    &self,
+    name: &str,
+    callee: CtToken,
+    positional: Vec<CtToken>,
+    named: Vec<(String, CtToken)>,
+    environments: &mut Vec<Environment>,
+  ) -> CtToken {
+    match callee {
+      Some(Token::Function(function)) => {
+        self.call_function(&function, None, positional, named, None, environments)
+      }
+      Some(Token::Closure { function, captured }) => {
+        self.call_function(&function, Some(captured), positional, named, None, environments)
+      }
+      Some(Token::Native(native)) => {
+        if let Some((arg, _)) = named.first() {
+          return self.error(&format!(
+            "Native function {} does not accept named arguments, received: {}",
+            native, arg
+          ));
+        }
+
+        match self.intrinsic(&native, positional, environments) {
+          Ok(result) => result,
+          Err(args) => {
+            let args: Vec<CtToken> = args
+              .into_iter()
+              .map(|arg| thaw_all(self.display(arg, environments)))
+              .collect();
+            if OUTPUT_NATIVES.contains(&&native[..]) {
+              if let Err(stopped) = self.write(&args) {
+                return stopped;
+              }
+            }
+            let result = self.predefs.execute(&native, args);
+            // A native that gave up because the script was interrupted stops it with the reason
+            match self.interrupted() {
+              Ok(()) => result,
+              Err(stopped) => stopped,
+            }
+          }
+        }
+      }
+      value => self.error(&format!(
+        "Unable to call {}, it is not a function: {}",
+        name,
+        get_type(&value)
+      )),
+    }
+  }
+
+  fn call_function(
+    &self,
+    function: &Function,
+    captured: Option<Rc<HashMap<String, CtToken>>>,
+    positional: Vec<CtToken>,
+    named: Vec<(String, CtToken)>,
+    receiver: Option<&mut CtToken>,
+    environments: &mut Vec<Environment>,
+  ) -> CtToken {
+    let calls = self.calls.get();
+    if calls >= self.max_calls {
+      return Some(Token::Error(format!(
+        "Maximum recursion depth of {} exceeded in {}",
+        self.max_calls, function.header.name
+      )));
+    }
+
+    self.calls.set(calls + 1);
+    let result = self.run_function(function, captured, positional, named, receiver, environments);
+    self.calls.set(calls);
+    result
+  }
+
+  // Tail calls reuse the current call instead of nesting, `return f(n - 1)` runs in constant stack
+  fn run_function(
+    &self,
+    function: &Function,
+    captured: Option<Rc<HashMap<String, CtToken>>>,
+    positional: Vec<CtToken>,
+    named: Vec<(String, CtToken)>,
+    mut receiver: Option<&mut CtToken>,
+    environments: &mut Vec<Environment>,
+  ) -> CtToken {
+    let depth = environments.len();
+    let (mut function, mut captured) = (function.clone(), captured);
+    let (mut positional, mut named) = (positional, named);
+
+    loop {
+      // Captured variables sit between the caller and the parameters, a named closure sees itself
+      if let Some(captured) = captured {
+        let mut scope = Environment::new();
+        for (name, value) in captured.iter() {
+          scope.define(name.clone(), value.clone());
+        }
+        if function.header.name != types::ANONYMOUS {
+          scope.define(
+            function.header.name.clone(),
+            Some(Token::Closure {
+              function: function.clone(),
+              captured,
+            }),
+          );
+        }
+        environments.push(scope);
+      }
+
+      self.bind_arguments(&function.header, positional, named, environments);
+      if function.header.return_type == types::GENERATOR {
+        let generator = Generator {
+          scopes: environments.split_off(depth),
+          frames: vec![Frame::Block {
+            body: Rc::new(function.body.clone()),
+            next: 0,
+            scoped: false,
+          }],
+        };
+        return Some(Token::Iterator(Iter::Generator(Box::new(generator)).shared()));
+      }
+
+      let mut result = None;
+      for raw in function.body.iter() {
+        let value = self.statement(raw.clone(), environments);
+        if let Some(Token::Return(value)) = self.discard(Interpreter::is_call(raw), value) {
+          // TODO: Handle possible type differences
+          result = match *value {
+            Some(Token::TailCall(_)) => *value,
+            value => self.returned(value, environments),
+          };
+          break;
+        }
+      }
+
+      // Methods see their receiver as the first parameter, what it holds at the end is the receiver
+      if let (Some(receiver), Some(Some(Token::Variable { name, .. }))) =
+        (receiver.take(), function.header.args.first())
+      {
+        if let Some(environment) = Environment::lookup(environments, name) {
+          *receiver = environment.get(name);
+        }
+      }
+
+      environments.truncate(depth);
+      match result {
+        Some(Token::TailCall(call)) => {
+          function = call.function;
+          captured = call.captured;
+          positional = call.positional;
+          named = call.named;
+        }
+        result => return result,
+      }
+    }
+  }
+
+  // What `return value` gives back, a call to a script function is left for the caller to run.
+  // The call is in tail position, as the `return` only travels up through statements to
+  // `run_function` or the generator running it, expressions reject it
+  fn returned(&self, value: CtToken, environments: &mut Vec<Environment>) -> CtToken {
+    // Outside of a function nothing would run the tail call, so the call is made right away
+    let (name, args) = match value {
+      Some(Token::FunctionCall { name, args }) if self.calls.get() > 0 => (name, args),
+      value => return self.evaluate(value, environments),
+    };
+
+    let callee = Environment::lookup(environments, &name).map(|e| e.get(&name));
+    let (function, captured) = match callee {
+      Some(Some(Token::Function(function))) => (function, None),
+      Some(Some(Token::Closure { function, captured })) => (function, Some(captured)),
+      _ => return self.evaluate(Some(Token::FunctionCall { name, args }), environments),
+    };
+
+    let (positional, named) = self.evaluate_arguments(args, environments);
+    Some(Token::TailCall(Box::new(Call {
+      function,
+      captured,
+      positional,
+      named,
+    })))
+  }
+
+  // Calls a method of the receiver's struct, writing back any changes the method made to it
+  fn call_method(
+    &self,
+    receiver: &mut CtToken,
+    name: &str,
+    args: Vec<CtToken>,
+    environments: &mut Vec<Environment>,
+  ) -> CtToken {
+    if let Some(Token::Error(_)) = receiver {
+      return receiver.clone();
+    }
+
+    let method = self.method(receiver, name);
+    if method.is_none() {
+      return Some(Token::Error(format!(
+        "{} has no method {}",
+        get_type(receiver),
+        name
+      )));
+    }
+
+    let mut positional = vec![receiver.clone()];
+    positional.extend(args);
+    let owner = get_type(receiver);
+    let arguments = (positional, Vec::new());
+    self.invoke_method(&owner, name, method, arguments, receiver, environments)
+  }
+
+  // Methods of a struct that extends another see `super`, bound to the parent of the struct that
+  // defines them and to the name of their receiver parameter
+  fn invoke_method(
+    &self,
+    owner: &str,
+    name: &str,
+    method: CtToken,
+    arguments: (Vec<CtToken>, Vec<(String, CtToken)>),
+    receiver: &mut CtToken,
+    environments: &mut Vec<Environment>,
+  ) -> CtToken {
+    let (positional, named) = arguments;
+    let parent = match self.structs.borrow().get(owner) {
+      Some(structure) => structure
+        .owner(&String::from(name))
+        .and_then(|owner| owner.parent.as_ref())
+        .map(|parent| parent.name.clone()),
+      None => None,
+    };
+
+    let (function, captured) = match method {
+      Some(Token::Function(function)) => (function, None),
+      Some(Token::Closure { function, captured }) => (function, Some(captured)),
+      callee => return self.call(name, callee, positional, named, environments),
+    };
+
+    let depth = environments.len();
+    if let (Some(parent), Some(Some(Token::Variable { name, .. }))) =
+      (parent, function.header.args.first())
+    {
+      let mut scope = Environment::new();
+      scope.define(
+        String::from(types::SUPER),
+        Some(Token::Super {
+          parent,
+          receiver: name.clone(),
+        }),
+      );
+      environments.push(scope);
+    }
+
+    let receiver = Some(receiver);
+    let result = self.call_function(&function, captured, positional, named, receiver, environments);
+    environments.truncate(depth);
+    result
+  }
+
+  // `super.method(...)` runs the parent's method on the current receiver
+  fn call_super(
+    &self,
+    parent: &str,
+    receiver: &str,
+    attribute: String,
+    args: Option<Vec<CtToken>>,
+    environments: &mut Vec<Environment>,
+  ) -> CtToken {
+    let method = match self.structs.borrow().get(parent) {
+      Some(structure) => structure.get(&attribute),
+      None => None,
+    };
+    let args = match (method.is_some(), args) {
+      (true, Some(args)) => args,
+      (true, None) => {
+        return self
+          .parser
+          .error(&format!("super.{} can only be called", attribute))
+      }
+      (false, _) => {
+        return self
+          .parser
+          .error(&format!("{} has no method {}", parent, attribute))
+      }
+    };
+
+    let value = match Environment::lookup(environments, &String::from(receiver)) {
+      Some(environment) => environment.get(&String::from(receiver)),
+      None => None,
+    };
+    let (mut positional, named) = self.evaluate_arguments(args, environments);
+    positional.insert(0, value.clone());
+
+    let mut updated = value.clone();
+    let arguments = (positional, named);
+    let result =
+      self.invoke_method(parent, &attribute, method, arguments, &mut updated, environments);
+    if !values_equal(&value, &updated) {
+      self.assign(Token::VariableCall(String::from(receiver)), updated, environments);
+    }
+    result
+  }
+
+  // Struct methods come from the prototype, maps may carry their own methods as entries
+  fn method(&self, value: &CtToken, name: &str) -> CtToken {
+    match (self.structs.borrow().get(&get_type(value)), value) {
+      (Some(structure), _) => structure.get(&String::from(name)),
+      (None, Some(Token::Map(entries))) => match entries.get(name) {
+        Some(Some(Token::Function(function))) => Some(Token::Function(function.clone())),
+        Some(Some(Token::Closure { function, captured })) => Some(Token::Closure {
+          function: function.clone(),
+          captured: captured.clone(),
+        }),
+        _ => None,
+      },
+      _ => None,
+    }
+  }
+
+  fn has_method(&self, value: &CtToken, name: &str) -> bool {
+    self.method(value, name).is_some()
+  }
+
+  // Arrays, strings, maps (as `[key, value]` pairs), files (by line), iterators and structs
+  // implementing `next()` or `iter()` can all be iterated
+  fn to_iterator(
+    &self,
+    value: CtToken,
+    environments: &mut Vec<Environment>,
+  ) -> Result<SharedIter, CtToken> {
+    let custom = self.has_method(&value, "next") || self.has_method(&value, "iter");
+    match value {
+      Some(Token::Iterator(iter)) => Ok(iter),
+      Some(Token::Error(e)) => Err(Some(Token::Error(e))),
+      Some(Token::Frozen(value)) => self.to_iterator(Some(*value), environments),
+      Some(Token::Array(items)) => Ok(Iter::Items(items.into_iter()).shared()),
+      Some(Token::String(text)) => Ok(
+        Iter::Items(
+          text
+            .chars()
+            .map(|c| Some(Token::String(c.to_string())))
+            .collect::<Vec<CtToken>>()
+            .into_iter(),
+        )
+        .shared(),
+      ),
+      Some(Token::Map(entries)) if !custom => Ok(
+        Iter::Items(
+          entries
+            .into_iter()
+            .map(|(key, value)| Some(Token::Array(vec![Some(Token::String(key)), value])))
+            .collect::<Vec<CtToken>>()
+            .into_iter(),
+        )
+        .shared(),
+      ),
+      Some(Token::Structure { ref name, .. }) if name == "File" => Ok(Iter::Lines(value).shared()),
+      value => {
+        if !custom {
+          Err(Some(Token::Error(format!(
+            "Value of type {} is not iterable",
+            get_type(&value)
+          ))))
+        } else if self.has_method(&value, "next") {
+          Ok(Iter::Custom(value).shared())
+        } else {
+          let mut receiver = value;
+          match self.call_method(&mut receiver, "iter", Vec::new(), environments) {
+            Some(Token::Error(e)) => Err(Some(Token::Error(e))),
+            iterable => self.to_iterator(iterable, environments),
+          }
+        }
+      }
+    }
+  }
+
+  // Pulls the next item out of any iterator, running a custom `next()` or resuming a generator as
+  // needed. `Ok(None)` once it is exhausted, `Err` with the error the source gave otherwise
+  fn advance(
+    &self,
+    iter: &SharedIter,
+    environments: &mut Vec<Environment>,
+  ) -> Result<Option<CtToken>, CtToken> {
+    // Each item is a step, so collecting an endless iterator runs out of fuel or time
+    self.step()?;
+
+    // Resuming a generator that loops over another one nests on the stack like a call does
+    let calls = self.calls.get();
+    if calls >= self.max_calls {
+      return Err(Some(Token::Error(format!(
+        "Maximum recursion depth of {} exceeded in an iterator",
+        self.max_calls
+      ))));
+    }
+    self.calls.set(calls + 1);
+    let item = self.next_item(iter, environments);
+    self.calls.set(calls);
+    item
+  }
+
+  fn next_item(
+    &self,
+    iter: &SharedIter,
+    environments: &mut Vec<Environment>,
+  ) -> Result<Option<CtToken>, CtToken> {
+    let mut state = match iter.try_borrow_mut() {
+      Ok(state) => state,
+      Err(_) => {
+        return Err(Some(Token::Error(String::from(
+          "Iterator is already being advanced",
+        ))))
+      }
+    };
+
+    if let Some(item) = state.advance_plain() {
+      return Ok(item);
+    }
+
+    let item = match &mut *state {
+      Iter::Lines(file) => match self
+        .predefs
+        .execute(&String::from("file_read_line"), vec![file.clone()])
+      {
+        None | Some(Token::Null) => None,
+        Some(Token::Error(e)) => return Err(Some(Token::Error(e))),
+        line => Some(line),
+      },
+      Iter::Custom(value) => {
+        let (done, item) = match thaw(self.call_method(value, "next", Vec::new(), environments)) {
+          Some(Token::Map(fields)) => (fields.get("done").cloned(), fields.get("value").cloned()),
+          Some(Token::Structure { fields, .. }) => {
+            (fields.get("done").cloned(), fields.get("value").cloned())
+          }
+          Some(Token::Error(e)) => return Err(Some(Token::Error(e))),
+          result => {
+            return Err(Some(Token::Error(format!(
+              "Expected next() to return {{ done, value }}, but received: {}",
+              get_type(&result)
+            ))))
+          }
+        };
+
+        match done {
+          Some(Some(Token::Boolean(true))) => None,
+          Some(Some(Token::Boolean(false))) | None => Some(item.unwrap_or(None)),
+          done => {
+            return Err(Some(Token::Error(format!(
+              "Expected the done flag of next() to be a bool, but received: {}",
+              get_type(&done.unwrap_or(None))
+            ))))
+          }
+        }
+      }
+      Iter::Generator(generator) => self.resume(generator, environments)?,
+      Iter::Enumerate(inner, index) => match self.advance(inner, environments)? {
+        Some(item) => {
+          *index += 1;
+          Some(Some(Token::Array(vec![Some(Token::Integer(*index - 1)), item])))
+        }
+        None => None,
+      },
+      Iter::Zip(a, b) => match self.advance(a, environments)? {
+        Some(first) => self
+          .advance(b, environments)?
+          .map(|second| Some(Token::Array(vec![first, second]))),
+        None => None,
+      },
+      Iter::Take(inner, remaining) => {
+        if *remaining > 0 {
+          *remaining -= 1;
+          self.advance(inner, environments)?
+        } else {
+          None
+        }
+      }
+      Iter::Skip(inner, count) => {
+        while *count > 0 {
+          *count -= 1;
+          if self.advance(inner, environments)?.is_none() {
+            break;
+          }
+        }
+        self.advance(inner, environments)?
+      }
+      Iter::Chain(a, b) => match self.advance(a, environments)? {
+        Some(item) => Some(item),
+        None => self.advance(b, environments)?,
+      },
+      Iter::Map(inner, f) => match self.advance(inner, environments)? {
+        Some(item) => match self.call("map", f.clone(), vec![item], Vec::new(), environments) {
+          Some(Token::Error(e)) => return Err(Some(Token::Error(e))),
+          value => Some(value),
+        },
+        None => None,
+      },
+      Iter::Filter(inner, f) => loop {
+        match self.advance(inner, environments)? {
+          Some(item) => match self.call(
+            "filter",
+            f.clone(),
+            vec![item.clone()],
+            Vec::new(),
+            environments,
+          ) {
+            Some(Token::Boolean(true)) => break Some(item),
+            Some(Token::Boolean(false)) => (),
+            Some(Token::Error(e)) => return Err(Some(Token::Error(e))),
+            value => {
+              return Err(Some(Token::Error(format!(
+                "Expected the filter callback to return a bool, but received: {}",
+                get_type(&value)
+              ))))
+            }
+          },
+          None => break None,
+        }
+      },
+      _ => None,
+    };
+
+    if item.is_none() {
+      *state = Iter::Done;
+    }
+    Ok(item)
+  }
+
+  // Local variables are copied into closures, the global scope is always reachable anyway
+  fn capture(environments: &[Environment]) -> Rc<HashMap<String, CtToken>> {
+    let mut captured = HashMap::new();
+    for environment in environments.iter().skip(1) {
+      for (name, value) in environment.vars.iter() {
+        captured.insert(name.clone(), value.clone());
+      }
+    }
+
+    Rc::new(captured)
+  }
+
+  fn is_declared(&self, name: &str) -> bool {
+    self
+      .structs
+      .borrow()
+      .get(name)
+      .is_some_and(|structure| structure.declared)
+  }
+
+  // `Point(1, y: 2)`, fields without a value fall back to their defaults like parameters do
+  fn construct(
+    &self,
+    name: &str,
+    args: Vec<CtToken>,
+    environments: &mut Vec<Environment>,
+  ) -> CtToken {
+    let header = match self.structs.borrow().get(name) {
+      Some(structure) => structure.constructor.header.clone(),
+      None => return self.error(&format!("Unknown struct: {}", name)),
+    };
+
+    let (positional, named) = self.evaluate_arguments(args, environments);
+    let depth = environments.len();
+    self.bind_arguments(&header, positional, named, environments);
+    let fields = environments.pop().map(|scope| scope.vars).unwrap_or_default();
+    environments.truncate(depth);
+
+    Some(Token::Structure {
+      name: String::from(name),
+      fields,
+    })
+  }
+
+  // Negative indexes count from the end
+  fn position(index: i64, len: usize) -> Option<usize> {
+    let index = if index < 0 { index + len as i64 } else { index };
+    if index >= 0 && (index as usize) < len {
+      Some(index as usize)
+    } else {
+      None
+    }
+  }
+
+  // An item of an array or map held by a variable, None when anything else is indexed or the index
+  // is out of bounds
+  fn index_in_place(
+    name: &String,
+    index: &CtToken,
+    environments: &mut Vec<Environment>,
+  ) -> Option<CtToken> {
+    let (value, frozen) = match Environment::lookup(environments, name)?.vars.get(name)? {
+      Some(Token::Frozen(value)) => (&**value, true),
+      Some(value) => (value, false),
+      None => return None,
+    };
+
+    let item = match (value, index) {
+      (Token::Array(items), Some(Token::Integer(i))) => {
+        items[Interpreter::position(*i, items.len())?].clone()
+      }
+      (Token::Map(entries), Some(Token::String(key))) => {
+        entries.get(key).cloned().unwrap_or(Some(Token::Null))
+      }
+      _ => return None,
+    };
+    Some(if frozen { Interpreter::freeze(item) } else { item })
+  }
+
+  fn index(&self, target: CtToken, index: CtToken, environments: &mut Vec<Environment>) -> CtToken {
+    let frozen = matches!(target, Some(Token::Frozen(_)));
+    let value = match (thaw(target), index) {
+      (Some(Token::Array(items)), Some(Token::Integer(i))) => {
+        match Interpreter::position(i, items.len()) {
+          Some(i) => items[i].clone(),
+          None => {
+            return self.error(&format!(
+              "Index {} is out of bounds for an array of length {}",
+              i,
+              items.len()
+            ))
+          }
+        }
+      }
+      (Some(Token::String(text)), Some(Token::Integer(i))) => {
+        let chars: Vec<char> = text.chars().collect();
+        match Interpreter::position(i, chars.len()) {
+          Some(i) => Some(Token::String(chars[i].to_string())),
+          None => {
+            return self.error(&format!(
+              "Index {} is out of bounds for a string of length {}",
+              i,
+              chars.len()
+            ))
+          }
+        }
+      }
+      (Some(Token::Map(entries)), Some(Token::String(key))) => {
+        entries.get(&key).cloned().unwrap_or(Some(Token::Null))
+      }
+      (target, index) if self.has_method(&target, "index") => {
+        let mut receiver = if frozen { Interpreter::freeze(target) } else { target };
+        return self.call_method(&mut receiver, "index", vec![index], environments);
+      }
+      (target, index) => {
+        return self.error(&format!(
+          "Unable to index {} with {}",
+          get_type(&target),
+          get_type(&index)
+        ))
+      }
+    };
+
+    if frozen {
+      Interpreter::freeze(value)
+    } else {
+      value
+    }
+  }
+
+  // Structs take part in operators through their methods: `add`, `sub`, `mul`, `div` and `rem` for
+  // arithmetic, `eq` for equality, `lt` for every comparison and `to_string` when joined to a string
+  fn binary(
+    &self,
+    operator: String,
+    left: CtToken,
+    right: CtToken,
+    environments: &mut Vec<Environment>,
+  ) -> CtToken {
+    // Errors pass through arithmetic, so one from deep in a recursion reaches the outermost caller
+    if operator != "==" && operator != "!=" {
+      for value in [&left, &right].iter() {
+        if let Some(Token::Error(_)) = value {
+          return (*value).clone();
+        }
+      }
+    }
+
+    let is_struct = |value: &CtToken| matches!(thaw(value.clone()), Some(Token::Structure { .. }));
+    if !is_struct(&left) && !is_struct(&right) {
+      return self.apply_binary(operator, left, right);
+    }
+
+    let method = match &operator[..] {
+      "+" => "add",
+      "-" => "sub",
+      "*" => "mul",
+      "/" => "div",
+      "%" => "rem",
+      "==" | "!=" => "eq",
+      "<" | ">" | "<=" | ">=" => "lt",
+      _ => return self.apply_binary(operator, left, right),
+    };
+
+    if operator == "+" {
+      match (&left, &right) {
+        (Some(Token::String(text)), value) if self.has_method(value, "to_string") => {
+          let value = self.display(value.clone(), environments);
+          return self.apply_binary(operator, Some(Token::String(text.clone())), value);
+        }
+        (value, Some(Token::String(text))) if self.has_method(value, "to_string") => {
+          let value = self.display(value.clone(), environments);
+          return self.apply_binary(operator, value, Some(Token::String(text.clone())));
+        }
+        _ => (),
+      }
+    }
+
+    // `a > b` asks `b < a` and `a <= b` is `!(b < a)`, equality may be answered by either side
+    let (receiver, argument) = match &operator[..] {
+      ">" | "<=" => (right, left),
+      "==" | "!=" if !self.has_method(&left, method) => (right, left),
+      _ => (left, right),
+    };
+
+    if !self.has_method(&receiver, method) {
+      if method == "eq" {
+        return self.apply_binary(operator, receiver, argument);
+      }
+      return self.error(&format!(
+        "Unable to apply {}, {} has no {} method",
+        operator,
+        get_type(&receiver),
+        method
+      ));
+    }
+
+    let mut receiver = receiver;
+    let result = self.call_method(&mut receiver, method, vec![argument], environments);
+    if method != "eq" && method != "lt" {
+      return result;
+    }
+
+    match result {
+      Some(Token::Boolean(result)) => Some(Token::Boolean(match &operator[..] {
+        "!=" | "<=" | ">=" => !result,
+        _ => result,
+      })),
+      Some(Token::Error(e)) => Some(Token::Error(e)),
+      value => self.error(&format!(
+        "Expected {} of {} to return a bool, but received: {}",
+        method,
+        get_type(&receiver),
+        get_type(&value)
+      )),
+    }
+  }
+
+  // The string natives see for a value, structs with a `to_string` method are converted through it
+  fn display(&self, value: CtToken, environments: &mut Vec<Environment>) -> CtToken {
+    let is_struct = matches!(thaw(value.clone()), Some(Token::Structure { .. }));
+    if !is_struct || !self.has_method(&value, "to_string") {
+      return value;
+    }
+
+    let mut receiver = value;
+    match self.call_method(&mut receiver, "to_string", Vec::new(), environments) {
+      Some(Token::String(text)) => Some(Token::String(text)),
+      Some(Token::Error(e)) => Some(Token::Error(e)),
+      result => self.error(&format!(
+        "Expected to_string of {} to return a string, but received: {}",
+        get_type(&receiver),
+        get_type(&result)
+      )),
+    }
+  }
+
+  fn apply_binary(&self, operator: String, left: CtToken, right: CtToken) -> CtToken {
+    let left = thaw(left);
+    let right = thaw(right);
+
+    // A missing value and `null` are the same to scripts, and only equal to each other
+    let is_null = |t: &CtToken| matches!(t, None | Some(Token::Null));
+    if (operator == "==" || operator == "!=") && (is_null(&left) || is_null(&right)) {
+      let equal = is_null(&left) && is_null(&right);
+      return Some(Token::Boolean(if operator == "==" { equal } else { !equal }));
+    }
+
+    if left.is_none() || right.is_none() {
+      return self.error(&format!(
+        "Unable to apply binary expression: {} {} {}",
+        get_type(&left),
+        operator,
+        get_type(&right)
+      ));
+    }
+    let left = left.as_ref().unwrap();
+    let right = right.as_ref().unwrap();
+
+    if let (Token::Integer(num1), Token::Integer(num2)) = (left, right) {
+      match operator.as_ref() {
+        "<" => return Some(Token::Boolean(num1 < num2)),
+        ">" => return Some(Token::Boolean(num1 > num2)),
+        "<=" => return Some(Token::Boolean(num1 <= num2)),
         ">=" => return Some(Token::Boolean(num1 >= num2)),
         "==" => return Some(Token::Boolean(num1 == num2)),
         "!=" => return Some(Token::Boolean(num1 != num2)),
-        "+" => return Some(Token::Integer(num1 + num2)),
-        "-" => return Some(Token::Integer(num1 - num2)),
-        "*" => return Some(Token::Integer(num1 * num2)),
-        "/" => return Some(Token::Integer(num1 / num2)),
-        "%" => return Some(Token::Integer(num1 % num2)),
+        "+" | "-" | "*" | "/" | "%" => {
+          let result = match operator.as_ref() {
+            "+" => num1.checked_add(*num2),
+            "-" => num1.checked_sub(*num2),
+            "*" => num1.checked_mul(*num2),
+            _ if *num2 == 0 => return Some(Token::Error(String::from("Division by zero"))),
+            "/" => num1.checked_div(*num2),
+            _ => num1.checked_rem(*num2),
+          };
+
+          // Overflow is an error value rather than a wrapped result
+          return Some(match result {
+            Some(n) => Token::Integer(n),
+            None => Token::Error(format!("Integer overflow in {} {} {}", num1, operator, num2)),
+          });
+        }
+        ".." | "..=" => {
+          return Some(Token::Iterator(
+            Iter::Range {
+              next: *num1,
+              end: *num2,
+              step: 1,
+              inclusive: operator == "..=",
+            }
+            .shared(),
+          ))
+        }
+        _ => (),
+      }
+    }
+
+    let mut maybe_num1 = None;
+    let mut maybe_num2 = None;
+
+    if let (Token::Float(num1), Token::Float(num2)) = (left, right) {
+      maybe_num1 = Some(*num1);
+      maybe_num2 = Some(*num2);
+    }
+
+    if let (Token::Integer(num1), Token::Float(num2)) = (left, right) {
+      maybe_num1 = Some(*num1 as f64);
+      maybe_num2 = Some(*num2);
+    }
+
+    if let (Token::Float(num1), Token::Integer(num2)) = (left, right) {
+      maybe_num1 = Some(*num1);
+      maybe_num2 = Some(*num2 as f64);
+    }
+
+    if maybe_num1.is_some() && maybe_num2.is_some() {
+      match operator.as_ref() {
+        "<" => return Some(Token::Boolean(maybe_num1.unwrap() < maybe_num2.unwrap())),
+        ">" => return Some(Token::Boolean(maybe_num1.unwrap() > maybe_num2.unwrap())),
+        "<=" => return Some(Token::Boolean(maybe_num1.unwrap() <= maybe_num2.unwrap())),
+        ">=" => return Some(Token::Boolean(maybe_num1.unwrap() >= maybe_num2.unwrap())),
+        "==" => return Some(Token::Boolean(maybe_num1.unwrap() == maybe_num2.unwrap())),
+        "!=" => return Some(Token::Boolean(maybe_num1.unwrap() != maybe_num2.unwrap())),
+        "+" => return Some(Token::Float(maybe_num1.unwrap() + maybe_num2.unwrap())),
+        "-" => return Some(Token::Float(maybe_num1.unwrap() - maybe_num2.unwrap())),
+        "*" => return Some(Token::Float(maybe_num1.unwrap() * maybe_num2.unwrap())),
+        "/" => return Some(Token::Float(maybe_num1.unwrap() / maybe_num2.unwrap())),
+        "%" => return Some(Token::Float(maybe_num1.unwrap() % maybe_num2.unwrap())),
         _ => (),
       }
     }
 
-    let mut maybe_num1 = None;
-    let mut maybe_num2 = None;
+    if let (Token::Boolean(bool1), Token::Boolean(bool2)) = (left, right) {
+      match operator.as_ref() {
+        "==" => return Some(Token::Boolean(bool1 == bool2)),
+        "!=" => return Some(Token::Boolean(bool1 != bool2)),
+        "&&" => return Some(Token::Boolean(*bool1 && *bool2)),
+        "||" => return Some(Token::Boolean(*bool1 || *bool2)),
+        _ => (),
+      }
+    }
+
+    if let (Token::String(str1), Token::String(str2)) = (left, right) {
+      match operator.as_ref() {
+        "<" => return Some(Token::Boolean(str1 < str2)),
+        ">" => return Some(Token::Boolean(str1 > str2)),
+        "<=" => return Some(Token::Boolean(str1 <= str2)),
+        ">=" => return Some(Token::Boolean(str1 >= str2)),
+        "==" => return Some(Token::Boolean(str1 == str2)),
+        "!=" => return Some(Token::Boolean(str1 != str2)),
+        "+" => return Some(Token::String((*str1).clone() + str2)),
+        _ => (),
+      }
+    }
+
+    // Collections, structures and enum values compare by value
+    let compound = |t: &Token| {
+      matches!(
+        t,
+        Token::Array(_) | Token::Map(_) | Token::Structure { .. } | Token::EnumValue { .. }
+      )
+    };
+    if (operator == "==" || operator == "!=") && compound(left) && compound(right) {
+      let equal = values_equal(&Some(left.clone()), &Some(right.clone()));
+      return Some(Token::Boolean(if operator == "==" { equal } else { !equal }));
+    }
+
+    self.error(&format!(
+      "Unknown operator expression: {} {} {}",
+      left, operator, right
+    ))
+  }
+
+  // Pushes the function scope with every parameter bound: positional arguments first, then named ones,
+  // then defaults for whatever is left, while extra positional arguments are collected by a rest parameter
+  fn bind_arguments(
+    &self,
+    header: &FunctionHeader,
+    positional: Vec<CtToken>,
+    named: Vec<(String, CtToken)>,
+    environments: &mut Vec<Environment>,
+  ) {
+    let signature = header.signature();
+
+    let rest = match header.args.last() {
+      Some(Some(Token::Variadic(name))) => Some(name.clone()),
+      _ => None,
+    };
+    let fixed = header.args.len() - rest.iter().count();
+    let names: Vec<String> = header.args[..fixed]
+      .iter()
+      .map(|param| match param {
+        Some(Token::Variable { name, .. }) => name.clone(),
+        Some(Token::Binary { left, .. }) => match &**left {
+          Some(Token::Variable { name, .. }) => name.clone(),
+          _ => String::new(),
+        },
+        _ => String::new(),
+      })
+      .collect();
+
+    let mut values: Vec<Option<CtToken>> = vec![None; fixed];
+    let mut extra = Vec::new();
+    let count = positional.len();
+    for (i, value) in positional.into_iter().enumerate() {
+      if i < fixed {
+        values[i] = Some(value);
+      } else if rest.is_some() {
+        extra.push(value);
+      } else {
+        self.error(&format!(
+          "Too many arguments in call to {}, received {}",
+          signature, count
+        ));
+      }
+    }
+
+    for (name, value) in named {
+      match names.iter().position(|param| *param == name) {
+        Some(i) if values[i].is_some() => {
+          self.error(&format!(
+            "Argument {} was given more than once in call to {}",
+            name, signature
+          ));
+        }
+        Some(i) => values[i] = Some(value),
+        None => {
+          self.error(&format!(
+            "Unknown argument {} in call to {}",
+            name, signature
+          ));
+        }
+      }
+    }
+
+    // Defaults are evaluated inside the new scope, so they can refer to earlier parameters
+    environments.push(Environment::new());
+    for (i, name) in names.into_iter().enumerate() {
+      let value = match values[i].take() {
+        Some(value) => value,
+        None => match &header.args[i] {
+          Some(Token::Binary { right, .. }) => self.evaluate((**right).clone(), environments),
+          _ => self.error(&format!(
+            "Missing argument {} in call to {}",
+            name, signature
+          )),
+        },
+      };
+
+      // TODO: Handle possible type differences
+      environments.last_mut().unwrap().define(name, value);
+    }
+    if let Some(rest) = rest {
+      environments
+        .last_mut()
+        .unwrap()
+        .define(rest, Some(Token::Array(extra)));
+    }
+  }
+
+  // Assigns to a variable or to a field of the value it holds, constants and frozen values are rejected
+  fn assign(&self, target: Token, value: CtToken, environments: &mut Vec<Environment>) -> CtToken {
+    // `a[i] = v` updates a copy of the container and assigns that back to wherever it came from
+    if let Token::Index { target, index } = target {
+      let index = self.evaluate(*index, environments);
+      let updated = match (self.evaluate((*target).clone(), environments), index) {
+        (Some(Token::Array(mut items)), Some(Token::Integer(i))) => {
+          match Interpreter::position(i, items.len()) {
+            Some(i) => {
+              items[i] = value.clone();
+              Token::Array(items)
+            }
+            None => {
+              return self.error(&format!(
+                "Index {} is out of bounds for an array of length {}",
+                i,
+                items.len()
+              ))
+            }
+          }
+        }
+        (Some(Token::Map(mut entries)), Some(Token::String(key))) => {
+          entries.insert(key, value.clone());
+          Token::Map(entries)
+        }
+        (Some(Token::Frozen(_)), _) => {
+          return self
+            .parser
+            .error("Cannot assign to an index of a frozen value")
+        }
+        (container, index) => {
+          return self.error(&format!(
+            "Unable to assign to index {} of {}",
+            get_type(&index),
+            get_type(&container)
+          ))
+        }
+      };
+
+      return match *target {
+        Some(target) => {
+          self.assign(target, Some(updated), environments);
+          value
+        }
+        None => None,
+      };
+    }
+
+    // `a.b.c = v` updates field c of a copy of a.b, then assigns that copy back to a.b
+    if let Token::MemberAccess {
+      target, attribute, ..
+    } = target
+    {
+      let path = Interpreter::path(&target);
+      let updated = match self.evaluate((*target).clone(), environments) {
+        Some(Token::Map(mut entries)) => {
+          entries.insert(attribute, value.clone());
+          Token::Map(entries)
+        }
+        Some(Token::Structure {
+          name: structure,
+          mut fields,
+        }) if fields.contains_key(&attribute) => {
+          fields.insert(attribute, value.clone());
+          Token::Structure {
+            name: structure,
+            fields,
+          }
+        }
+        Some(Token::Frozen(_)) => {
+          return self.error(&format!(
+            "Cannot assign to {}.{}, the value is frozen",
+            path, attribute
+          ))
+        }
+        current => {
+          return self.error(&format!(
+            "Unable to assign field {} of {}: {}",
+            attribute,
+            path,
+            get_type(&current)
+          ))
+        }
+      };
+
+      return match *target {
+        Some(target) => {
+          self.assign(target, Some(updated), environments);
+          value
+        }
+        None => None,
+      };
+    }
+
+    let name = match target {
+      Token::VariableCall(name) => name,
+      t => return self.error(&format!("Unable to assign to: {}", t)),
+    };
 
-    if let (Token::Float(num1), Token::Float(num2)) = (left, right) {
-      maybe_num1 = Some(*num1);
-      maybe_num2 = Some(*num2);
+    let environment = match Environment::lookup(environments, &name) {
+      Some(environment) => environment,
+      None => return self.error(&format!("Unknown variable: {}", name)),
+    };
+    if environment.is_constant(&name) {
+      return self
+        .parser
+        .error(&format!("Cannot assign to constant: {}", name));
     }
 
-    if let (Token::Integer(num1), Token::Float(num2)) = (left, right) {
-      maybe_num1 = Some(*num1 as f64);
-      maybe_num2 = Some(*num2);
+    environment.set(&name, value)
+  }
+
+  // How an assignment target reads in error messages, `a.b` or `a[..]`
+  fn path(target: &CtToken) -> String {
+    match target {
+      Some(Token::VariableCall(name)) => name.clone(),
+      Some(Token::MemberAccess {
+        target, attribute, ..
+      }) => format!("{}.{}", Interpreter::path(target), attribute),
+      Some(Token::Index { target, .. }) => format!("{}[..]", Interpreter::path(target)),
+      _ => String::from("value"),
     }
+  }
 
-    if let (Token::Float(num1), Token::Integer(num2)) = (left, right) {
-      maybe_num1 = Some(*num1);
-      maybe_num2 = Some(*num2 as f64);
+  // Scalars are immutable already, so only collections and structure instances are wrapped
+  fn freeze(value: CtToken) -> CtToken {
+    match value {
+      Some(token @ Token::Array(_))
+      | Some(token @ Token::Map(_))
+      | Some(token @ Token::Structure { .. })
+      | Some(token @ Token::EnumValue { .. }) => Some(Token::Frozen(Box::new(token))),
+      value => value,
     }
+  }
 
-    if maybe_num1.is_some() && maybe_num2.is_some() {
-      match operator.as_ref() {
-        "<" => return Some(Token::Boolean(maybe_num1.unwrap() < maybe_num2.unwrap())),
-        ">" => return Some(Token::Boolean(maybe_num1.unwrap() > maybe_num2.unwrap())),
-        "<=" => return Some(Token::Boolean(maybe_num1.unwrap() <= maybe_num2.unwrap())),
-        ">=" => return Some(Token::Boolean(maybe_num1.unwrap() >= maybe_num2.unwrap())),
-        "==" => return Some(Token::Boolean(maybe_num1.unwrap() == maybe_num2.unwrap())),
-        "!=" => return Some(Token::Boolean(maybe_num1.unwrap() != maybe_num2.unwrap())),
-        "+" => return Some(Token::Float(maybe_num1.unwrap() + maybe_num2.unwrap())),
-        "-" => return Some(Token::Float(maybe_num1.unwrap() - maybe_num2.unwrap())),
-        "*" => return Some(Token::Float(maybe_num1.unwrap() * maybe_num2.unwrap())),
-        "/" => return Some(Token::Float(maybe_num1.unwrap() / maybe_num2.unwrap())),
-        "%" => return Some(Token::Float(maybe_num1.unwrap() % maybe_num2.unwrap())),
-        _ => (),
-      }
+  fn match_pattern(pattern: &Pattern, value: &CtToken, bindings: &mut Vec<(String, CtToken)>) -> bool {
+    if let Some(Token::Frozen(inner)) = value {
+      let inner = Some((**inner).clone());
+      return match pattern {
+        Pattern::Binding(name) => {
+          bindings.push((name.clone(), value.clone()));
+          true
+        }
+        pattern => Interpreter::match_pattern(pattern, &inner, bindings),
+      };
     }
 
-    if let (Token::Boolean(bool1), Token::Boolean(bool2)) = (left, right) {
-      match operator.as_ref() {
-        "==" => return Some(Token::Boolean(bool1 == bool2)),
-        "!=" => return Some(Token::Boolean(bool1 != bool2)),
-        "&&" => return Some(Token::Boolean(*bool1 && *bool2)),
-        "||" => return Some(Token::Boolean(*bool1 || *bool2)),
-        _ => (),
+    match pattern {
+      Pattern::Wildcard => true,
+      Pattern::Binding(name) => {
+        bindings.push((name.clone(), value.clone()));
+        true
       }
-    }
+      Pattern::Literal(literal) => values_equal(&Some(literal.clone()), value),
+      Pattern::Range {
+        start,
+        end,
+        inclusive,
+      } => {
+        let number = |t: &Token| match t {
+          Token::Integer(n) => Some(*n as f64),
+          Token::Float(n) => Some(*n),
+          _ => None,
+        };
 
-    if let (Token::String(str1), Token::String(str2)) = (left, right) {
-      match operator.as_ref() {
-        "<" => return Some(Token::Boolean(str1 < str2)),
-        ">" => return Some(Token::Boolean(str1 > str2)),
-        "<=" => return Some(Token::Boolean(str1 <= str2)),
-        ">=" => return Some(Token::Boolean(str1 >= str2)),
-        "==" => return Some(Token::Boolean(str1 == str2)),
-        "!=" => return Some(Token::Boolean(str1 != str2)),
-        "+" => return Some(Token::String((*str1).clone() + str2)),
-        _ => (),
+        match (number(start), number(end), value.as_ref().and_then(number)) {
+          (Some(start), Some(end), Some(n)) => n >= start && if *inclusive { n <= end } else { n < end },
+          _ => match (start, end, value) {
+            (Token::String(start), Token::String(end), Some(Token::String(s))) => {
+              s >= start && if *inclusive { s <= end } else { s < end }
+            }
+            _ => false,
+          },
+        }
+      }
+      Pattern::Alternative(alternatives) => alternatives.iter().any(|alternative| {
+        let mut alternative_bindings = Vec::new();
+        if Interpreter::match_pattern(alternative, value, &mut alternative_bindings) {
+          bindings.extend(alternative_bindings);
+          true
+        } else {
+          false
+        }
+      }),
+      Pattern::Array(patterns) => match value {
+        Some(Token::Array(items)) if items.len() == patterns.len() => patterns
+          .iter()
+          .zip(items.iter())
+          .all(|(pattern, item)| Interpreter::match_pattern(pattern, item, bindings)),
+        _ => false,
+      },
+      Pattern::Variant {
+        name,
+        variant,
+        fields: patterns,
+      } => match value {
+        Some(Token::EnumValue {
+          name: enum_name,
+          variant: actual,
+          fields,
+        }) => {
+          name.as_ref().is_none_or(|name| name == enum_name)
+            && variant == actual
+            && patterns.as_ref().is_none_or(|patterns| {
+              patterns.len() == fields.len()
+                && patterns
+                  .iter()
+                  .zip(fields.iter())
+                  .all(|(pattern, (_, field))| Interpreter::match_pattern(pattern, field, bindings))
+            })
+        }
+        _ => false,
+      },
+      Pattern::Structure {
+        name,
+        fields: patterns,
+      } => {
+        let field = |key: &String| match (name, value) {
+          (Some(name), Some(Token::Structure { name: actual, fields })) if name == actual => {
+            fields.get(key).cloned()
+          }
+          (None, Some(Token::Structure { fields, .. })) => fields.get(key).cloned(),
+          (None, Some(Token::Map(entries))) => entries.get(key).cloned(),
+          _ => None,
+        };
+
+        let shape = match (name, value) {
+          (Some(name), Some(Token::Structure { name: actual, .. })) => name == actual,
+          (None, Some(Token::Structure { .. })) | (None, Some(Token::Map(_))) => true,
+          _ => false,
+        };
+
+        shape
+          && patterns.iter().all(|(key, pattern)| match field(key) {
+            Some(field_value) => Interpreter::match_pattern(pattern, &field_value, bindings),
+            None => false,
+          })
       }
     }
+  }
 
-    self.parser.error(&format!(
-      "Unknown operator expression: {} {} {}",
-      left, operator, right
-    ))
+  // Describes the first place a destructured value does not have the shape of its pattern
+  fn shape_mismatch(pattern: &Pattern, value: &CtToken) -> String {
+    let describe = |value: &CtToken| match value {
+      Some(t) => t.to_string(),
+      None => String::from(types::NULL),
+    };
+    let mismatch = |pattern: &Pattern, item: &CtToken| {
+      if Interpreter::match_pattern(pattern, item, &mut Vec::new()) {
+        None
+      } else {
+        Some(Interpreter::shape_mismatch(pattern, item))
+      }
+    };
+
+    let found = match (pattern, thaw(value.clone())) {
+      (Pattern::Array(patterns), Some(Token::Array(items))) => {
+        if items.len() == patterns.len() {
+          patterns
+            .iter()
+            .zip(items.iter())
+            .find_map(|(pattern, item)| mismatch(pattern, item))
+        } else {
+          Some(format!(
+            "Expected an array of {} items to destructure, but received {}: {}",
+            patterns.len(),
+            items.len(),
+            describe(value)
+          ))
+        }
+      }
+      (Pattern::Array(patterns), _) => Some(format!(
+        "Expected an array of {} items to destructure, but received: {}",
+        patterns.len(),
+        get_type(value)
+      )),
+      (Pattern::Structure { name: None, fields }, thawed) => {
+        let field = |key: &String| match &thawed {
+          Some(Token::Map(entries)) => entries.get(key).cloned(),
+          Some(Token::Structure { fields, .. }) => fields.get(key).cloned(),
+          _ => None,
+        };
+
+        match thawed {
+          Some(Token::Map(_)) | Some(Token::Structure { .. }) => {
+            fields.iter().find_map(|(key, pattern)| match field(key) {
+              Some(item) => mismatch(pattern, &item),
+              None => Some(format!("Missing {} to destructure in {}", key, describe(value))),
+            })
+          }
+          _ => Some(format!(
+            "Expected a map or struct to destructure, but received: {}",
+            get_type(value)
+          )),
+        }
+      }
+      _ => None,
+    };
+
+    found.unwrap_or_else(|| format!("Unable to destructure {}", describe(value)))
   }
 
-  fn unwrap_return(&self, t: CtToken, environments: &mut Vec<Environment>) -> CtToken {
-    if let Token::Return(value) = t.unwrap() {
-      let return_value = self.evaluate(*value, environments);
+  fn assign_all(&self, targets: Vec<CtToken>, value: CtToken, environments: &mut Vec<Environment>) {
+    let items = match thaw(value) {
+      Some(Token::Array(items)) if items.len() == targets.len() => items,
+      Some(Token::Array(items)) => {
+        self.error(&format!(
+          "Expected an array of {} items to assign, but received {}",
+          targets.len(),
+          items.len()
+        ));
+        return;
+      }
+      value => {
+        self.error(&format!(
+          "Expected an array of {} items to assign, but received: {}",
+          targets.len(),
+          get_type(&value)
+        ));
+        return;
+      }
+    };
 
-      // TODO: Handle possible type differences
-      let return_value = self.evaluate(return_value, environments);
-      environments.pop();
-      return_value
-    } else {
-      None
+    for (target, item) in targets.into_iter().zip(items) {
+      match target {
+        Some(Token::VariableCall(ref name)) if name == "_" => (),
+        Some(Token::Array(targets)) => self.assign_all(targets, item, environments),
+        Some(target @ Token::VariableCall(_))
+        | Some(target @ Token::MemberAccess { args: None, .. })
+        | Some(target @ Token::Index { .. }) => {
+          self.assign(target, item, environments);
+        }
+        target => {
+          self.error(&format!(
+            "Unable to assign to {}",
+            target.map_or(String::from(types::NULL), |t| t.to_string())
+          ));
+        }
+      }
     }
   }
 
-  fn string_to_int(string: &String) -> Option<i32> {
-    if let Ok(i) = string.parse::<i32>() {
+  fn string_to_int(string: &String) -> Option<i64> {
+    if let Ok(i) = string.parse::<i64>() {
       Some(i)
     } else {
       None
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use ct::modules::{os, regex, string, time};
+  use ct::structs;
+
+  // Runs a script statement by statement, each one has to leave just the global scope behind
+  fn run(code: &str) -> Environment {
+    run_with_depth(code, MAX_CALL_DEPTH)
+  }
+
+  fn run_with_depth(code: &str, max_call_depth: usize) -> Environment {
+    let mut interpreter = Interpreter::new(String::from(code));
+    interpreter.set_max_call_depth(max_call_depth);
+    interpreter.append_module(string::Str);
+    interpreter.append_struct("String", structs::string::string_struct());
+    interpreter.append_struct("Array", structs::array::array_struct());
+    interpreter.append_struct("Iterator", structs::iterator::iterator_struct());
+    let mut environments = vec![Environment::new()];
+
+    while let Some(token) = interpreter.parser.next() {
+      let statement = token.to_string();
+      interpreter.evaluate(Some(token), &mut environments);
+      assert_eq!(environments.len(), 1, "scope depth after: {}", statement);
+    }
+
+    environments.pop().unwrap()
+  }
+
+  // The message a script stops with, script errors unwind as panics
+  fn script_error(code: &str) -> String {
+    let code = String::from(code);
+    let payload =
+      std::panic::catch_unwind(move || run(&code)).expect_err("the script ran to the end");
+    match payload.downcast::<String>() {
+      Ok(message) => *message,
+      Err(_) => String::from("a panic without a message"),
+    }
+  }
+
+  fn assert_var(scope: &Environment, name: &str, expected: CtToken) {
+    let value = scope.get(&String::from(name));
+    assert!(values_equal(&value, &expected), "{} is {}", name, get_type(&value));
+  }
+
+  #[test]
+  fn signals_are_statements_only() {
+    let cases = [
+      "let show(let x) { return x; } show(if (true) { return 1; } else { return 0; });",
+      "let f() { let y = 1 + if (true) { return 5; } else { return 0; }; } f();",
+      "while (true) { let x = match (1) { 1 => { break; } }; }",
+    ];
+    for code in cases.iter() {
+      let error = script_error(code);
+      assert!(error.contains("can only be used as"), "{} stopped with {}", code, error);
+    }
+  }
+
+  #[test]
+  fn if_blocks_pop_their_scope() {
+    let scope = run("let x = 0; if (true) { let y = 1; x = y; } else { x = 2; }");
+    assert_var(&scope, "x", Some(Token::Integer(1)));
+    assert!(!scope.vars.contains_key("y"));
+  }
+
+  #[test]
+  fn loops_pop_their_scope_on_every_exit() {
+    let scope = run(
+      "let n = 0;
+      while (n < 10) { let m = n; n += 1; if (m == 5) { break; } continue; }
+      for x in 0..10 { if (x % 2 == 0) { continue; } if (x > 6) { break; } }
+      let i = 0; while (i < 3) { i += 1; }",
+    );
+    assert_var(&scope, "n", Some(Token::Integer(6)));
+    assert_var(&scope, "i", Some(Token::Integer(3)));
+  }
+
+  #[test]
+  fn return_leaves_nested_blocks() {
+    let scope = run(
+      "let f(let a) { if (a > 1) { for x in 0..a { while (true) { return x + 10; } } } return 0; }
+      let g() { let unused = 1; }
+      let r = [f(5), f(0), g()];",
+    );
+    assert_var(
+      &scope,
+      "r",
+      Some(Token::Array(vec![
+        Some(Token::Integer(10)),
+        Some(Token::Integer(0)),
+        None,
+      ])),
+    );
+  }
+
+  #[test]
+  fn integer_overflow_is_an_error() {
+    let scope = run(
+      "let a = 9223372036854775807 + 1; let b = 1 / 0; let c = 7 % 0;
+      let d = 0 - 9223372036854775807 - 2; let e = 4611686018427387904 * 2; let f = 7 / 2;",
+    );
+    for name in ["a", "b", "c", "d", "e"].iter() {
+      assert!(matches!(scope.get(&String::from(*name)), Some(Token::Error(_))), "{} is not an error", name);
+    }
+    assert_var(&scope, "f", Some(Token::Integer(3)));
+  }
+
+  #[test]
+  fn match_arms_pop_their_scope() {
+    let scope = run("let r = match ([1, 2]) { [a, b] if a > b => a, [a, b] => b, _ => 0 };");
+    assert_var(&scope, "r", Some(Token::Integer(2)));
+  }
+
+  #[test]
+  fn inner_declarations_shadow_outer_ones() {
+    let scope = run(
+      "let x = 1; const c = 1;
+      if (true) { let x = 2; const c = 2; x += 1; }
+      let f(let x) { x = 5; return x; }
+      let y = f(x);
+      if (true) { x = 3; }",
+    );
+    assert_var(&scope, "x", Some(Token::Integer(3)));
+    assert_var(&scope, "c", Some(Token::Integer(1)));
+    assert_var(&scope, "y", Some(Token::Integer(5)));
+  }
+
+  #[test]
+  #[should_panic(expected = "Cannot redeclare constant: c")]
+  fn constants_cannot_be_redeclared_in_their_own_scope() {
+    run("const c = 1; if (true) { const c = 2; const c = 3; }");
+  }
+
+  #[test]
+  fn constants_and_frozen_values_reject_changes() {
+    let cases = [
+      ("const c = 1; c = 2;", "Cannot assign to constant: c"),
+      ("const c = 1; c += 2;", "Cannot assign to constant: c"),
+      ("const c = [1]; c[0] = 2;", "Cannot assign to constant: c"),
+      ("let a = freeze([1, [2]]); let b = a[1]; b[0] = 3;", "Cannot assign to an index of a frozen value"),
+      ("let m = freeze({ k: 1 }); m.k = 2;", "Cannot assign to m.k, the value is frozen"),
+      ("struct P { let x; } let p = freeze(P(1)); p.x = 2;", "Cannot assign to p.x, the value is frozen"),
+    ];
+    for (code, expected) in cases.iter() {
+      let error = script_error(code);
+      assert!(error.contains(expected), "{} stopped with {}", code, error);
+    }
+  }
+
+  #[test]
+  fn frozen_values_can_be_read_and_copied() {
+    let scope = run(
+      "const limit = 3; let total = 0; for i in 0..limit { total += i; }
+      let config = freeze({ sizes: [1, 2] }); let first = config.sizes[0];
+      let flags = [is_frozen(config), is_frozen(config.sizes), is_frozen([1])];",
+    );
+    assert_var(&scope, "total", Some(Token::Integer(3)));
+    assert_var(&scope, "first", Some(Token::Integer(1)));
+    assert_var(
+      &scope,
+      "flags",
+      Some(Token::Array(vec![
+        Some(Token::Boolean(true)),
+        Some(Token::Boolean(true)),
+        Some(Token::Boolean(false)),
+      ])),
+    );
+  }
+
+  #[test]
+  fn script_definitions_win_over_intrinsics() {
+    let scope = run(
+      "let freeze(let x) { return \"mine\"; } let is_frozen(let x) { return 42; }
+      struct range { let low; }
+      let wrap() { let map(let f, let a) { return \"inner\"; } return map(1, 2); }
+      let r = [freeze(1), is_frozen(1), range(5).low, wrap()];",
+    );
+    assert_var(
+      &scope,
+      "r",
+      Some(Token::Array(vec![
+        Some(Token::String(String::from("mine"))),
+        Some(Token::Integer(42)),
+        Some(Token::Integer(5)),
+        Some(Token::String(String::from("inner"))),
+      ])),
+    );
+  }
+
+  #[test]
+  fn default_named_and_rest_parameters() {
+    let scope = run(
+      "let f(let a, let b = 2) { return a * 10 + b; }
+      let g(let a, let b = a + 1) { return b; }
+      let sum(let first = 0, let ...rest) { let t = first; for n in rest { t += n; } return t; }
+      let rest(let a, let ...more) { return more; }
+      let r = [f(1), f(1, 3), f(b: 5, a: 2), f(4, b: 0), g(1), sum(), sum(1, 2, 3), rest(1)];",
+    );
+    let expected = [12, 13, 25, 40, 2, 0, 6];
+    match scope.get(&String::from("r")) {
+      Some(Token::Array(items)) => {
+        for (item, expected) in items.iter().zip(expected.iter()) {
+          assert!(values_equal(item, &Some(Token::Integer(*expected))));
+        }
+        assert!(values_equal(&items[7], &Some(Token::Array(Vec::new()))));
+      }
+      value => panic!("r is {}", get_type(&value)),
+    }
+  }
+
+  #[test]
+  fn parameter_mismatches_name_the_signature() {
+    let cases = [
+      ("let f(let a, let b = 2) { return a; } f();", "Missing argument a in call to f(a, b = 2)"),
+      ("let f(let a) { return a; } f(1, 2);", "Too many arguments in call to f(a), received 2"),
+      ("let f(let a) { return a; } f(c: 1);", "Unknown argument c in call to f(a)"),
+      ("let f(let a) { return a; } f(1, a: 2);", "Argument a was given more than once"),
+      ("let f(let a) { return a; } f(a: 1, 2);", "Positional arguments must come before named"),
+      ("let f(let ...a, let b) { return a; }", "The rest parameter of f must be the last"),
+    ];
+    for (code, expected) in cases.iter() {
+      let error = script_error(code);
+      assert!(error.contains(expected), "{} stopped with {}", code, error);
+    }
+  }
+
+  #[test]
+  fn functions_closures_and_natives_are_values() {
+    let scope = run(
+      "let apply(let f, let x) { return f(x); }
+      let adder(let n) { return let (x) { return x + n; }; }
+      let twice(let f) { return let (x) { return f(f(x)); }; }
+      let inc = adder(1); let named = apply; let add10 = twice(adder(5));
+      let r = [apply(inc, 1), apply(len, \"abc\"), add10(0), named(twice(inc), 0)];",
+    );
+    let expected: Vec<CtToken> = [2, 3, 10, 2].iter().map(|n| Some(Token::Integer(*n))).collect();
+    assert_var(&scope, "r", Some(Token::Array(expected)));
+  }
+
+  #[test]
+  fn array_callbacks() {
+    let scope = run(
+      "let xs = [3, 1, 2]; let big(let x) { return x > 1; }
+      let doubled = xs.map(let (x) { return x * 2; }); let kept = xs.filter(big);
+      let total = xs.reduce(let (acc, x) { return acc + x; }, 10);
+      let checks = [xs.any(big), xs.all(big), xs.find(big), xs.find(let (x) { return x > 5; })];
+      let sorted = xs.sort_by(let (a, b) { return b - a; });",
+    );
+    let ints = |ns: &[i64]| {
+      Some(Token::Array(ns.iter().map(|n| Some(Token::Integer(*n))).collect()))
+    };
+    assert_var(&scope, "doubled", ints(&[6, 2, 4]));
+    assert_var(&scope, "kept", ints(&[3, 2]));
+    assert_var(&scope, "total", Some(Token::Integer(16)));
+    assert_var(&scope, "sorted", ints(&[3, 2, 1]));
+    assert_var(
+      &scope,
+      "checks",
+      Some(Token::Array(vec![
+        Some(Token::Boolean(true)),
+        Some(Token::Boolean(false)),
+        Some(Token::Integer(3)),
+        Some(Token::Null),
+      ])),
+    );
+  }
+
+  #[test]
+  fn iterators_and_ranges() {
+    let scope = run(
+      "let stepped = range(0, 10, 3).collect(); let down = range(3, 0, -1).collect();
+      let exclusive = (1..4).collect(); let inclusive = (1..=4).collect();
+      let pairs = iter([\"a\", \"b\"]).enumerate().collect();
+      let zipped = iter(\"ab\").zip(range(5, 1000000000)).collect();
+      let lazy = range(0, 1000000000).skip(2).take(3).collect();
+      let chained = iter([1]).chain(iter([2, 3])).collect();
+      let keys = \"\"; for [key, value] in { b: 2, a: 1 } { keys = keys + key; }
+      let total = 0; for x in 1..=3 { total += x; }
+      let it = iter([1]); let steps = [next(it), next(it)];",
+    );
+    let ints = |ns: &[i64]| {
+      Some(Token::Array(ns.iter().map(|n| Some(Token::Integer(*n))).collect()))
+    };
+    assert_var(&scope, "stepped", ints(&[0, 3, 6, 9]));
+    assert_var(&scope, "down", ints(&[3, 2, 1]));
+    assert_var(&scope, "exclusive", ints(&[1, 2, 3]));
+    assert_var(&scope, "inclusive", ints(&[1, 2, 3, 4]));
+    assert_var(&scope, "lazy", ints(&[2, 3, 4]));
+    assert_var(&scope, "chained", ints(&[1, 2, 3]));
+    assert_var(&scope, "total", Some(Token::Integer(6)));
+    assert_var(&scope, "keys", Some(Token::String(String::from("ab"))));
+    let show = |name: &str| scope.get(&String::from(name)).unwrap().to_string();
+    assert_eq!(show("pairs"), "[[0, \"a\"], [1, \"b\"]]");
+    assert_eq!(show("zipped"), "[[\"a\", 5], [\"b\", 6]]");
+    assert_eq!(
+      show("steps"),
+      "[{\"done\": false, \"value\": 1}, {\"done\": true, \"value\": null}]"
+    );
+  }
+
+  #[test]
+  fn structs_implement_the_iterator_protocol() {
+    let scope = run(
+      "struct Countdown {
+        let n;
+        let next(let self) {
+          if (self.n == 0) { return { done: true }; }
+          self.n -= 1;
+          return { done: false, value: self.n + 1 };
+        }
+      }
+      struct Bag { let items; let iter(let self) { return iter(self.items); } }
+      let seen = 0; for x in Countdown(3) { seen = seen * 10 + x; }
+      let bagged = 0; for x in Bag([4, 5]) { bagged += x; }
+      let taken = iter(Countdown(5)).take(2).collect();",
+    );
+    assert_var(&scope, "seen", Some(Token::Integer(321)));
+    assert_var(&scope, "bagged", Some(Token::Integer(9)));
+    assert_var(
+      &scope,
+      "taken",
+      Some(Token::Array(vec![Some(Token::Integer(5)), Some(Token::Integer(4))])),
+    );
+  }
+
+  #[test]
+  fn broken_iterators_stop_the_script() {
+    let cases = [
+      ("for x in 5 { }", "Value of type int is not iterable"),
+      (
+        "struct Bad { let next(let self) { return 1; } } for x in Bad() { }",
+        "Expected next() to return { done, value }",
+      ),
+    ];
+    for (code, expected) in cases.iter() {
+      let error = script_error(code);
+      assert!(error.contains(expected), "{} stopped with {}", code, error);
+    }
+  }
+
+  #[test]
+  fn generators_suspend_between_items() {
+    let scope = run(
+      "let nat() { let i = 0; while (true) { yield i; i += 1; } }
+      let evens() {
+        for n in nat() { if (n % 2 == 1) { continue; } if (n > 6) { break; } yield n; }
+        yield \"end\";
+      }
+      let early(let n) { yield 1; if (n > 0) { return 99; } yield 2; }
+      let started = 0; let counting() { started += 1; yield started; }
+      let pending = counting(); let before = started; let counted = pending.collect();
+      let g = nat(); next(g); let second = next(g).value;
+      let firsts = nat().take(3).collect();
+      let r = [evens().collect(), early(1).collect(), early(0).collect()];",
+    );
+    let ints = |ns: &[i64]| {
+      Some(Token::Array(ns.iter().map(|n| Some(Token::Integer(*n))).collect()))
+    };
+    assert_var(&scope, "before", Some(Token::Integer(0)));
+    assert_var(&scope, "counted", ints(&[1]));
+    assert_var(&scope, "second", Some(Token::Integer(1)));
+    assert_var(&scope, "firsts", ints(&[0, 1, 2]));
+    let r = scope.get(&String::from("r")).unwrap().to_string();
+    assert_eq!(r, "[[0, 2, 4, 6, \"end\"], [1], [1, 2]]");
+  }
+
+  #[test]
+  #[should_panic(expected = "yield can only be used inside a function")]
+  fn yield_needs_a_function() {
+    run("yield 1;");
+  }
+
+  #[test]
+  fn destructuring_and_multiple_returns() {
+    let scope = run(
+      "let [a, b] = [1, 2]; [a, b] = [b, a];
+      let { x, y: renamed } = { x: 3, y: 4 };
+      let [first, [second, third]] = [5, [6, 7]];
+      let pair() { return 8, 9; } let [p, q] = pair();
+      struct Point { let px; let py; } let { px, py } = Point(10, 11);",
+    );
+    let expected = [
+      ("a", 2), ("b", 1), ("x", 3), ("renamed", 4), ("first", 5), ("second", 6), ("third", 7),
+      ("p", 8), ("q", 9), ("px", 10), ("py", 11),
+    ];
+    for (name, value) in expected.iter() {
+      assert_var(&scope, name, Some(Token::Integer(*value)));
+    }
+  }
+
+  #[test]
+  fn destructuring_checks_the_shape() {
+    let cases = [
+      ("let [a, b] = [1];", "Expected an array of 2 items to destructure, but received 1"),
+      ("let [a] = 5;", "Expected an array of 1 items to destructure, but received: int"),
+      ("let { x } = { y: 1 };", "Missing x to destructure"),
+      ("const [a, b] = [1, 2]; a = 3;", "Cannot assign to constant: a"),
+    ];
+    for (code, expected) in cases.iter() {
+      let error = script_error(code);
+      assert!(error.contains(expected), "{} stopped with {}", code, error);
+    }
+  }
+
+  #[test]
+  fn structs_overload_operators() {
+    let scope = run(
+      "struct Vec2 {
+        let x = 0; let y = 0;
+        let add(let self, let o) { return Vec2(self.x + o.x, self.y + o.y); }
+        let mul(let self, let k) { return Vec2(self.x * k, self.y * k); }
+        let eq(let self, let o) { return self.x == o.x && self.y == o.y; }
+        let lt(let self, let o) { return self.x < o.x; }
+        let to_string(let self) { return self.x > 0 ? \"right\" : \"left\"; }
+        let index(let self, let i) { return i == 0 ? self.x : self.y; }
+        let shift(let self, let dx) { self.x += dx; }
+      }
+      let a = Vec2(1, 2); let b = Vec2(y: 5);
+      let sum = a + b; let scaled = a * 3; let label = \"v=\" + Vec2(-1);
+      let checks = [a == Vec2(1, 2), a != b, a < b, a >= b, b <= a];
+      let second = a[1]; a.shift(10); let moved = a.x;
+      let xs = [1, 2, 3]; xs[-1] = 9; let last = xs[-1];",
+    );
+    let field = |name: &str, field: &str| match scope.get(&String::from(name)) {
+      Some(Token::Structure { fields, .. }) => fields[field].clone(),
+      value => panic!("{} is {}", name, get_type(&value)),
+    };
+    assert!(values_equal(&field("sum", "y"), &Some(Token::Integer(7))));
+    assert!(values_equal(&field("scaled", "x"), &Some(Token::Integer(3))));
+    assert_var(&scope, "label", Some(Token::String(String::from("v=left"))));
+    assert_var(&scope, "second", Some(Token::Integer(2)));
+    assert_var(&scope, "moved", Some(Token::Integer(11)));
+    assert_var(&scope, "last", Some(Token::Integer(9)));
+    let checks = [true, true, false, true, true];
+    let checks = checks.iter().map(|b| Some(Token::Boolean(*b))).collect();
+    assert_var(&scope, "checks", Some(Token::Array(checks)));
+  }
+
+  #[test]
+  fn variables_are_indexed_in_place() {
+    let scope = run(
+      "let a = [1, [2, 3]]; let m = { k: 4 }; const f = freeze([[5]]);
+      let items = [a[0], a[-1][1], m[\"k\"], m[\"missing\"], f[0][0]]; let inner = f[0];",
+    );
+    assert_eq!(scope.get(&String::from("items")).unwrap().to_string(), "[1, 3, 4, null, 5]");
+    assert!(matches!(scope.get(&String::from("inner")), Some(Token::Frozen(_))));
+  }
+
+  #[test]
+  fn struct_misuse_is_reported() {
+    let cases = [
+      ("struct A { let x; } let a = A(1) + A(2);", "Unable to apply +, A has no add method"),
+      ("struct A { let x; } A(1, 2);", "Too many arguments in call to A(x), received 2"),
+      ("struct A { let x; } A(z: 1);", "Unknown argument z in call to A(x)"),
+      ("let a = [1][5];", "Index 5 is out of bounds for an array of length 1"),
+      ("let a = [1]; let b = a[-2];", "Index -2 is out of bounds for an array of length 1"),
+    ];
+    for (code, expected) in cases.iter() {
+      let error = script_error(code);
+      assert!(error.contains(expected), "{} stopped with {}", code, error);
+    }
+  }
+
+  #[test]
+  fn inheritance_super_and_interfaces() {
+    let scope = run(
+      "interface Speaker { let speak(let self); }
+      struct Animal {
+        let name; let legs = 4;
+        let speak(let self) { return \"...\"; }
+        let describe(let self) { return self.name; }
+      }
+      struct Dog extends Animal implements Speaker {
+        let speak(let self) { return \"woof\"; }
+        let quiet(let self) { return super.speak(); }
+      }
+      let d = Dog(\"rex\");
+      let said = [d.speak(), d.describe(), d.quiet()]; let legs = d.legs;
+      let checks = [d is Dog, d is Animal, d is Speaker, Animal(\"a\") is Dog];",
+    );
+    let said = scope.get(&String::from("said")).unwrap().to_string();
+    assert_eq!(said, "[\"woof\", \"rex\", \"...\"]");
+    assert_var(&scope, "legs", Some(Token::Integer(4)));
+    let checks = [true, true, true, false];
+    let checks = checks.iter().map(|b| Some(Token::Boolean(*b))).collect();
+    assert_var(&scope, "checks", Some(Token::Array(checks)));
+  }
+
+  #[test]
+  fn broken_hierarchies_are_reported() {
+    let cases = [
+      (
+        "interface S { let speak(let self); } struct A implements S { let x; }",
+        "A does not implement speak(self) of interface S",
+      ),
+      ("struct A extends Missing { let x; }", "Unknown struct Missing extended by A"),
+      (
+        "struct A { let f(let self) { return super.f(); } } A().f();",
+        "super can only be used in methods of a struct that extends another",
+      ),
+      ("struct A { let x; } struct A { let y; }", "Struct already exists: A"),
+    ];
+    for (code, expected) in cases.iter() {
+      let error = script_error(code);
+      assert!(error.contains(expected), "{} stopped with {}", code, error);
+    }
+  }
+
+  #[test]
+  fn member_access_on_any_expression() {
+    let scope = run(
+      "let f() { return \"abc\"; } let m = { a: { b: { c: 3 } } };
+      let r = [\"abc\".len(), f().len(), m.a.b.c, (\"x\" + \"yz\").len(), [[1, 2]][0][1],
+        m.a.missing];
+      let unknown = [1].push(2);",
+    );
+    let expected = [3, 3, 3, 3, 2];
+    match scope.get(&String::from("r")) {
+      Some(Token::Array(items)) => {
+        for (item, expected) in items.iter().zip(expected.iter()) {
+          assert!(values_equal(item, &Some(Token::Integer(*expected))));
+        }
+        assert!(values_equal(&items[5], &Some(Token::Null)));
+      }
+      value => panic!("r is {}", get_type(&value)),
+    }
+    match scope.get(&String::from("unknown")) {
+      Some(Token::Error(e)) => assert_eq!(e, "Array has no method push"),
+      value => panic!("unknown is {}", get_type(&value)),
+    }
+  }
+
+  #[test]
+  fn discarded_errors_are_uncaught_anywhere() {
+    let cases = [
+      "[1].push(2);",
+      "let f() { [1].push(2); return 1; } let r = f();",
+      "if (true) { [1].push(2); }",
+      "for x in [1] { [1].push(2); }",
+      "let n = match (1) { 1 => { [1].push(2); 2; } };",
+      "let g() { [1].push(2); yield 1; } let all = collect(g());",
+    ];
+    for code in cases.iter() {
+      let mut interpreter = Interpreter::new(String::from(*code));
+      assert_eq!(
+        interpreter.execute(),
+        Err(String::from("Uncaught error: Array has no method push")),
+        "{}",
+        code
+      );
+    }
+
+    let mut interpreter = Interpreter::new(String::from(
+      "let r = [1].push(2); let n = match (1) { 1 => [1].push(2) };",
+    ));
+    assert_eq!(interpreter.execute(), Ok(()));
+  }
+
+  #[test]
+  fn built_in_methods_ignore_script_functions() {
+    let scope = run(
+      "let len(let x) { return 99; } let map(let a, let b, let c) { return 0; }
+      let n = \"abc\".len(); let m = [1, 2].map(let (x) { return x * 2; });",
+    );
+    assert_var(&scope, "n", Some(Token::Integer(3)));
+    assert_eq!(scope.get(&String::from("m")).unwrap().to_string(), "[2, 4]");
+  }
+
+  #[test]
+  fn member_access_keeps_errors() {
+    let mut interpreter = Interpreter::new(String::from(
+      "let r = regex(\"(\"); let m = r.matches(\"x\"); let s = r.source;",
+    ));
+    interpreter.append_module(regex::RegexModule);
+    interpreter.append_struct("Regex", structs::regex::regex_struct());
+    assert_eq!(interpreter.execute(), Ok(()));
+    for name in ["m", "s"].iter() {
+      match interpreter.environments[0].get(&String::from(*name)) {
+        Some(Token::Error(e)) => assert!(e.starts_with("Invalid regex"), "{} is {}", name, e),
+        value => panic!("{} is {}", name, get_type(&value)),
+      }
+    }
+  }
+
+  #[test]
+  fn deep_recursion_returns_an_error() {
+    let scope = run_with_depth(
+      "let f(let n) { if (n == 0) { return 0; } return f(n - 1) + 1; }
+      let shallow = f(5); let deep = f(50);",
+      10,
+    );
+    assert_var(&scope, "shallow", Some(Token::Integer(5)));
+    match scope.get(&String::from("deep")) {
+      Some(Token::Error(e)) => assert_eq!(e, "Maximum recursion depth of 10 exceeded in f"),
+      value => panic!("deep is {}", get_type(&value)),
+    }
+  }
+
+  #[test]
+  fn nested_generators_count_towards_the_depth() {
+    let scope = run_with_depth(
+      "let g(let n) { if (n < 100) { for x in g(n + 1) { yield x; } } yield n; }
+      let shallow = collect(take(g(97), 2)); let deep = collect(g(0));",
+      10,
+    );
+    assert_eq!(scope.get(&String::from("shallow")).unwrap().to_string(), "[100, 99]");
+    match scope.get(&String::from("deep")) {
+      Some(Token::Error(e)) => assert!(e.starts_with("Maximum recursion depth of 10"), "{}", e),
+      value => panic!("deep is {}", get_type(&value)),
+    }
+  }
+
+  #[test]
+  fn tail_calls_never_escape_their_function() {
+    let error = script_error(
+      "let h() { return 1; } let k() { let x = [if (true) { return h(); } else { return 0; }];
+      return x; } let y = k();",
+    );
+    assert!(error.contains("return can only be used as a statement"), "{}", error);
+  }
+
+  #[test]
+  fn tail_calls_run_in_constant_stack() {
+    let scope = run_with_depth(
+      "let sum(let n, let acc) { if (n == 0) { return acc; } return sum(n - 1, acc + n); }
+      let even(let n) { if (n == 0) { return true; } return odd(n - 1); }
+      let odd(let n) { if (n == 0) { return false; } return even(n - 1); }
+      let total = sum(10000, 0); let is_even = even(10001);",
+      10,
+    );
+    assert_var(&scope, "total", Some(Token::Integer(50005000)));
+    assert_var(&scope, "is_even", Some(Token::Boolean(false)));
+  }
+
+  #[test]
+  fn top_level_returns_run_their_call() {
+    let mut interpreter = Interpreter::new(String::from(
+      "let calls = 0; let f() { calls += 1; return calls; }
+      if (true) { return f(); } while (true) { return f(); } return f();
+      let after = calls;",
+    ));
+    assert_eq!(interpreter.execute(), Ok(()));
+    assert_var(&interpreter.environments[0], "after", Some(Token::Integer(3)));
+  }
+
+  fn execute_with(code: &str, limits: Limits) -> Interpreter {
+    let mut interpreter = Interpreter::new(String::from(code));
+    interpreter.append_module(iostream::IOStream);
+    interpreter.set_limits(limits);
+    interpreter
+  }
+
+  #[test]
+  fn fuel_bounds_evaluation_steps() {
+    let limits = Limits {
+      fuel: Some(1000),
+      ..Limits::default()
+    };
+    assert_eq!(
+      execute_with("let i = 0; while (true) { i += 1; }", limits).execute(),
+      Err(String::from("Fuel limit of 1000 evaluation steps exceeded"))
+    );
+  }
+
+  #[test]
+  fn fuel_bounds_endless_iterators() {
+    let limits = Limits {
+      fuel: Some(1000),
+      ..Limits::default()
+    };
+    let mut interpreter = execute_with("let all = collect(range(0, 1000000000));", limits);
+    assert_eq!(
+      interpreter.execute(),
+      Err(String::from("Fuel limit of 1000 evaluation steps exceeded"))
+    );
+  }
+
+  #[test]
+  fn time_bounds_execution() {
+    let limits = Limits {
+      time: Some(std::time::Duration::from_millis(50)),
+      ..Limits::default()
+    };
+    assert_eq!(
+      execute_with("while (true) { }", limits).execute(),
+      Err(String::from("Time limit of 50ms exceeded"))
+    );
+  }
+
+  #[test]
+  fn memory_bounds_value_sizes() {
+    let limits = Limits {
+      memory: Some(64),
+      ..Limits::default()
+    };
+    assert_eq!(
+      execute_with("let s = \"abcde\"; while (true) { s = s + s; }", limits).execute(),
+      Err(String::from("Memory limit of 64 bytes exceeded by a String of 80 bytes"))
+    );
+  }
+
+  #[test]
+  fn memory_counts_nested_values() {
+    let limits = Limits {
+      memory: Some(1 << 16),
+      ..Limits::default()
+    };
+    let mut interpreter = execute_with("let a = [1]; while (true) { a = [a, a]; }", limits);
+    match interpreter.execute() {
+      Err(e) => assert!(e.starts_with("Memory limit of 65536 bytes exceeded by a Array"), "{}", e),
+      result => panic!("expected the memory limit, received {:?}", result),
+    }
+  }
+
+  #[test]
+  fn memory_adds_up_all_variables() {
+    let limits = Limits {
+      memory: Some(1000),
+      ..Limits::default()
+    };
+    let text = "let s = \"{}\"; let a = s; let b = s; let c = s; let d = s; let done = true;";
+    let mut interpreter = execute_with(&text.replace("{}", &"x".repeat(300)), limits.clone());
+    assert_eq!(
+      interpreter.execute(),
+      Err(String::from("Memory limit of 1000 bytes exceeded, variables hold 1200 bytes"))
+    );
+
+    let mut interpreter = execute_with(
+      &format!(
+        "let deep(let n) {{ let s = \"{}\"; if (n == 0) {{ return 0; }} return 1 + deep(n - 1); }}
+        let depth = deep(100);",
+        "x".repeat(100)
+      ),
+      limits,
+    );
+    assert_eq!(
+      interpreter.execute(),
+      Err(String::from("Memory limit of 1000 bytes exceeded, variables hold 1100 bytes"))
+    );
+  }
+
+  #[test]
+  fn output_is_checked_before_it_is_written() {
+    let limits = Limits {
+      output: Some(4),
+      ..Limits::default()
+    };
+    assert_eq!(
+      execute_with("print(\"{}\", 1234); print(\"5\");", limits).execute(),
+      Err(String::from("Output limit of 4 bytes exceeded"))
+    );
+  }
+
+  #[test]
+  fn interrupt_stops_a_running_script() {
+    let mut interpreter = execute_with("while (true) { }", Limits::default());
+    let interrupt = interpreter.interrupt_handle();
+    std::thread::spawn(move || {
+      std::thread::sleep(std::time::Duration::from_millis(50));
+      interrupt.interrupt();
+    });
+    assert_eq!(interpreter.execute(), Err(String::from("Execution interrupted")));
+  }
+
+  #[test]
+  fn time_limit_interrupts_blocking_natives() {
+    let limits = Limits {
+      time: Some(std::time::Duration::from_millis(50)),
+      ..Limits::default()
+    };
+    let mut interpreter = execute_with("sleep(60000);", limits);
+    interpreter.append_module(time::Time);
+    let started = std::time::Instant::now();
+    assert_eq!(interpreter.execute(), Err(String::from("Time limit of 50ms exceeded")));
+    assert!(started.elapsed() < std::time::Duration::from_secs(10));
+  }
+
+  #[test]
+  fn exit_ends_the_script_with_its_code() {
+    let mut interpreter =
+      execute_with("let before = 1; exit(3); let after = 2;", Limits::default());
+    interpreter.append_module(os::Os::new(Vec::new()));
+    assert_eq!(interpreter.execute(), Ok(()));
+    assert_eq!(interpreter.exit_code(), Some(3));
+    assert!(!interpreter.environments[0].vars.contains_key("after"));
+  }
+
+  #[test]
+  fn natives_can_be_replaced() {
+    let mut interpreter = execute_with("exit(1);", Limits::default());
+    interpreter.append_module(os::Os::new(Vec::new()));
+    interpreter.replace_native("exit", Box::new(|_| Some(Token::Error(String::from("denied")))));
+    assert_eq!(interpreter.execute(), Err(String::from("Uncaught error: denied")));
+    assert_eq!(interpreter.exit_code(), None);
+  }
+
+  #[test]
+  fn array_literals_evaluate_their_items() {
+    let scope = run("let x = 2; let a = [1, [x, x + 1], \"s\", null];");
+    assert_var(
+      &scope,
+      "a",
+      Some(Token::Array(vec![
+        Some(Token::Integer(1)),
+        Some(Token::Array(vec![Some(Token::Integer(2)), Some(Token::Integer(3))])),
+        Some(Token::String(String::from("s"))),
+        Some(Token::Null),
+      ])),
+    );
+  }
+
+  #[test]
+  fn discarded_call_errors_stop_the_script() {
+    let mut interpreter = Interpreter::new(String::from("let x = 1; freeze(1, 2); x = 2;"));
+    assert_eq!(
+      interpreter.execute(),
+      Err(String::from("Uncaught error: Expected a single argument for freeze"))
+    );
+    assert_var(&interpreter.environments[0], "x", Some(Token::Integer(1)));
+
+    // Kept errors are values like any other
+    let mut interpreter = Interpreter::new(String::from("let e = freeze(1, 2);"));
+    assert_eq!(interpreter.execute(), Ok(()));
+  }
+
+  #[test]
+  fn null_coalescing_and_optional_chaining() {
+    let scope = run(
+      "let n = null; let m = { a: { b: 3 } };
+      let a = n ?? 5; let b = n?.b ?? 7; let c = m?.a?.b; let d = n?.b?.c; let e = 0 ?? 1;
+      let f = n??-1; let g = n ?? n ?? \"last\";",
+    );
+    assert_var(&scope, "a", Some(Token::Integer(5)));
+    assert_var(&scope, "b", Some(Token::Integer(7)));
+    assert_var(&scope, "c", Some(Token::Integer(3)));
+    assert_var(&scope, "d", Some(Token::Null));
+    assert_var(&scope, "e", Some(Token::Integer(0)));
+    assert_var(&scope, "f", Some(Token::Integer(-1)));
+    assert_var(&scope, "g", Some(Token::String(String::from("last"))));
+  }
+
+  #[test]
+  fn question_marks_lex_apart_from_other_operators() {
+    let scope = run("let x = true; let y = x ?-1 : 2; let z = x?1:2; let n = 2; let w = -n * 3;");
+    assert_var(&scope, "y", Some(Token::Integer(-1)));
+    assert_var(&scope, "z", Some(Token::Integer(1)));
+    assert_var(&scope, "w", Some(Token::Integer(-6)));
+  }
+
+  #[test]
+  fn conditional_expressions_only_evaluate_one_branch() {
+    let scope = run(
+      "let a = false ? 1 : true ? 2 : 3; let b = 1 + 2 > 2 ? \"big\" : \"small\";
+      let c = (true ? [1, 2] : [3])[1];
+      let calls = 0; let bump() { calls += 1; return calls; }
+      let d = false ? bump() : 10; let e = true ? 20 : bump();
+      let countdown(let n) { return n > 0 ? countdown(n - 1) : \"done\"; } let f = countdown(3);",
+    );
+    assert_var(&scope, "a", Some(Token::Integer(2)));
+    assert_var(&scope, "b", Some(Token::String(String::from("big"))));
+    assert_var(&scope, "c", Some(Token::Integer(2)));
+    assert_var(&scope, "d", Some(Token::Integer(10)));
+    assert_var(&scope, "e", Some(Token::Integer(20)));
+    assert_var(&scope, "calls", Some(Token::Integer(0)));
+    assert_var(&scope, "f", Some(Token::String(String::from("done"))));
+  }
+
+  #[test]
+  #[should_panic(expected = "Expected ':'")]
+  fn conditional_expressions_need_both_branches() {
+    run("let x = true ? 1;");
+  }
+
+  #[test]
+  fn match_patterns() {
+    let scope = run(
+      "struct Point { let x; let y; }
+      let describe(let v) {
+        return match (v) {
+          1 => \"one\",
+          \"a\" | \"b\" => \"letter\",
+          -5 => \"minus five\",
+          2..5 => \"low\",
+          10..=20 => \"teen\",
+          [x, [y]] => x + y,
+          [x, y] if x == y => \"pair\",
+          Point { x, y: 0 } => x * 10,
+          { name } => name,
+          _ => \"other\"
+        };
+      }
+      let r = [describe(1), describe(\"b\"), describe(-5), describe(4), describe(5), describe(20),
+        describe([3, 3]), describe([1, [2]]), describe(Point(7, 0)), describe(Point(1, 2)),
+        describe({ name: \"map\" }), describe(null)];",
+    );
+    let expected = [
+      "one", "letter", "minus five", "low", "other", "teen", "pair", "3", "70", "other", "map", "other",
+    ];
+    match scope.get(&String::from("r")) {
+      Some(Token::Array(items)) => {
+        let items: Vec<String> = items
+          .iter()
+          .map(|item| item.as_ref().unwrap().to_string())
+          .collect();
+        assert_eq!(items, expected);
+      }
+      value => panic!("r is {}", get_type(&value)),
+    }
+  }
+
+  #[test]
+  fn match_arms_with_blocks_and_no_match() {
+    let scope = run(
+      "let total = 0;
+      for n in 0..6 { match (n % 3) { 0 => { total += 10; }, 1 => total += 1, _ => {} } }
+      let missing = match (3) { 1 => \"a\" };
+      let bound = match ([1, 2, 3]) { [a, b] => 0, [a, b, c] => a + b + c };",
+    );
+    assert_var(&scope, "total", Some(Token::Integer(22)));
+    assert_var(&scope, "missing", Some(Token::Null));
+    assert_var(&scope, "bound", Some(Token::Integer(6)));
+  }
+
+  #[test]
+  #[should_panic(expected = "Expected '=>' after match pattern")]
+  fn match_arms_need_an_arrow() {
+    run("let r = match (1) { 1 -> 2 };");
+  }
+
+  #[test]
+  fn enum_values_and_dispatch() {
+    let scope = run(
+      "enum Shape { Circle(r), Rect(w, h), Empty }
+      let area(let s) { return match (s) { Circle(r) => 3 * r * r, Shape.Rect(w, h) => w * h, Empty => 0 }; }
+      let areas = [area(Shape.Circle(2)), area(Shape.Rect(2, 5)), area(Shape.Empty)];
+      let first = match (Shape.Circle(1)) { Empty => \"empty\", Shape.Circle(r) => \"circle\" };
+      let rect = Shape.Rect(3, 4); let w = rect.w;
+      let checks = [rect is Shape, rect == Shape.Rect(3, 4), rect == Shape.Rect(4, 3), 1 is Shape];",
+    );
+    assert_var(
+      &scope,
+      "areas",
+      Some(Token::Array(vec![
+        Some(Token::Integer(12)),
+        Some(Token::Integer(10)),
+        Some(Token::Integer(0)),
+      ])),
+    );
+    assert_var(&scope, "first", Some(Token::String(String::from("circle"))));
+    assert_var(&scope, "w", Some(Token::Integer(3)));
+    let rect = scope.get(&String::from("rect"));
+    assert_eq!(get_type(&rect), "Shape");
+    assert_eq!(rect.unwrap().to_string(), "Shape.Rect(3, 4)");
+    assert_var(
+      &scope,
+      "checks",
+      Some(Token::Array(vec![
+        Some(Token::Boolean(true)),
+        Some(Token::Boolean(true)),
+        Some(Token::Boolean(false)),
+        Some(Token::Boolean(false)),
+      ])),
+    );
+  }
+
+  #[test]
+  #[should_panic(expected = "Variant Empty belongs to more than one enum")]
+  fn ambiguous_bare_variants_are_rejected() {
+    run("enum A { Empty } enum B { Empty } let r = match (A.Empty) { Empty => 1, _ => 2 };");
+  }
+
+  #[test]
+  fn map_literals_and_field_reads() {
+    let scope = run(
+      "let k = 2; let m = { a: 1, \"two words\": k, nested: { b: k + 1 } };
+      let a = m.a; let b = m.nested.b; let missing = m.c;",
+    );
+    assert_var(&scope, "a", Some(Token::Integer(1)));
+    assert_var(&scope, "b", Some(Token::Integer(3)));
+    assert_var(&scope, "missing", Some(Token::Null));
+    match scope.get(&String::from("m")) {
+      Some(Token::Map(entries)) => {
+        assert!(values_equal(&entries["two words"], &Some(Token::Integer(2))))
+      }
+      value => panic!("m is {}", get_type(&value)),
+    }
+  }
+}
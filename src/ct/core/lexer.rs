@@ -27,6 +27,9 @@ impl Lexer {
       return Lexer::null();
     }
     self.read_while(Lexer::whitespace);
+    if self.input.eof() {
+      return Lexer::null();
+    }
 
     let c = self.input.peek();
 
@@ -34,12 +37,36 @@ impl Lexer {
       Some(Token::String(self.read_string()))
     } else if Lexer::id_start(c) {
       Some(Token::Identifier(self.read_while(Lexer::id)))
+    } else if c == '.' && self.input.peek_next() == Some('.') {
+      self.input.next();
+      self.input.next();
+      if !self.input.eof() && self.input.peek() == '=' {
+        self.input.next();
+        Some(Token::Operator(String::from("..=")))
+      } else if !self.input.eof() && self.input.peek() == '.' {
+        self.input.next();
+        Some(Token::Operator(String::from("...")))
+      } else {
+        Some(Token::Operator(String::from("..")))
+      }
     } else if Lexer::punc(c) {
       Some(Token::Punctuation(self.input.next()))
+    } else if c == '?' {
+      // `?`, `?.` and `??` are fixed tokens, so `x ?-1 : 2` does not lex as a `?-` operator
+      let mut op = self.input.next().to_string();
+      if !self.input.eof() && (self.input.peek() == '.' || self.input.peek() == '?') {
+        op.push(self.input.next());
+      }
+      Some(Token::Operator(op))
     } else if Lexer::oper(c) {
       Some(Token::Operator(self.read_while(Lexer::oper)))
     } else if Lexer::digit(c) {
-      let num = self.read_while(Lexer::number);
+      let mut num = self.read_while(Lexer::digit);
+      // A dot only continues the number when it is not the start of a `..` range
+      if !self.input.eof() && self.input.peek() == '.' && self.input.peek_next() != Some('.') {
+        num.push(self.input.next());
+        num.push_str(&self.read_while(Lexer::digit));
+      }
       if num.contains('.') {
         Some(Token::Float(match num.parse::<f64>() {
           Ok(value) => value,
@@ -49,7 +76,7 @@ impl Lexer {
           }
         }))
       } else {
-        Some(Token::Integer(match num.parse::<i32>() {
+        Some(Token::Integer(match num.parse::<i64>() {
           Ok(value) => value,
           Err(_) => {
             self.error("Expected an integer");
@@ -77,6 +104,10 @@ impl Lexer {
     Lexer::null()
   }
 
+  pub fn warn(&self, s: &str) {
+    self.input.warn(s);
+  }
+
   pub fn null() -> CtToken {
     None
   }
@@ -130,10 +161,6 @@ impl Lexer {
     String::from(" \t\r\n").contains(c)
   }
 
-  fn number(c: char) -> bool {
-    Lexer::digit(c) || c == '.'
-  }
-
   fn digit(c: char) -> bool {
     c >= '0' && c <= '9'
   }
@@ -147,10 +174,10 @@ impl Lexer {
   }
 
   fn punc(c: char) -> bool {
-    String::from("(){}[];,.").contains(c)
+    String::from("(){}[];,.:").contains(c)
   }
 
   fn oper(c: char) -> bool {
-    String::from("&|%*/+-=<>").contains(c)
+    String::from("&|%*/+-=<>!").contains(c)
   }
 }
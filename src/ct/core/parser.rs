@@ -1,9 +1,15 @@
 use ct::core::lexer::Lexer;
 use ct::syntax::std::*;
 
+use std::collections::{BTreeMap, HashMap};
+
 pub struct Parser {
   lexer: Lexer,
   req_sc: bool,
+  // Variant names of every enum declared so far, used to check `match` exhaustiveness
+  enums: HashMap<String, Vec<String>>,
+  // One entry per function body being parsed, set once that body yields
+  generators: Vec<bool>,
 }
 
 impl Parser {
@@ -11,18 +17,34 @@ impl Parser {
     Parser {
       lexer: Lexer::new(code),
       req_sc: true,
+      enums: HashMap::new(),
+      generators: Vec::new(),
     }
   }
 
   pub fn next(&mut self) -> CtToken {
-    let t = self.parse_primary();
+    let mut t = self.parse_primary();
     if t.is_none() {
       return Lexer::null();
     }
 
+    // `return a, b` returns an array, ready to be destructured by the caller
+    if let (Some(Token::Return(value)), true) = (&mut t, self.equals(',')) {
+      let mut values = vec![value.take()];
+      while self.equals(',') {
+        self.lexer.next();
+        values.push(self.parse_primary());
+      }
+      **value = Some(Token::Array(values));
+    }
+
     if self.req_sc {
       self.skip(';');
     } else {
+      // Block statements used as expressions, `let x = match (y) { ... };`, may still end in a semicolon
+      if self.equals(';') {
+        self.lexer.next();
+      }
       self.req_sc = true;
     }
 
@@ -59,11 +81,41 @@ impl Parser {
       }
     }
 
-    match t {
+    let value = match t {
       Token::Identifier(id) => self.parse_identifier(id),
       Token::Integer(_) | Token::Float(_) | Token::String(_) => self.lexer.next(),
-      Token::Punctuation('(') => self.parse_parenthesis(),
+      // A parenthesised expression is a value of its own, `(a + b).len()`
+      Token::Punctuation('(') => {
+        let value = self.parse_parenthesis();
+        return self.parse_postfix(value);
+      }
+      Token::Punctuation('[') => self.parse_array(),
+      Token::Punctuation('{') => self.parse_map(),
+      // Unary minus binds tighter than any binary operator, `-a * b` is `(-a) * b`
+      Token::Operator(ref op) if op == "-" => {
+        self.lexer.next();
+        return match self.parse_generic() {
+          Some(Token::Integer(value)) => Some(Token::Integer(-value)),
+          Some(Token::Float(value)) => Some(Token::Float(-value)),
+          None => self.error("Expected an operand after '-'"),
+          operand => Some(Token::Binary {
+            operator: String::from("-"),
+            left: Box::new(Some(Token::Integer(0))),
+            right: Box::new(operand),
+          }),
+        };
+      }
       _ => self.error(&format!("Unable to parse: {}", t)),
+    };
+
+    // Statements such as `if` or `while` blocks are never followed by member access
+    match value {
+      Some(Token::VariableCall(_))
+      | Some(Token::FunctionCall { .. })
+      | Some(Token::String(_))
+      | Some(Token::Array(_))
+      | Some(Token::Map(_)) => self.parse_postfix(value),
+      value => value,
     }
   }
 
@@ -83,6 +135,25 @@ impl Parser {
 
       let operator = operator.unwrap();
       self.lexer.next(); // Skip operator
+
+      // `cond ? a : b`, the else branch is parsed at the same precedence so conditionals nest to the right
+      if operator == "?" {
+        let then = self.parse_primary();
+        self.skip(':');
+        let otherwise = self.parse_generic();
+        let otherwise = self.parse_binary(prec, otherwise);
+        if then.is_none() || otherwise.is_none() {
+          return self.error("Invalid branch in conditional expression");
+        }
+
+        left = Some(Token::Conditional {
+          condition: Box::new(left),
+          then: Box::new(then),
+          otherwise: Box::new(otherwise),
+        });
+        continue;
+      }
+
       let mut right = self.parse_generic();
 
       let (new_prec, _) = self.get_precedence();
@@ -106,36 +177,47 @@ impl Parser {
 
     match &id[..] {
       types::TRUE => return Some(Token::Boolean(true)),
+      types::NULL => return Some(Token::Null),
       types::FALSE => return Some(Token::Boolean(false)),
       types::RETURN => return Some(Token::Return(Box::new(self.parse_primary()))),
+      types::YIELD => {
+        match self.generators.last_mut() {
+          Some(yields) => *yields = true,
+          None => return self.error("yield can only be used inside a function"),
+        }
+        return Some(Token::Yield(Box::new(self.parse_primary())));
+      }
       types::BREAK => return Some(Token::Break),
       types::CONTINUE => return Some(Token::Continue),
       _ => (),
     };
 
     if !self.equals('(') {
-      return if self.equals('.') {
-        self.skip('.'); // Skip punctuation
-        let t = self.lexer.next().unwrap();
-        let args = if self.equals('(') {
-          Some(self.parse_arguments())
-        } else {
-          None
-        };
-
-        match t {
-          Token::Identifier(s) => Some(Token::StructureCall {
-            name: id,
-            attribute: s.clone(),
-            args,
-          }),
-          _ => self.error(&format!(
-            "Expected structure attribute to be an identifier, but received: {}",
-            t
-          )),
-        }
+      return if (id == types::DECLARE || id == types::CONST)
+        && (self.equals('[') || self.equals('{'))
+      {
+        Some(Token::Destructure {
+          pattern: Box::new(self.parse_pattern()),
+          constant: id == types::CONST,
+        })
       } else if id == types::DECLARE {
         self.parse_function()
+      } else if id == types::CONST {
+        match self.lexer.next() {
+          Some(Token::Identifier(name)) => Some(Token::Constant(name)),
+          t => self.error(&format!(
+            "Expected constant name, but received: {}",
+            t.map_or(String::from(types::NULL), |t| t.to_string())
+          )),
+        }
+      } else if id == types::ENUM {
+        self.parse_enum()
+      } else if id == types::FOR {
+        self.parse_for()
+      } else if id == types::STRUCT {
+        self.parse_struct()
+      } else if id == types::INTERFACE {
+        self.parse_interface()
       } else {
         Some(Token::VariableCall(id))
       };
@@ -150,6 +232,12 @@ impl Parser {
     if id == types::WHILE {
       return self.parse_while();
     }
+    if id == types::MATCH {
+      return self.parse_match();
+    }
+    if id == types::DECLARE {
+      return self.parse_lambda();
+    }
     if id == types::STRUCT {
       return self.parse_struct();
     }
@@ -160,6 +248,50 @@ impl Parser {
     });
   }
 
+  // `.field`, `?.field`, `.method(args)` and `[index]`, chained after any value
+  fn parse_postfix(&mut self, mut target: CtToken) -> CtToken {
+    loop {
+      if self.equals('[') {
+        self.skip('[');
+        let index = self.parse_primary();
+        self.skip(']');
+        target = Some(Token::Index {
+          target: Box::new(target),
+          index: Box::new(index),
+        });
+        continue;
+      }
+
+      let optional = self.equals_operator("?.");
+      if !optional && !self.equals('.') {
+        return target;
+      }
+      self.lexer.next(); // Skip punctuation
+
+      let attribute = match self.lexer.next() {
+        Some(Token::Identifier(attribute)) => attribute,
+        t => {
+          return self.error(&format!(
+            "Expected member name to be an identifier, but received: {}",
+            t.map_or(String::from(types::NULL), |t| t.to_string())
+          ))
+        }
+      };
+      let args = if self.equals('(') {
+        Some(self.parse_arguments())
+      } else {
+        None
+      };
+
+      target = Some(Token::MemberAccess {
+        target: Box::new(target),
+        attribute,
+        args,
+        optional,
+      });
+    }
+  }
+
   fn parse_parenthesis(&mut self) -> CtToken {
     self.skip('(');
     let t = self.parse_primary();
@@ -178,11 +310,20 @@ impl Parser {
 
     let mut args = Vec::new();
     while !self.equals(')') {
-      let token = self.parse_primary();
+      let mut token = self.parse_primary();
       if token.is_none() {
         self.error(&format!("Invalid parameter in funcion arguments"));
       }
 
+      // A bare name followed by a colon is a named argument, `f(b: 3)`
+      if let (Some(Token::VariableCall(name)), true) = (&token, self.equals(':')) {
+        self.skip(':');
+        token = Some(Token::NamedArgument {
+          name: name.clone(),
+          value: Box::new(self.parse_primary()),
+        });
+      }
+
       args.push(token);
       if !self.equals(')') {
         self.skip(',');
@@ -195,6 +336,63 @@ impl Parser {
     args
   }
 
+  fn parse_array(&mut self) -> CtToken {
+    self.skip('[');
+
+    let mut items = Vec::new();
+    while !self.equals(']') {
+      let token = self.parse_primary();
+      if token.is_none() {
+        return self.error("Invalid element in array literal");
+      }
+
+      items.push(token);
+      if !self.equals(']') {
+        self.skip(',');
+      }
+    }
+
+    self.skip(']');
+    self.req_sc = true;
+
+    Some(Token::Array(items))
+  }
+
+  // Keys are either identifiers or strings: `{ name: "a", "two words": 2 }`
+  fn parse_map(&mut self) -> CtToken {
+    self.skip('{');
+
+    let mut entries = BTreeMap::new();
+    while !self.equals('}') {
+      let key = match self.lexer.next() {
+        Some(Token::Identifier(key)) | Some(Token::String(key)) => key,
+        Some(t) => {
+          return self.error(&format!(
+            "Expected map key to be an identifier or string, but received: {}",
+            t
+          ))
+        }
+        None => return Lexer::null(),
+      };
+
+      self.skip(':');
+      let value = self.parse_primary();
+      if value.is_none() {
+        return self.error(&format!("Invalid value for map key: {}", key));
+      }
+
+      entries.insert(key, value);
+      if !self.equals('}') {
+        self.skip(',');
+      }
+    }
+
+    self.skip('}');
+    self.req_sc = true;
+
+    Some(Token::Map(entries))
+  }
+
   fn parse_function_body(&mut self) -> Vec<CtToken> {
     self.skip('{');
     let mut body = Vec::new();
@@ -215,6 +413,15 @@ impl Parser {
     match self.lexer.next() {
       Some(t) => match t {
         Token::Identifier(id) => name = id,
+        Token::Operator(ref op) if op == "..." => {
+          return match self.lexer.next() {
+            Some(Token::Identifier(id)) => Some(Token::Variadic(id)),
+            t => self.error(&format!(
+              "Expected rest parameter name, found: {}",
+              t.map_or(String::from(types::NULL), |t| t.to_string())
+            )),
+          };
+        }
         _ => {
           return self.error(&format!("Expected variable name, found: {}", t));
         }
@@ -245,6 +452,7 @@ impl Parser {
             return_type: types::ANY,
           });
         }
+        Token::Variadic(name) => return Some(Token::Variadic(name)),
         Token::FunctionHeader(fh) => header = fh,
         _ => {
           return self.error("Unknown function header");
@@ -255,10 +463,79 @@ impl Parser {
       }
     }
 
-    Some(Token::Function(Function {
-      header,
-      body: self.parse_function_body(),
-    }))
+    self.check_parameters(&header);
+
+    let (header, body) = self.parse_generator_body(header);
+    Some(Token::Function(Function { header, body }))
+  }
+
+  // A function whose body yields is a generator, calling it returns an iterator over what it yields
+  fn parse_generator_body(&mut self, mut header: FunctionHeader) -> (FunctionHeader, Vec<CtToken>) {
+    self.generators.push(false);
+    let body = self.parse_function_body();
+    if self.generators.pop() == Some(true) {
+      header.return_type = types::GENERATOR;
+    }
+
+    (header, body)
+  }
+
+  fn check_parameters(&self, header: &FunctionHeader) {
+    let rest = header
+      .args
+      .iter()
+      .position(|arg| matches!(arg, Some(Token::Variadic(_))));
+    if rest.is_some_and(|index| index + 1 != header.args.len()) {
+      self.error(&format!(
+        "The rest parameter of {} must be the last parameter",
+        header.name
+      ));
+    }
+  }
+
+  // Lambda parameters may leave out `let`: `let (a, b = 2) { ... }`
+  fn parse_lambda(&mut self) -> CtToken {
+    let parameter = |name: String| {
+      Some(Token::Variable {
+        name,
+        return_type: types::ANY,
+      })
+    };
+    let args = self
+      .parse_arguments()
+      .into_iter()
+      .map(|arg| match arg {
+        Some(Token::VariableCall(name)) => parameter(name),
+        Some(Token::Binary {
+          operator,
+          left,
+          right,
+        }) => match *left {
+          Some(Token::VariableCall(name)) if operator == "=" => Some(Token::Binary {
+            operator,
+            left: Box::new(parameter(name)),
+            right,
+          }),
+          left => Some(Token::Binary {
+            operator,
+            left: Box::new(left),
+            right,
+          }),
+        },
+        arg => arg,
+      })
+      .collect();
+
+    let header = FunctionHeader {
+      name: String::from(types::ANONYMOUS),
+      args,
+      return_type: types::ANY,
+    };
+
+    self.check_parameters(&header);
+
+    let (header, body) = self.parse_generator_body(header);
+    Some(Token::Lambda(Function { header, body }))
   }
 
   fn parse_if(&mut self) -> CtToken {
@@ -327,8 +604,34 @@ impl Parser {
     Some(Token::If(ifs))
   }
 
+  // `for (x in iterable) { ... }`, the parentheses are optional and the binding may be any match pattern
   fn parse_for(&mut self) -> CtToken {
-    Lexer::null()
+    let parenthesised = self.equals('(');
+    if parenthesised {
+      self.skip('(');
+    }
+
+    let pattern = self.parse_pattern();
+    match self.lexer.next() {
+      Some(Token::Identifier(ref id)) if id == types::IN => (),
+      t => {
+        return self.error(&format!(
+          "Expected 'in' after for loop binding, but received: {}",
+          t.map_or(String::from(types::NULL), |t| t.to_string())
+        ))
+      }
+    }
+
+    let iterable = self.parse_primary();
+    if parenthesised {
+      self.skip(')');
+    }
+
+    Some(Token::For {
+      pattern: Box::new(pattern),
+      iterable: Box::new(iterable),
+      body: self.parse_function_body(),
+    })
   }
 
   fn parse_while(&mut self) -> CtToken {
@@ -342,24 +645,442 @@ impl Parser {
     })
   }
 
-  fn parse_struct(&mut self) -> CtToken {
-    let raw = self.lexer.next();
-    if let Some(s) = raw.as_ref() {
-      if let Token::String(name) = s {
-        self.skip('{');
+  // `match (value) { pattern if guard => body, ... }`, where a body is a block or a single expression
+  fn parse_match(&mut self) -> CtToken {
+    self.skip('(');
+    let value = self.parse_primary();
+    self.skip(')');
+    self.skip('{');
+
+    let mut arms = Vec::new();
+    while !self.equals('}') {
+      let pattern = self.parse_pattern();
+
+      let guard = match self.lexer.peek() {
+        Some(Token::Identifier(ref id)) if id == types::IF => {
+          self.lexer.next();
+          self.parse_primary()
+        }
+        _ => None,
+      };
 
-        self.skip('}');
+      if !self.equals_operator("=>") {
+        return self.error("Expected '=>' after match pattern");
       }
+      self.lexer.next();
+
+      let body = if self.equals('{') {
+        self.parse_function_body()
+      } else {
+        vec![self.parse_primary()]
+      };
+
+      arms.push(MatchArm {
+        pattern,
+        guard: Box::new(guard),
+        body,
+      });
+      if self.equals(',') {
+        self.lexer.next();
+      }
+    }
+
+    self.skip('}');
+    self.req_sc = false;
+
+    if !self.exhaustive(&arms) {
+      self
+        .lexer
+        .warn("Match is not exhaustive, values without a matching arm evaluate to null; add a '_' arm");
     }
 
-    self.error(&format!(
-      "Expected a string after 'struct' keyword, but received {}",
-      if raw.is_some() {
-        raw.unwrap()
+    Some(Token::Match {
+      value: Box::new(value),
+      arms,
+    })
+  }
+
+  // Only a catch-all arm, both booleans or every variant of an enum prove exhaustiveness, guarded arms never count
+  fn exhaustive(&self, arms: &[MatchArm]) -> bool {
+    let mut patterns = Vec::new();
+    for arm in arms.iter().filter(|arm| arm.guard.is_none()) {
+      match &arm.pattern {
+        Pattern::Alternative(alternatives) => patterns.extend(alternatives.iter()),
+        pattern => patterns.push(pattern),
+      }
+    }
+
+    let irrefutable = |p: &Pattern| matches!(p, Pattern::Wildcard | Pattern::Binding(_));
+    let covers = |b: bool| {
+      patterns
+        .iter()
+        .any(|p| matches!(p, Pattern::Literal(Token::Boolean(value)) if *value == b))
+    };
+
+    let mut covered: HashMap<&String, Vec<&String>> = HashMap::new();
+    for pattern in &patterns {
+      if let Pattern::Variant {
+        name,
+        variant,
+        fields,
+      } = pattern
+      {
+        let enum_name = match name {
+          Some(name) => self.enums.get_key_value(name).map(|(name, _)| name),
+          None => self
+            .enums
+            .iter()
+            .find(|(_, variants)| variants.contains(variant))
+            .map(|(name, _)| name),
+        };
+
+        let complete = fields
+          .as_ref()
+          .is_none_or(|fields| fields.iter().all(irrefutable));
+        if let (Some(enum_name), true) = (enum_name, complete) {
+          covered.entry(enum_name).or_default().push(variant);
+        }
+      }
+    }
+
+    patterns.iter().any(|p| irrefutable(p))
+      || (covers(true) && covers(false))
+      || covered
+        .iter()
+        .any(|(name, variants)| self.enums[*name].iter().all(|v| variants.contains(&v)))
+  }
+
+  fn parse_pattern(&mut self) -> Pattern {
+    let mut alternatives = vec![self.parse_single_pattern()];
+    while self.equals_operator("|") {
+      self.lexer.next();
+      alternatives.push(self.parse_single_pattern());
+    }
+
+    if alternatives.len() == 1 {
+      alternatives.pop().unwrap()
+    } else {
+      Pattern::Alternative(alternatives)
+    }
+  }
+
+  fn parse_single_pattern(&mut self) -> Pattern {
+    match self.lexer.peek() {
+      Some(Token::Punctuation('[')) => {
+        self.skip('[');
+        let mut items = Vec::new();
+        while !self.equals(']') {
+          items.push(self.parse_pattern());
+          if !self.equals(']') {
+            self.skip(',');
+          }
+        }
+        self.skip(']');
+
+        Pattern::Array(items)
+      }
+      Some(Token::Punctuation('{')) => self.parse_structure_pattern(None),
+      Some(Token::Identifier(id)) => {
+        self.lexer.next();
+        match &id[..] {
+          "_" => Pattern::Wildcard,
+          types::TRUE => Pattern::Literal(Token::Boolean(true)),
+          types::FALSE => Pattern::Literal(Token::Boolean(false)),
+          types::NULL => Pattern::Literal(Token::Null),
+          _ if self.equals('{') => self.parse_structure_pattern(Some(id)),
+          _ if self.equals('.') => {
+            self.skip('.');
+            match self.lexer.next() {
+              Some(Token::Identifier(variant)) => self.parse_variant_pattern(Some(id), variant),
+              t => {
+                self.error(&format!(
+                  "Expected enum variant in pattern, but received: {}",
+                  t.map_or(String::from(types::NULL), |t| t.to_string())
+                ));
+                Pattern::Wildcard
+              }
+            }
+          }
+          _ if self.equals('(') => self.parse_variant_pattern(None, id),
+          // A bare variant name is the variant rather than a binding that would catch every value
+          _ => {
+            let mut owners = self.enums.iter().filter(|(_, variants)| variants.contains(&id));
+            match (owners.next(), owners.next()) {
+              (Some((name, _)), None) => Pattern::Variant {
+                name: Some(name.clone()),
+                variant: id,
+                fields: None,
+              },
+              (Some(_), Some(_)) => {
+                self.error(&format!(
+                  "Variant {} belongs to more than one enum, name the enum in the pattern",
+                  id
+                ));
+                Pattern::Wildcard
+              }
+              _ => Pattern::Binding(id),
+            }
+          }
+        }
+      }
+      _ => {
+        let start = self.parse_pattern_literal();
+        let inclusive = self.equals_operator("..=");
+        if inclusive || self.equals_operator("..") {
+          self.lexer.next();
+          let end = self.parse_pattern_literal();
+          Pattern::Range {
+            start,
+            end,
+            inclusive,
+          }
+        } else {
+          Pattern::Literal(start)
+        }
+      }
+    }
+  }
+
+  fn parse_variant_pattern(&mut self, name: Option<String>, variant: String) -> Pattern {
+    let fields = if self.equals('(') {
+      self.skip('(');
+      let mut fields = Vec::new();
+      while !self.equals(')') {
+        fields.push(self.parse_pattern());
+        if !self.equals(')') {
+          self.skip(',');
+        }
+      }
+      self.skip(')');
+
+      Some(fields)
+    } else {
+      None
+    };
+
+    Pattern::Variant {
+      name,
+      variant,
+      fields,
+    }
+  }
+
+  fn parse_structure_pattern(&mut self, name: Option<String>) -> Pattern {
+    self.skip('{');
+
+    let mut fields = Vec::new();
+    while !self.equals('}') {
+      let field = match self.lexer.next() {
+        Some(Token::Identifier(field)) | Some(Token::String(field)) => field,
+        t => {
+          self.error(&format!(
+            "Expected field name in pattern, but received: {}",
+            t.map_or(String::from(types::NULL), |t| t.to_string())
+          ));
+          String::new()
+        }
+      };
+
+      // `{ x }` is shorthand for `{ x: x }`
+      let pattern = if self.equals(':') {
+        self.skip(':');
+        self.parse_pattern()
       } else {
-        Token::String(String::from("None"))
+        Pattern::Binding(field.clone())
+      };
+
+      fields.push((field, pattern));
+      if !self.equals('}') {
+        self.skip(',');
       }
-    ))
+    }
+    self.skip('}');
+
+    Pattern::Structure { name, fields }
+  }
+
+  fn parse_pattern_literal(&mut self) -> Token {
+    let negative = self.equals_operator("-");
+    if negative {
+      self.lexer.next();
+    }
+
+    match self.lexer.next() {
+      Some(Token::Integer(value)) => Token::Integer(if negative { -value } else { value }),
+      Some(Token::Float(value)) => Token::Float(if negative { -value } else { value }),
+      Some(Token::String(value)) if !negative => Token::String(value),
+      t => {
+        self.error(&format!(
+          "Invalid match pattern: {}",
+          t.map_or(String::from(types::NULL), |t| t.to_string())
+        ));
+        Token::Null
+      }
+    }
+  }
+
+  // `enum Shape { Circle(r), Rect(w, h), Empty }`
+  fn parse_enum(&mut self) -> CtToken {
+    let name = match self.lexer.next() {
+      Some(Token::Identifier(name)) => name,
+      t => {
+        return self.error(&format!(
+          "Expected enum name, but received: {}",
+          t.map_or(String::from(types::NULL), |t| t.to_string())
+        ))
+      }
+    };
+    self.skip('{');
+
+    let mut variants = Vec::new();
+    while !self.equals('}') {
+      let variant = match self.lexer.next() {
+        Some(Token::Identifier(variant)) => variant,
+        t => {
+          return self.error(&format!(
+            "Expected variant name in enum {}, but received: {}",
+            name,
+            t.map_or(String::from(types::NULL), |t| t.to_string())
+          ))
+        }
+      };
+
+      let mut fields = Vec::new();
+      if self.equals('(') {
+        self.skip('(');
+        while !self.equals(')') {
+          match self.lexer.next() {
+            Some(Token::Identifier(field)) => fields.push(field),
+            t => {
+              return self.error(&format!(
+                "Expected field name in variant {}, but received: {}",
+                variant,
+                t.map_or(String::from(types::NULL), |t| t.to_string())
+              ))
+            }
+          }
+          if !self.equals(')') {
+            self.skip(',');
+          }
+        }
+        self.skip(')');
+      }
+
+      variants.push((variant, fields));
+      if !self.equals('}') {
+        self.skip(',');
+      }
+    }
+
+    self.skip('}');
+    self.req_sc = false;
+
+    self.enums.insert(
+      name.clone(),
+      variants.iter().map(|(variant, _)| variant.clone()).collect(),
+    );
+    Some(Token::Enum { name, variants })
+  }
+
+  // Struct and interface names may be written bare or quoted, `struct "Dog" extends "Animal"`
+  fn parse_type_name(&mut self, after: &str) -> String {
+    match self.lexer.next() {
+      Some(Token::Identifier(name)) | Some(Token::String(name)) => name,
+      t => {
+        self.error(&format!(
+          "Expected a name after '{}', but received {}",
+          after,
+          t.map_or(String::from(types::NULL), |t| t.to_string())
+        ));
+        String::new()
+      }
+    }
+  }
+
+  fn equals_keyword(&mut self, keyword: &str) -> bool {
+    matches!(self.lexer.peek(), Some(Token::Identifier(ref id)) if id == keyword)
+  }
+
+  // Fields are declared like variables, methods like functions taking the instance first
+  fn parse_struct(&mut self) -> CtToken {
+    let name = self.parse_type_name(types::STRUCT);
+
+    let parent = if self.equals_keyword(types::EXTENDS) {
+      self.lexer.next();
+      Some(self.parse_type_name(types::EXTENDS))
+    } else {
+      None
+    };
+
+    let mut interfaces = Vec::new();
+    if self.equals_keyword(types::IMPLEMENTS) {
+      self.lexer.next();
+      interfaces.push(self.parse_type_name(types::IMPLEMENTS));
+      while self.equals(',') {
+        self.lexer.next();
+        interfaces.push(self.parse_type_name(types::IMPLEMENTS));
+      }
+    }
+
+    let mut fields = Vec::new();
+    let mut methods = Vec::new();
+    for member in self.parse_function_body() {
+      match member {
+        Some(Token::Function(method)) => methods.push(method),
+        field @ Some(Token::Variable { .. }) => fields.push(field),
+        Some(Token::Binary { ref operator, ref left, .. })
+          if operator == "=" && matches!(**left, Some(Token::Variable { .. })) =>
+        {
+          fields.push(member)
+        }
+        t => {
+          return self.error(&format!(
+            "Expected a field or method in struct {}, but received: {}",
+            name,
+            t.map_or(String::from(types::NULL), |t| t.to_string())
+          ))
+        }
+      }
+    }
+
+    Some(Token::StructDeclaration {
+      name,
+      parent,
+      interfaces,
+      fields,
+      methods,
+    })
+  }
+
+  // Only the method headers are declared, `let speak(let self, let loud);`
+  fn parse_interface(&mut self) -> CtToken {
+    let name = self.parse_type_name(types::INTERFACE);
+    self.skip('{');
+
+    let mut methods = Vec::new();
+    while !self.equals('}') {
+      if !self.equals_keyword(types::DECLARE) {
+        return self.error(&format!("Expected a method declaration in interface {}", name));
+      }
+      self.lexer.next();
+
+      match self.parse_function_header() {
+        Some(Token::FunctionHeader(header)) => methods.push(header),
+        t => {
+          return self.error(&format!(
+            "Expected a method declaration in interface {}, but received: {}",
+            name,
+            t.map_or(String::from(types::NULL), |t| t.to_string())
+          ))
+        }
+      }
+      self.skip(';');
+    }
+
+    self.skip('}');
+    self.req_sc = false;
+
+    Some(Token::InterfaceDeclaration { name, methods })
   }
 
   fn skip(&mut self, c: char) {
@@ -392,6 +1113,13 @@ impl Parser {
     }
   }
 
+  fn equals_operator(&mut self, op: &str) -> bool {
+    match self.lexer.peek() {
+      Some(Token::Operator(value)) => value == op,
+      _ => false,
+    }
+  }
+
   fn get_precedence(&mut self) -> (i8, Option<String>) {
     let bin_op = self.lexer.peek();
     if bin_op.is_none() {
@@ -404,6 +1132,11 @@ impl Parser {
           "=" => 1,
           "+=" => 1,
           "-=" => 1,
+          "*=" => 1,
+          "/=" => 1,
+          "%=" => 1,
+          "?" => 3,
+          "??" => 4,
           "||" => 5,
           "&&" => 6,
           "<" => 10,
@@ -412,6 +1145,8 @@ impl Parser {
           "<=" => 10,
           "==" => 10,
           "!=" => 10,
+          ".." => 15,
+          "..=" => 15,
           "+" => 20,
           "-" => 20,
           "*" => 30,
@@ -421,6 +1156,9 @@ impl Parser {
         },
         Some(value),
       ),
+      Token::Identifier(ref value) if value == types::IS || value == types::INSTANCEOF => {
+        (10, Some(value.clone()))
+      }
       _ => (-1, None),
     }
   }
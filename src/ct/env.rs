@@ -1,16 +1,25 @@
 use ct::syntax::std::*;
+use ct::utils::limits;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
+// One scope per function call and per if, while, for and match block. `let` and `const` declare in
+// the innermost scope, shadowing outer names until the block ends, while assignment updates the
+// nearest scope that declared the name. Only constants cannot be redeclared in their own scope
 #[derive(Clone, Debug)]
 pub struct Environment {
   pub vars: HashMap<String, CtToken>,
+  pub constants: HashSet<String>,
+  // Bytes held by the values in `vars`, which the memory limit adds up over all running scopes
+  size: usize,
 }
 
 impl Environment {
   pub fn new() -> Environment {
     Environment {
       vars: HashMap::new(),
+      constants: HashSet::new(),
+      size: 0,
     }
   }
 
@@ -39,8 +48,24 @@ impl Environment {
     }
   }
 
+  pub fn define_constant(&mut self, name: String, value: CtToken) -> CtToken {
+    self.constants.insert(name.clone());
+    self.define(name, value)
+  }
+
+  pub fn is_constant(&self, name: &String) -> bool {
+    self.constants.contains(name)
+  }
+
+  pub fn size(&self) -> usize {
+    self.size
+  }
+
   pub fn define(&mut self, name: String, value: CtToken) -> CtToken {
-    self.vars.insert(name.clone(), value);
+    self.size = self.size.saturating_add(value.as_ref().map_or(0, limits::size_of));
+    if let Some(old) = self.vars.insert(name.clone(), value) {
+      self.size = self.size.saturating_sub(old.as_ref().map_or(0, limits::size_of));
+    }
     self.get(&name)
   }
 }
@@ -0,0 +1,289 @@
+use ct::modules::filestream;
+use ct::utils::module::Module;
+use ct::utils::predefs::Predefs;
+use ct::syntax::std::*;
+
+use std::collections::BTreeMap;
+
+pub struct Csv;
+
+impl Module for Csv {
+	fn extend(&self, predefs: &mut Predefs) {
+		predefs.insert(String::from("csv_parse"), Box::new(csv_parse));
+		predefs.insert(String::from("csv_write"), Box::new(csv_write));
+		predefs.insert(String::from("csv_read_file"), Box::new(csv_read_file));
+		predefs.insert(String::from("csv_write_file"), Box::new(csv_write_file));
+	}
+}
+
+struct Options {
+	header: bool,
+	delimiter: char,
+}
+
+// Recognised keys: `header` (bool) and `delimiter` (a single character string)
+fn parse_options(options: Option<&CtToken>, header: bool) -> Result<Options, CtToken> {
+	let mut parsed = Options { header, delimiter: ',' };
+
+	let entries = match options {
+		None => return Ok(parsed),
+		Some(Some(Token::Map(entries))) => entries,
+		Some(t) => return Err(Some(Token::Error(format!("Expected CSV options to be a map, but received: {}", match t { Some(t) => t.to_string(), None => String::from(types::NULL) }))))
+	};
+
+	for (key, value) in entries {
+		match (&key[..], value) {
+			("header", Some(Token::Boolean(b))) => parsed.header = *b,
+			("delimiter", Some(Token::String(s))) if s.chars().count() == 1 && s != "\"" && s != "\n" && s != "\r" => parsed.delimiter = s.chars().next().unwrap(),
+			(key, value) => return Err(Some(Token::Error(format!("Invalid CSV option {}: {}", key, match value { Some(t) => t.to_string(), None => String::from(types::NULL) }))))
+		}
+	}
+
+	Ok(parsed)
+}
+
+// csv_parse(text) or csv_parse(text, options); rows are arrays of strings, or maps keyed by the first row when `header` is set
+pub fn csv_parse(args: Vec<CtToken>) -> CtToken {
+	if args.is_empty() || args.len() > 2 || args[0].is_none() { return Some(Token::Error(String::from("Expected CSV text and optional options"))); }
+
+	let options = match parse_options(args.get(1), false) {
+		Ok(options) => options,
+		Err(e) => return e
+	};
+
+	match args[0].as_ref().unwrap() {
+		Token::String(text) => match parse_rows(text, options.delimiter) {
+			Ok(rows) => to_records(rows, options.header),
+			Err(e) => Some(Token::Error(e))
+		},
+		t => Some(Token::Error(format!("Expected CSV text to be of type string, but received: {}", t)))
+	}
+}
+
+pub fn csv_read_file(args: Vec<CtToken>) -> CtToken {
+	if args.is_empty() || args.len() > 2 { return Some(Token::Error(String::from("Expected a file name and optional options"))); }
+
+	let mut args = args;
+	match filestream::read_file(vec![args.remove(0)]) {
+		Some(Token::String(text)) => {
+			args.insert(0, Some(Token::String(text)));
+			csv_parse(args)
+		},
+		other => other
+	}
+}
+
+fn parse_rows(text: &str, delimiter: char) -> Result<Vec<Vec<String>>, String> {
+	let mut rows = Vec::new();
+	let mut row = Vec::new();
+	let mut field = String::new();
+	let mut chars = text.chars().peekable();
+	let mut line = 1;
+
+	// Tracks whether anything was read since the last row ended, so a trailing newline adds no empty row
+	let mut pending = false;
+
+	while let Some(c) = chars.next() {
+		pending = true;
+		match c {
+			'"' if field.is_empty() => {
+				let start = line;
+				loop {
+					match chars.next() {
+						Some('"') => {
+							if chars.peek() == Some(&'"') {
+								chars.next();
+								field.push('"');
+							} else {
+								break;
+							}
+						},
+						Some(c) => {
+							if c == '\n' { line += 1; }
+							field.push(c);
+						},
+						None => return Err(format!("Unterminated quoted field starting on line {}", start))
+					}
+				}
+
+				match chars.peek() {
+					Some(&c) if c == delimiter || c == '\n' || c == '\r' => (),
+					None => (),
+					Some(&c) => return Err(format!("Unexpected '{}' after quoted field on line {}", c, line))
+				}
+			},
+			c if c == delimiter => row.push(field.split_off(0)),
+			'\r' if chars.peek() == Some(&'\n') => (),
+			'\n' | '\r' => {
+				row.push(field.split_off(0));
+				rows.push(row.split_off(0));
+				line += 1;
+				pending = false;
+			},
+			c => field.push(c),
+		}
+	}
+
+	if pending {
+		row.push(field);
+		rows.push(row);
+	}
+
+	Ok(rows)
+}
+
+fn to_records(rows: Vec<Vec<String>>, header: bool) -> CtToken {
+	let string = |s: String| Some(Token::String(s));
+
+	if !header {
+		return Some(Token::Array(rows.into_iter().map(|row| Some(Token::Array(row.into_iter().map(string).collect()))).collect()));
+	}
+
+	let mut rows = rows.into_iter();
+	let names = match rows.next() {
+		Some(names) => names,
+		None => return Some(Token::Array(Vec::new()))
+	};
+
+	let mut records = Vec::new();
+	for (i, row) in rows.enumerate() {
+		if row.len() > names.len() {
+			return Some(Token::Error(format!("Row {} has {} fields, but the header has {}", i + 2, row.len(), names.len())));
+		}
+
+		// Short rows leave the remaining columns null
+		let mut record = BTreeMap::new();
+		let mut values = row.into_iter();
+		for name in &names {
+			record.insert(name.clone(), values.next().map(string).unwrap_or(Some(Token::Null)));
+		}
+		records.push(Some(Token::Map(record)));
+	}
+
+	Some(Token::Array(records))
+}
+
+// csv_write(rows) or csv_write(rows, options); rows are arrays, or maps whose keys become a header row unless `header` is false
+pub fn csv_write(args: Vec<CtToken>) -> CtToken {
+	if args.is_empty() || args.len() > 2 || args[0].is_none() { return Some(Token::Error(String::from("Expected rows and optional options"))); }
+
+	let options = match parse_options(args.get(1), true) {
+		Ok(options) => options,
+		Err(e) => return e
+	};
+
+	let rows = match args[0].as_ref().unwrap() {
+		Token::Array(rows) => rows,
+		t => return Some(Token::Error(format!("Expected rows to be an array, but received: {}", t)))
+	};
+
+	let mut out = String::new();
+	let mut columns: Option<Vec<String>> = None;
+
+	for (i, row) in rows.iter().enumerate() {
+		let fields = match row {
+			Some(Token::Array(fields)) => fields.clone(),
+			Some(Token::Map(record)) => {
+				if columns.is_none() {
+					let names: Vec<String> = record.keys().cloned().collect();
+					if options.header {
+						write_row(&mut out, &names.iter().map(|name| Some(Token::String(name.clone()))).collect::<Vec<CtToken>>(), options.delimiter);
+					}
+					columns = Some(names);
+				}
+				columns.as_ref().unwrap().iter().map(|name| record.get(name).cloned().unwrap_or(None)).collect()
+			},
+			t => return Some(Token::Error(format!("Expected row {} to be an array or map, but received: {}", i + 1, match t { Some(t) => t.to_string(), None => String::from(types::NULL) })))
+		};
+
+		write_row(&mut out, &fields, options.delimiter);
+	}
+
+	Some(Token::String(out))
+}
+
+pub fn csv_write_file(args: Vec<CtToken>) -> CtToken {
+	if args.len() < 2 || args.len() > 3 { return Some(Token::Error(String::from("Expected a file name, rows and optional options"))); }
+
+	let mut args = args;
+	let file_name = args.remove(0);
+	match csv_write(args) {
+		Some(Token::String(text)) => filestream::write_file(vec![file_name, Some(Token::String(text))]),
+		other => other
+	}
+}
+
+fn write_row(out: &mut String, fields: &[CtToken], delimiter: char) {
+	for (i, field) in fields.iter().enumerate() {
+		if i > 0 {
+			out.push(delimiter);
+		}
+
+		let value = match field {
+			None | Some(Token::Null) => String::new(),
+			Some(t) => t.to_string()
+		};
+
+		if value.contains([delimiter, '"', '\n', '\r']) {
+			out.push('"');
+			out.push_str(&value.replace('"', "\"\""));
+			out.push('"');
+		} else {
+			out.push_str(&value);
+		}
+	}
+	out.push('\n');
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn text(s: &str) -> CtToken {
+		Some(Token::String(String::from(s)))
+	}
+
+	fn options(entries: Vec<(&str, CtToken)>) -> CtToken {
+		Some(Token::Map(entries.into_iter().map(|(key, value)| (String::from(key), value)).collect()))
+	}
+
+	fn written(args: Vec<CtToken>) -> String {
+		match csv_write(args) {
+			Some(Token::String(s)) => s,
+			t => panic!("expected CSV text, received {:?}", t),
+		}
+	}
+
+	fn rows_of(rows: &[&[&str]]) -> CtToken {
+		let row = |row: &&[&str]| Some(Token::Array(row.iter().map(|s| text(s)).collect()));
+		Some(Token::Array(rows.iter().map(row).collect()))
+	}
+
+	#[test]
+	fn quoted_fields() {
+		let rows = csv_parse(vec![text("a,\"b,\"\"c\"\"\"\r\n\"multi\nline\",\n")]);
+		assert_eq!(rows.unwrap().to_string(), rows_of(&[&["a", "b,\"c\""], &["multi\nline", ""]]).unwrap().to_string());
+		assert_eq!(written(vec![rows_of(&[&["a", "b,\"c\""], &["x\ny", ""]])]), "a,\"b,\"\"c\"\"\"\n\"x\ny\",\n");
+	}
+
+	#[test]
+	fn headers_become_keys() {
+		let records = csv_parse(vec![text("name;age\nann;3\nbob\n"), options(vec![("header", Some(Token::Boolean(true))), ("delimiter", text(";"))])]);
+		assert_eq!(records.clone().unwrap().to_string(), r#"[{"age": "3", "name": "ann"}, {"age": null, "name": "bob"}]"#);
+		assert_eq!(written(vec![records]), "age,name\n3,ann\n,bob\n");
+	}
+
+	#[test]
+	fn malformed_input_is_an_error() {
+		let cases = vec![
+			csv_parse(vec![text("a,\"b")]),
+			csv_parse(vec![text("\"a\"b")]),
+			csv_parse(vec![text("a\nb,c"), options(vec![("header", Some(Token::Boolean(true)))])]),
+			csv_parse(vec![text("a"), options(vec![("delimiter", text("\""))])]),
+			csv_write(vec![Some(Token::Array(vec![Some(Token::Integer(1))]))]),
+		];
+		for case in cases {
+			assert!(matches!(case, Some(Token::Error(_))), "expected an error, received {:?}", case);
+		}
+	}
+}
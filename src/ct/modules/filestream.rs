@@ -2,14 +2,85 @@ use ct::utils::module::Module;
 use ct::utils::predefs::Predefs;
 use ct::syntax::std::*;
 
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::io::prelude::*;
-use std::fs::File;
+use std::io::{BufReader, BufWriter};
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::rc::Rc;
 
 pub struct FileStream;
 
 impl Module for FileStream {
 	fn extend(&self, predefs: &mut Predefs) {
 		predefs.insert(String::from("read_file"), Box::new(read_file));
+		predefs.insert(String::from("write_file"), Box::new(write_file));
+		predefs.insert(String::from("append_file"), Box::new(append_file));
+		predefs.insert(String::from("read_lines"), Box::new(read_lines));
+		predefs.insert(String::from("exists"), Box::new(exists));
+		predefs.insert(String::from("remove"), Box::new(remove));
+		predefs.insert(String::from("rename"), Box::new(rename));
+		predefs.insert(String::from("copy"), Box::new(copy));
+		predefs.insert(String::from("mkdir"), Box::new(mkdir));
+		predefs.insert(String::from("mkdir_all"), Box::new(mkdir_all));
+		predefs.insert(String::from("list_dir"), Box::new(list_dir));
+		predefs.insert(String::from("is_file"), Box::new(is_file));
+		predefs.insert(String::from("is_dir"), Box::new(is_dir));
+		predefs.insert(String::from("file_size"), Box::new(file_size));
+
+		let handles = Rc::new(RefCell::new(Handles::new()));
+
+		let table = handles.clone();
+		predefs.insert(String::from("open"), Box::new(move |args| open(&table, args)));
+		let table = handles.clone();
+		predefs.insert(String::from("file_read_line"), Box::new(move |args| file_read_line(&table, args)));
+		let table = handles.clone();
+		predefs.insert(String::from("file_eof"), Box::new(move |args| file_eof(&table, args)));
+		let table = handles.clone();
+		predefs.insert(String::from("file_write"), Box::new(move |args| file_write(&table, args)));
+		predefs.insert(String::from("file_close"), Box::new(move |args| file_close(&handles, args)));
+	}
+}
+
+enum Handle {
+	Reader(BufReader<File>),
+	Writer(BufWriter<File>),
+}
+
+// Open files, keyed by the id stored in the `handle` field of a `File` struct
+pub struct Handles {
+	files: HashMap<i64, Handle>,
+	next: i64,
+}
+
+impl Handles {
+	pub fn new() -> Handles {
+		Handles {
+			files: HashMap::new(),
+			next: 0,
+		}
+	}
+}
+
+pub fn string_args(args: &[CtToken], count: usize) -> Result<Vec<String>, CtToken> {
+	if args.len() != count || args.iter().any(|arg| arg.is_none()) { return Err(Some(Token::Error(String::from("Not enough arguments provided")))); }
+
+	let mut strings = Vec::new();
+	for arg in args {
+		match arg.as_ref().unwrap() {
+			Token::String(s) => strings.push(s.clone()),
+			t => return Err(Some(Token::Error(format!("Expected argument to be of type string, but received: {}", t))))
+		}
+	}
+
+	Ok(strings)
+}
+
+fn unit(result: std::io::Result<()>) -> CtToken {
+	match result {
+		Ok(_) => None,
+		Err(e) => Some(Token::Error(e.to_string()))
 	}
 }
 
@@ -30,4 +101,294 @@ pub fn read_file(args: Vec<CtToken>) -> CtToken {
 	} else {
 		Some(Token::Error(format!("Expected file name to be of type string, but received: {}", args[0].as_ref().unwrap())))
 	}
-}
\ No newline at end of file
+}
+
+pub fn write_file(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 2) {
+		Ok(s) => unit(fs::write(&s[0], &s[1])),
+		Err(e) => e
+	}
+}
+
+pub fn append_file(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 2) {
+		Ok(s) => unit(OpenOptions::new().create(true).append(true).open(&s[0]).and_then(|mut f| f.write_all(s[1].as_bytes()))),
+		Err(e) => e
+	}
+}
+
+pub fn read_lines(args: Vec<CtToken>) -> CtToken {
+	match read_file(args) {
+		Some(Token::String(contents)) => Some(Token::Array(contents.lines().map(|line| Some(Token::String(String::from(line)))).collect())),
+		other => other
+	}
+}
+
+pub fn exists(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => Some(Token::Boolean(fs::metadata(&s[0]).is_ok())),
+		Err(e) => e
+	}
+}
+
+pub fn remove(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => match fs::metadata(&s[0]) {
+			Ok(meta) => unit(if meta.is_dir() { fs::remove_dir(&s[0]) } else { fs::remove_file(&s[0]) }),
+			Err(e) => Some(Token::Error(e.to_string()))
+		},
+		Err(e) => e
+	}
+}
+
+pub fn rename(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 2) {
+		Ok(s) => unit(fs::rename(&s[0], &s[1])),
+		Err(e) => e
+	}
+}
+
+pub fn copy(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 2) {
+		Ok(s) => unit(fs::copy(&s[0], &s[1]).map(|_| ())),
+		Err(e) => e
+	}
+}
+
+pub fn mkdir(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => unit(fs::create_dir(&s[0])),
+		Err(e) => e
+	}
+}
+
+pub fn mkdir_all(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => unit(fs::create_dir_all(&s[0])),
+		Err(e) => e
+	}
+}
+
+// Entry names only, sorted so scripts see the same order on every platform
+pub fn list_dir(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => match fs::read_dir(&s[0]) {
+			Ok(entries) => {
+				let mut names = Vec::new();
+				for entry in entries {
+					match entry {
+						Ok(entry) => names.push(entry.file_name().to_string_lossy().into_owned()),
+						Err(e) => return Some(Token::Error(e.to_string()))
+					}
+				}
+				names.sort();
+				Some(Token::Array(names.into_iter().map(|name| Some(Token::String(name))).collect()))
+			},
+			Err(e) => Some(Token::Error(e.to_string()))
+		},
+		Err(e) => e
+	}
+}
+
+pub fn is_file(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => Some(Token::Boolean(fs::metadata(&s[0]).map(|meta| meta.is_file()).unwrap_or(false))),
+		Err(e) => e
+	}
+}
+
+pub fn is_dir(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => Some(Token::Boolean(fs::metadata(&s[0]).map(|meta| meta.is_dir()).unwrap_or(false))),
+		Err(e) => e
+	}
+}
+
+pub fn file_size(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => match fs::metadata(&s[0]) {
+			Ok(meta) => Some(Token::Integer(meta.len() as i64)),
+			Err(e) => Some(Token::Error(e.to_string()))
+		},
+		Err(e) => e
+	}
+}
+
+// Modes: "r" reads, "w" truncates and writes, "a" appends
+pub fn open(handles: &RefCell<Handles>, args: Vec<CtToken>) -> CtToken {
+	let s = match string_args(&args, 2) {
+		Ok(s) => s,
+		Err(e) => return e
+	};
+
+	let handle = match &s[1][..] {
+		"r" => File::open(&s[0]).map(|f| Handle::Reader(BufReader::new(f))),
+		"w" => File::create(&s[0]).map(|f| Handle::Writer(BufWriter::new(f))),
+		"a" => OpenOptions::new().create(true).append(true).open(&s[0]).map(|f| Handle::Writer(BufWriter::new(f))),
+		mode => return Some(Token::Error(format!("Unknown file mode: {}", mode)))
+	};
+
+	match handle {
+		Ok(handle) => {
+			let mut handles = handles.borrow_mut();
+			let id = handles.next;
+			handles.next += 1;
+			handles.files.insert(id, handle);
+
+			let mut fields = HashMap::new();
+			fields.insert(String::from("handle"), Some(Token::Integer(id)));
+			fields.insert(String::from("path"), Some(Token::String(s[0].clone())));
+			fields.insert(String::from("mode"), Some(Token::String(s[1].clone())));
+			Some(Token::Structure { name: String::from("File"), fields })
+		},
+		Err(e) => Some(Token::Error(e.to_string()))
+	}
+}
+
+fn handle_id(file: &CtToken) -> Result<i64, CtToken> {
+	if let Some(Token::Structure { name: _, fields }) = file {
+		if let Some(Some(Token::Integer(id))) = fields.get("handle") {
+			return Ok(*id);
+		}
+	}
+
+	Err(Some(Token::Error(format!("Expected a file, but received: {}", match file { Some(t) => t.to_string(), None => String::from(types::NULL) }))))
+}
+
+// Returns the next line without its line ending, or nothing once the end is reached
+pub fn file_read_line(handles: &RefCell<Handles>, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 1 { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	let id = match handle_id(&args[0]) {
+		Ok(id) => id,
+		Err(e) => return e
+	};
+
+	match handles.borrow_mut().files.get_mut(&id) {
+		Some(Handle::Reader(reader)) => {
+			let mut line = String::new();
+			match reader.read_line(&mut line) {
+				Ok(0) => None,
+				Ok(_) => {
+					if line.ends_with('\n') { line.pop(); }
+					if line.ends_with('\r') { line.pop(); }
+					Some(Token::String(line))
+				},
+				Err(e) => Some(Token::Error(e.to_string()))
+			}
+		},
+		Some(Handle::Writer(_)) => Some(Token::Error(String::from("File was not opened for reading"))),
+		None => Some(Token::Error(String::from("File is closed")))
+	}
+}
+
+pub fn file_eof(handles: &RefCell<Handles>, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 1 { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	let id = match handle_id(&args[0]) {
+		Ok(id) => id,
+		Err(e) => return e
+	};
+
+	match handles.borrow_mut().files.get_mut(&id) {
+		Some(Handle::Reader(reader)) => match reader.fill_buf() {
+			Ok(buffer) => Some(Token::Boolean(buffer.is_empty())),
+			Err(e) => Some(Token::Error(e.to_string()))
+		},
+		Some(Handle::Writer(_)) => Some(Token::Error(String::from("File was not opened for reading"))),
+		None => Some(Token::Error(String::from("File is closed")))
+	}
+}
+
+pub fn file_write(handles: &RefCell<Handles>, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 2 || args[1].is_none() { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	let id = match handle_id(&args[0]) {
+		Ok(id) => id,
+		Err(e) => return e
+	};
+
+	match handles.borrow_mut().files.get_mut(&id) {
+		Some(Handle::Writer(writer)) => unit(writer.write_all(args[1].as_ref().unwrap().to_string().as_bytes())),
+		Some(Handle::Reader(_)) => Some(Token::Error(String::from("File was not opened for writing"))),
+		None => Some(Token::Error(String::from("File is closed")))
+	}
+}
+
+pub fn file_close(handles: &RefCell<Handles>, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 1 { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	let id = match handle_id(&args[0]) {
+		Ok(id) => id,
+		Err(e) => return e
+	};
+
+	match handles.borrow_mut().files.remove(&id) {
+		Some(Handle::Writer(mut writer)) => unit(writer.flush()),
+		Some(Handle::Reader(_)) => None,
+		None => Some(Token::Error(String::from("File is already closed")))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn text(s: &str) -> CtToken {
+		Some(Token::String(String::from(s)))
+	}
+
+	// A fresh directory per test, so tests running in parallel never share files
+	fn scratch(name: &str) -> String {
+		let dir = std::env::temp_dir().join(format!("corten-filestream-{}-{}", std::process::id(), name));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(&dir).unwrap();
+		dir.to_string_lossy().into_owned()
+	}
+
+	fn is_error(value: &CtToken) -> bool {
+		matches!(value, Some(Token::Error(_)))
+	}
+
+	#[test]
+	fn whole_file_round_trip() {
+		let path = format!("{}/a.txt", scratch("round-trip"));
+		assert!(write_file(vec![text(&path), text("one\n")]).is_none());
+		assert!(append_file(vec![text(&path), text("two\n")]).is_none());
+
+		assert_eq!(read_file(vec![text(&path)]).unwrap().to_string(), "one\ntwo\n");
+		match read_lines(vec![text(&path)]) {
+			Some(Token::Array(lines)) => assert_eq!(lines.len(), 2),
+			t => panic!("expected lines, received {:?}", t),
+		}
+		assert!(matches!(file_size(vec![text(&path)]), Some(Token::Integer(8))));
+		assert!(matches!(exists(vec![text(&path)]), Some(Token::Boolean(true))));
+		assert!(remove(vec![text(&path)]).is_none());
+		assert!(matches!(exists(vec![text(&path)]), Some(Token::Boolean(false))));
+	}
+
+	#[test]
+	fn handles_stream_lines() {
+		let path = format!("{}/b.txt", scratch("handles"));
+		let handles = RefCell::new(Handles::new());
+
+		let writer = open(&handles, vec![text(&path), text("w")]);
+		assert!(file_write(&handles, vec![writer.clone(), text("x\r\ny")]).is_none());
+		assert!(is_error(&file_read_line(&handles, vec![writer.clone()])));
+		assert!(file_close(&handles, vec![writer.clone()]).is_none());
+		assert!(is_error(&file_close(&handles, vec![writer])));
+
+		let reader = open(&handles, vec![text(&path), text("r")]);
+		assert_eq!(file_read_line(&handles, vec![reader.clone()]).unwrap().to_string(), "x");
+		assert!(matches!(file_eof(&handles, vec![reader.clone()]), Some(Token::Boolean(false))));
+		assert_eq!(file_read_line(&handles, vec![reader.clone()]).unwrap().to_string(), "y");
+		assert!(file_read_line(&handles, vec![reader.clone()]).is_none());
+		assert!(matches!(file_eof(&handles, vec![reader]), Some(Token::Boolean(true))));
+	}
+
+	#[test]
+	fn failures_are_errors() {
+		let dir = scratch("failures");
+		let handles = RefCell::new(Handles::new());
+		assert!(is_error(&read_file(vec![text(&format!("{}/missing", dir))])));
+		assert!(is_error(&open(&handles, vec![text(&format!("{}/c.txt", dir)), text("x")])));
+		assert!(is_error(&write_file(vec![text("only one argument")])));
+		assert!(is_error(&read_file(vec![Some(Token::Integer(1))])));
+	}
+}
@@ -1,12 +1,16 @@
+use ct::utils::limits::Interrupt;
 use ct::utils::module::Module;
 use ct::utils::predefs::Predefs;
 use ct::syntax::std::*;
 
 use std::io;
 use std::io::Write;
+use std::sync::mpsc;
+use std::thread;
 
-pub fn print(args: Vec<CtToken>) -> CtToken {
-	if args.len() == 0 { return None; }
+// The text print writes for its arguments, a format string with `{}` for each argument after it
+pub fn format(args: &[CtToken]) -> String {
+	if args.len() == 0 { return String::new(); }
         match args[0].as_ref() {
             Some(t) => 
                 match t {
@@ -47,21 +51,33 @@ pub fn print(args: Vec<CtToken>) -> CtToken {
                                 }
                             }
                         }
-                        print!("{}", res);
+                        res
                     },
-                    _ => print!("{}", t)
+                    _ => format!("{}", t)
                 },
-            None => print!("None")
+            None => String::from("None")
     }
+}
+
+pub fn print(args: Vec<CtToken>) -> CtToken {
+	print!("{}", format(&args));
 	None
 }
 
-pub fn input(args: Vec<CtToken>) -> CtToken {
+// The line is read on its own thread, so an interrupted script does not wait for it
+pub fn input(interrupt: &Interrupt, args: Vec<CtToken>) -> CtToken {
 	print(args);
 
-	let mut input = String::new();
-	io::stdin().read_line(&mut input).expect("Unable to read input");
-	Some(Token::String(String::from(input.trim())))
+	let (sender, receiver) = mpsc::channel();
+	thread::spawn(move || {
+		let mut input = String::new();
+		let _ = sender.send(io::stdin().read_line(&mut input).map(|_| input));
+	});
+	match interrupt.wait(&receiver) {
+		Some(Ok(input)) => Some(Token::String(String::from(input.trim()))),
+		Some(Err(e)) => Some(Token::Error(format!("Unable to read input: {}", e))),
+		None => Some(Token::Error(String::from("Input interrupted")))
+	}
 }
 
 pub fn flush(args: Vec<CtToken>) -> CtToken {
@@ -75,7 +91,8 @@ pub struct IOStream;
 impl Module for IOStream {
 	fn extend(&self, predefs: &mut Predefs) {
 		predefs.insert(String::from("print"), Box::new(print));
-		predefs.insert(String::from("input"), Box::new(input));
+		let interrupt = predefs.interrupt();
+		predefs.insert(String::from("input"), Box::new(move |args| input(&interrupt, args)));
         predefs.insert(String::from("flush"), Box::new(flush));
 	} 
 }
\ No newline at end of file
@@ -0,0 +1,497 @@
+use ct::utils::limits::Interrupt;
+use ct::utils::module::Module;
+use ct::utils::predefs::Predefs;
+use ct::syntax::std::*;
+
+use std::collections::BTreeMap;
+
+// Arrays and objects nested deeper than this are rejected instead of exhausting the stack
+const MAX_DEPTH: usize = 512;
+
+pub struct Json;
+
+impl Module for Json {
+	fn extend(&self, predefs: &mut Predefs) {
+		let interrupt = predefs.interrupt();
+		predefs.insert(String::from("json_parse"), Box::new(move |args| json_parse(&interrupt, args)));
+		let interrupt = predefs.interrupt();
+		predefs.insert(String::from("json_stringify"), Box::new(move |args| json_stringify(&interrupt, args)));
+	}
+}
+
+// Both directions give up with an error when the script is interrupted halfway through a large document
+pub fn json_parse(interrupt: &Interrupt, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 1 || args[0].is_none() { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	if let Token::String(text) = args[0].as_ref().unwrap() {
+		let mut reader = Reader::new(text, interrupt);
+		match reader.parse_document() {
+			Ok(value) => value,
+			Err(e) => Some(Token::Error(e))
+		}
+	} else {
+		Some(Token::Error(format!("Expected JSON text to be of type string, but received: {}", args[0].as_ref().unwrap())))
+	}
+}
+
+// The optional second argument is the number of spaces to indent with; without it the output is compact
+pub fn json_stringify(interrupt: &Interrupt, args: Vec<CtToken>) -> CtToken {
+	if args.is_empty() || args.len() > 2 { return Some(Token::Error(String::from("Expected a value and an optional indent"))); }
+
+	let indent = match args.get(1) {
+		None => 0,
+		Some(Some(Token::Integer(n))) if *n >= 0 => *n as usize,
+		Some(t) => return Some(Token::Error(format!("Expected indent to be a positive int, but received: {}", match t { Some(t) => t.to_string(), None => String::from(types::NULL) })))
+	};
+
+	let mut out = String::new();
+	match write_value(&mut out, &args[0], indent, 0, interrupt) {
+		Ok(_) => Some(Token::String(out)),
+		Err(e) => Some(Token::Error(e))
+	}
+}
+
+fn write_value(out: &mut String, value: &CtToken, indent: usize, depth: usize, interrupt: &Interrupt) -> Result<(), String> {
+	if interrupt.is_interrupted() {
+		return Err(String::from("JSON conversion interrupted"));
+	}
+	if depth > MAX_DEPTH {
+		return Err(format!("Cannot represent values nested deeper than {} levels in JSON", MAX_DEPTH));
+	}
+
+	match value {
+		None | Some(Token::Null) => out.push_str("null"),
+		Some(Token::Boolean(b)) => out.push_str(if *b { "true" } else { "false" }),
+		Some(Token::Integer(n)) => out.push_str(&n.to_string()),
+		Some(Token::Float(n)) => {
+			if !n.is_finite() {
+				return Err(format!("Cannot represent {} in JSON", n));
+			}
+			// Keep whole floats recognisable so they parse back as floats
+			if n.fract() == 0.0 && n.abs() < 1e16 {
+				out.push_str(&format!("{:.1}", n));
+			} else {
+				out.push_str(&n.to_string());
+			}
+		},
+		Some(Token::String(s)) => write_string(out, s),
+		Some(Token::Array(items)) => {
+			if items.is_empty() {
+				out.push_str("[]");
+				return Ok(());
+			}
+
+			out.push('[');
+			for (i, item) in items.iter().enumerate() {
+				if i > 0 { out.push(','); }
+				write_newline(out, indent, depth + 1);
+				write_value(out, item, indent, depth + 1, interrupt)?;
+			}
+			write_newline(out, indent, depth);
+			out.push(']');
+		},
+		Some(Token::Map(entries)) => write_object(out, entries.iter(), indent, depth, interrupt)?,
+		Some(Token::Structure { name: _, fields }) => {
+			let sorted: BTreeMap<&String, &CtToken> = fields.iter().collect();
+			write_object(out, sorted.into_iter(), indent, depth, interrupt)?
+		},
+		Some(t) => return Err(format!("Cannot represent {} in JSON", get_type(&Some(t.clone()))))
+	}
+
+	Ok(())
+}
+
+fn write_object<'a, I>(out: &mut String, entries: I, indent: usize, depth: usize, interrupt: &Interrupt) -> Result<(), String>
+	where I: ExactSizeIterator<Item = (&'a String, &'a CtToken)> {
+	if entries.len() == 0 {
+		out.push_str("{}");
+		return Ok(());
+	}
+
+	out.push('{');
+	for (i, (key, value)) in entries.enumerate() {
+		if i > 0 { out.push(','); }
+		write_newline(out, indent, depth + 1);
+		write_string(out, key);
+		out.push_str(if indent > 0 { ": " } else { ":" });
+		write_value(out, value, indent, depth + 1, interrupt)?;
+	}
+	write_newline(out, indent, depth);
+	out.push('}');
+
+	Ok(())
+}
+
+fn write_newline(out: &mut String, indent: usize, depth: usize) {
+	if indent > 0 {
+		out.push('\n');
+		out.push_str(&" ".repeat(indent * depth));
+	}
+}
+
+fn write_string(out: &mut String, s: &str) {
+	out.push('"');
+	for c in s.chars() {
+		match c {
+			'"' => out.push_str("\\\""),
+			'\\' => out.push_str("\\\\"),
+			'\n' => out.push_str("\\n"),
+			'\r' => out.push_str("\\r"),
+			'\t' => out.push_str("\\t"),
+			'\x08' => out.push_str("\\b"),
+			'\x0C' => out.push_str("\\f"),
+			c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+			c => out.push(c),
+		}
+	}
+	out.push('"');
+}
+
+struct Reader<'a> {
+	chars: Vec<char>,
+	index: usize,
+	line: usize,
+	col: usize,
+	depth: usize,
+	interrupt: &'a Interrupt,
+}
+
+impl<'a> Reader<'a> {
+	fn new(text: &str, interrupt: &'a Interrupt) -> Reader<'a> {
+		Reader { chars: text.chars().collect(), index: 0, line: 1, col: 1, depth: 0, interrupt }
+	}
+
+	fn error(&self, message: &str) -> String {
+		format!("{} at line {}, column {}", message, self.line, self.col)
+	}
+
+	fn peek(&self) -> Option<char> {
+		self.chars.get(self.index).cloned()
+	}
+
+	fn next(&mut self) -> Option<char> {
+		let c = self.peek()?;
+		self.index += 1;
+		if c == '\n' {
+			self.line += 1;
+			self.col = 1;
+		} else {
+			self.col += 1;
+		}
+		Some(c)
+	}
+
+	fn skip_whitespace(&mut self) {
+		while let Some(' ') | Some('\t') | Some('\n') | Some('\r') = self.peek() {
+			self.next();
+		}
+	}
+
+	fn expect(&mut self, expected: char) -> Result<(), String> {
+		match self.peek() {
+			Some(c) if c == expected => { self.next(); Ok(()) },
+			Some(c) => Err(self.error(&format!("Expected '{}', but found '{}'", expected, c))),
+			None => Err(self.error(&format!("Expected '{}', but reached the end of input", expected)))
+		}
+	}
+
+	fn parse_document(&mut self) -> Result<CtToken, String> {
+		let value = self.parse_value()?;
+		self.skip_whitespace();
+		match self.peek() {
+			Some(c) => Err(self.error(&format!("Unexpected '{}' after JSON value", c))),
+			None => Ok(value)
+		}
+	}
+
+	fn parse_value(&mut self) -> Result<CtToken, String> {
+		if self.interrupt.is_interrupted() {
+			return Err(self.error("JSON parsing interrupted"));
+		}
+		self.skip_whitespace();
+		match self.peek() {
+			Some(c) if c == '{' || c == '[' => {
+				if self.depth == MAX_DEPTH {
+					return Err(self.error(&format!("Nesting deeper than {} levels", MAX_DEPTH)));
+				}
+
+				self.depth += 1;
+				let value = if c == '{' { self.parse_object() } else { self.parse_array() };
+				self.depth -= 1;
+				value
+			},
+			Some('"') => Ok(Some(Token::String(self.parse_string()?))),
+			Some('t') => self.parse_keyword("true", Some(Token::Boolean(true))),
+			Some('f') => self.parse_keyword("false", Some(Token::Boolean(false))),
+			Some('n') => self.parse_keyword("null", Some(Token::Null)),
+			Some(c) if c == '-' || c.is_ascii_digit() => self.parse_number(),
+			Some(c) => Err(self.error(&format!("Unexpected '{}'", c))),
+			None => Err(self.error("Unexpected end of input"))
+		}
+	}
+
+	fn parse_keyword(&mut self, keyword: &str, value: CtToken) -> Result<CtToken, String> {
+		for expected in keyword.chars() {
+			if self.peek() != Some(expected) {
+				return Err(self.error(&format!("Invalid literal, expected {}", keyword)));
+			}
+			self.next();
+		}
+		Ok(value)
+	}
+
+	// -?(0|[1-9][0-9]*)(.[0-9]+)?([eE][+-]?[0-9]+)?, so `01`, `1.` and `.5` are all rejected
+	fn parse_number(&mut self) -> Result<CtToken, String> {
+		let (line, col) = (self.line, self.col);
+		let mut text = String::new();
+		let mut is_float = false;
+
+		if self.peek() == Some('-') {
+			text.push('-');
+			self.next();
+		}
+
+		let leading_zero = self.peek() == Some('0');
+		let mut valid = self.read_digits(&mut text) > 0;
+		if leading_zero && text.trim_start_matches('-').len() > 1 {
+			valid = false;
+		}
+
+		if valid && self.peek() == Some('.') {
+			is_float = true;
+			text.push('.');
+			self.next();
+			valid = self.read_digits(&mut text) > 0;
+		}
+
+		if valid && (self.peek() == Some('e') || self.peek() == Some('E')) {
+			is_float = true;
+			text.push(self.next().unwrap());
+			if let Some(sign) = self.peek().filter(|c| *c == '+' || *c == '-') {
+				text.push(sign);
+				self.next();
+			}
+			valid = self.read_digits(&mut text) > 0;
+		}
+
+		// Show where the number went wrong, including the character that broke it
+		if !valid {
+			if let Some(c) = self.peek().filter(|c| !c.is_whitespace()) {
+				text.push(c);
+			}
+		}
+
+		let invalid = || format!("Invalid number {} at line {}, column {}", text, line, col);
+		if !valid {
+			Err(invalid())
+		} else if is_float {
+			text.parse::<f64>().map(|n| Some(Token::Float(n))).map_err(|_| invalid())
+		} else {
+			match text.parse::<i64>() {
+				Ok(n) => Ok(Some(Token::Integer(n))),
+				// Too large for an int, but still a valid JSON number
+				Err(_) => text.parse::<f64>().map(|n| Some(Token::Float(n))).map_err(|_| invalid())
+			}
+		}
+	}
+
+	fn read_digits(&mut self, text: &mut String) -> usize {
+		let mut count = 0;
+		while let Some(c) = self.peek().filter(|c| c.is_ascii_digit()) {
+			text.push(c);
+			self.next();
+			count += 1;
+		}
+		count
+	}
+
+	fn parse_string(&mut self) -> Result<String, String> {
+		self.expect('"')?;
+		let mut s = String::new();
+
+		loop {
+			match self.next() {
+				Some('"') => return Ok(s),
+				Some('\\') => match self.next() {
+					Some('"') => s.push('"'),
+					Some('\\') => s.push('\\'),
+					Some('/') => s.push('/'),
+					Some('b') => s.push('\x08'),
+					Some('f') => s.push('\x0C'),
+					Some('n') => s.push('\n'),
+					Some('r') => s.push('\r'),
+					Some('t') => s.push('\t'),
+					Some('u') => {
+						let high = self.parse_hex()?;
+						let code = if (0xD800..0xDC00).contains(&high) {
+							// Surrogate pair
+							self.expect('\\')?;
+							self.expect('u')?;
+							let low = self.parse_hex()?;
+							if !(0xDC00..0xE000).contains(&low) {
+								return Err(self.error("Invalid low surrogate"));
+							}
+							0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+						} else {
+							high
+						};
+						match ::std::char::from_u32(code) {
+							Some(c) => s.push(c),
+							None => return Err(self.error("Invalid unicode escape"))
+						}
+					},
+					Some(c) => return Err(self.error(&format!("Invalid escape '\\{}'", c))),
+					None => return Err(self.error("Unterminated string"))
+				},
+				Some(c) if (c as u32) < 0x20 => return Err(self.error("Control character in string")),
+				Some(c) => s.push(c),
+				None => return Err(self.error("Unterminated string"))
+			}
+		}
+	}
+
+	fn parse_hex(&mut self) -> Result<u32, String> {
+		let mut code = 0;
+		for _ in 0..4 {
+			match self.next().and_then(|c| c.to_digit(16)) {
+				Some(digit) => code = code * 16 + digit,
+				None => return Err(self.error("Invalid unicode escape"))
+			}
+		}
+		Ok(code)
+	}
+
+	fn parse_array(&mut self) -> Result<CtToken, String> {
+		self.expect('[')?;
+		let mut items = Vec::new();
+
+		self.skip_whitespace();
+		if self.peek() == Some(']') {
+			self.next();
+			return Ok(Some(Token::Array(items)));
+		}
+
+		loop {
+			items.push(self.parse_value()?);
+			self.skip_whitespace();
+			match self.next() {
+				Some(',') => (),
+				Some(']') => return Ok(Some(Token::Array(items))),
+				Some(c) => return Err(self.error(&format!("Expected ',' or ']', but found '{}'", c))),
+				None => return Err(self.error("Unterminated array"))
+			}
+		}
+	}
+
+	fn parse_object(&mut self) -> Result<CtToken, String> {
+		self.expect('{')?;
+		let mut entries = BTreeMap::new();
+
+		self.skip_whitespace();
+		if self.peek() == Some('}') {
+			self.next();
+			return Ok(Some(Token::Map(entries)));
+		}
+
+		loop {
+			self.skip_whitespace();
+			let key = self.parse_string()?;
+			self.skip_whitespace();
+			self.expect(':')?;
+			let value = self.parse_value()?;
+			entries.insert(key, value);
+
+			self.skip_whitespace();
+			match self.next() {
+				Some(',') => (),
+				Some('}') => return Ok(Some(Token::Map(entries))),
+				Some(c) => return Err(self.error(&format!("Expected ',' or '}}', but found '{}'", c))),
+				None => return Err(self.error("Unterminated object"))
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse(text: &str) -> CtToken {
+		json_parse(&Interrupt::new(), vec![Some(Token::String(String::from(text)))])
+	}
+
+	fn parse_error(text: &str) -> String {
+		match parse(text) {
+			Some(Token::Error(e)) => e,
+			t => panic!("expected {} to fail, received {:?}", text, t),
+		}
+	}
+
+	fn stringify(value: CtToken, indent: i64) -> String {
+		match json_stringify(&Interrupt::new(), vec![value, Some(Token::Integer(indent))]) {
+			Some(Token::String(s)) => s,
+			t => panic!("expected a string, received {:?}", t),
+		}
+	}
+
+	#[test]
+	fn values_round_trip() {
+		let text = r#"{"a":[1,-2.5,1e3,true,false,null],"b":{"c":"é\n😀"},"big":12345678901234567890}"#;
+		let value = parse(text);
+		assert_eq!(stringify(value.clone(), 0), r#"{"a":[1,-2.5,1000.0,true,false,null],"b":{"c":"é\n😀"},"big":12345678901234567000}"#);
+		assert_eq!(stringify(parse("[1,{\"x\":[]}]"), 2), "[\n  1,\n  {\n    \"x\": []\n  }\n]");
+		assert!(matches!(parse("-0"), Some(Token::Integer(0))));
+		assert!(matches!(parse("0.5e-2"), Some(Token::Float(_))));
+	}
+
+	#[test]
+	fn errors_have_a_position() {
+		assert_eq!(parse_error("[1,\n  tru]"), "Invalid literal, expected true at line 2, column 6");
+		assert_eq!(parse_error("{\"a\" 1}"), "Expected ':', but found '1' at line 1, column 6");
+		assert_eq!(parse_error("[1] x"), "Unexpected 'x' after JSON value at line 1, column 5");
+		assert_eq!(parse_error("\"abc"), "Unterminated string at line 1, column 5");
+	}
+
+	#[test]
+	fn numbers_follow_the_json_grammar() {
+		assert_eq!(parse_error("01"), "Invalid number 01 at line 1, column 1");
+		assert_eq!(parse_error("[1.]"), "Invalid number 1.] at line 1, column 2");
+		assert_eq!(parse_error("-"), "Invalid number - at line 1, column 1");
+		assert_eq!(parse_error("1e+"), "Invalid number 1e+ at line 1, column 1");
+		assert_eq!(parse_error("-01.5"), "Invalid number -01. at line 1, column 1");
+	}
+
+	#[test]
+	fn deep_nesting_is_an_error() {
+		let deep = "[".repeat(100000);
+		assert_eq!(parse_error(&deep), format!("Nesting deeper than {} levels at line 1, column {}", MAX_DEPTH, MAX_DEPTH + 1));
+		let limit = format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH));
+		assert!(matches!(parse(&limit), Some(Token::Array(_))));
+
+		let mut value = Some(Token::Null);
+		for _ in 0..MAX_DEPTH + 1 {
+			value = Some(Token::Array(vec![value]));
+		}
+		assert!(matches!(json_stringify(&Interrupt::new(), vec![value]), Some(Token::Error(_))));
+	}
+
+	#[test]
+	fn interrupted_conversions_are_errors() {
+		let interrupt = Interrupt::new();
+		interrupt.interrupt();
+		let text = Some(Token::String(String::from("[1, 2]")));
+		assert!(matches!(json_parse(&interrupt, vec![text.clone()]), Some(Token::Error(_))));
+		assert!(matches!(json_stringify(&interrupt, vec![text]), Some(Token::Error(_))));
+	}
+
+	#[test]
+	fn unrepresentable_values_are_errors() {
+		let cases = vec![
+			json_stringify(&Interrupt::new(), vec![Some(Token::Float(f64::NAN))]),
+			json_stringify(&Interrupt::new(), vec![Some(Token::Native(String::from("print")))]),
+			json_stringify(&Interrupt::new(), vec![Some(Token::Null), Some(Token::Integer(-1))]),
+		];
+		for case in cases {
+			assert!(matches!(case, Some(Token::Error(_))), "expected an error, received {:?}", case);
+		}
+	}
+}
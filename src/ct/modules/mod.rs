@@ -1,3 +1,11 @@
 pub mod iostream;
 pub mod filestream;
-pub mod string;
\ No newline at end of file
+pub mod string;
+pub mod random;
+pub mod path;
+pub mod os;
+pub mod process;
+pub mod json;
+pub mod csv;
+pub mod time;
+pub mod regex;
\ No newline at end of file
@@ -0,0 +1,116 @@
+use ct::modules::filestream::string_args;
+use ct::utils::limits::Interrupt;
+use ct::utils::module::Module;
+use ct::utils::predefs::Predefs;
+use ct::syntax::std::*;
+
+use std::env;
+
+pub struct Os {
+	args: Vec<String>,
+}
+
+impl Os {
+	// `args` are the arguments following the script path
+	pub fn new(args: Vec<String>) -> Os {
+		Os { args }
+	}
+}
+
+impl Module for Os {
+	fn extend(&self, predefs: &mut Predefs) {
+		let script_args = Some(Token::Array(self.args.iter().map(|arg| Some(Token::String(arg.clone()))).collect()));
+		predefs.insert(String::from("args"), Box::new(move |args| if args.is_empty() { script_args.clone() } else { Some(Token::Error(String::from("Expected no arguments"))) }));
+		predefs.insert(String::from("env"), Box::new(get_env));
+		predefs.insert(String::from("set_env"), Box::new(set_env));
+		predefs.insert(String::from("cwd"), Box::new(cwd));
+		let interrupt = predefs.interrupt();
+		predefs.insert(String::from("exit"), Box::new(move |args| exit(&interrupt, args)));
+	}
+}
+
+// Returns nothing when the variable is not set
+pub fn get_env(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => match env::var(&s[0]) {
+			Ok(value) => Some(Token::String(value)),
+			Err(env::VarError::NotPresent) => None,
+			Err(e) => Some(Token::Error(e.to_string()))
+		},
+		Err(e) => e
+	}
+}
+
+pub fn set_env(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 2) {
+		Ok(s) => {
+			if s[0].is_empty() || s[0].contains(['=', '\0']) || s[1].contains('\0') {
+				return Some(Token::Error(format!("Invalid environment variable: {}", s[0])));
+			}
+			env::set_var(&s[0], &s[1]);
+			None
+		},
+		Err(e) => e
+	}
+}
+
+pub fn cwd(args: Vec<CtToken>) -> CtToken {
+	if !args.is_empty() { return Some(Token::Error(String::from("Expected no arguments"))); }
+	match env::current_dir() {
+		Ok(path) => Some(Token::String(path.to_string_lossy().into_owned())),
+		Err(e) => Some(Token::Error(e.to_string()))
+	}
+}
+
+// Stops the script instead of the process, the host reads the code from the interpreter
+pub fn exit(interrupt: &Interrupt, args: Vec<CtToken>) -> CtToken {
+	let code = match args.first() {
+		None => 0,
+		// Larger codes would wrap around, and 256 would exit successfully
+		Some(Some(Token::Integer(code))) if (0..=255).contains(code) => *code as i32,
+		Some(Some(Token::Integer(code))) => return Some(Token::Error(format!("Expected exit code to be between 0 and 255, but received: {}", code))),
+		Some(t) => return Some(Token::Error(format!("Expected exit code to be of type int, but received: {}", match t { Some(t) => t.to_string(), None => String::from(types::NULL) })))
+	};
+
+	interrupt.exit(code);
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use ct::utils::limits::Reason;
+	use std::process;
+
+	fn text(s: &str) -> CtToken {
+		Some(Token::String(String::from(s)))
+	}
+
+	#[test]
+	fn environment_variables() {
+		let name = format!("CORTEN_OS_TEST_{}", process::id());
+		assert!(get_env(vec![text(&name)]).is_none());
+		assert!(set_env(vec![text(&name), text("value")]).is_none());
+		assert_eq!(get_env(vec![text(&name)]).unwrap().to_string(), "value");
+
+		assert!(matches!(set_env(vec![text("A=B"), text("x")]), Some(Token::Error(_))));
+		assert!(matches!(set_env(vec![text(""), text("x")]), Some(Token::Error(_))));
+	}
+
+	#[test]
+	fn invalid_arguments_are_errors() {
+		assert!(matches!(cwd(vec![text("x")]), Some(Token::Error(_))));
+		assert!(matches!(cwd(Vec::new()), Some(Token::String(_))));
+		assert!(matches!(exit(&Interrupt::new(), vec![text("1")]), Some(Token::Error(_))));
+		for code in [256, 4_294_967_296, -1].iter() {
+			assert!(matches!(exit(&Interrupt::new(), vec![Some(Token::Integer(*code))]), Some(Token::Error(_))));
+		}
+	}
+
+	#[test]
+	fn exit_stops_the_script_not_the_host() {
+		let interrupt = Interrupt::new();
+		assert!(exit(&interrupt, vec![Some(Token::Integer(3))]).is_none());
+		assert_eq!(interrupt.reason(), Some(Reason::Exit(3)));
+	}
+}
@@ -0,0 +1,347 @@
+use ct::modules::filestream::string_args;
+use ct::utils::module::Module;
+use ct::utils::predefs::Predefs;
+use ct::syntax::std::*;
+
+use std::env;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+pub struct PathModule;
+
+impl Module for PathModule {
+	fn extend(&self, predefs: &mut Predefs) {
+		predefs.insert(String::from("join"), Box::new(join));
+		predefs.insert(String::from("dirname"), Box::new(dirname));
+		predefs.insert(String::from("basename"), Box::new(basename));
+		predefs.insert(String::from("extension"), Box::new(extension));
+		predefs.insert(String::from("stem"), Box::new(stem));
+		predefs.insert(String::from("normalize"), Box::new(normalize));
+		predefs.insert(String::from("absolute"), Box::new(absolute));
+		predefs.insert(String::from("relative_to"), Box::new(relative_to));
+		predefs.insert(String::from("glob"), Box::new(glob));
+	}
+}
+
+fn to_token(path: &Path) -> CtToken {
+	Some(Token::String(path.to_string_lossy().into_owned()))
+}
+
+// Accepts any number of segments; an absolute segment restarts the path
+pub fn join(args: Vec<CtToken>) -> CtToken {
+	if args.is_empty() { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	match string_args(&args, args.len()) {
+		Ok(s) => {
+			let mut path = PathBuf::new();
+			for segment in s {
+				path.push(segment);
+			}
+			to_token(&path)
+		},
+		Err(e) => e
+	}
+}
+
+pub fn dirname(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => match Path::new(&s[0]).parent() {
+			Some(parent) if parent.as_os_str().is_empty() => Some(Token::String(String::from("."))),
+			Some(parent) => to_token(parent),
+			None => Some(Token::String(s[0].clone()))
+		},
+		Err(e) => e
+	}
+}
+
+pub fn basename(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => Some(Token::String(Path::new(&s[0]).file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default())),
+		Err(e) => e
+	}
+}
+
+// Without the leading dot, empty when there is none
+pub fn extension(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => Some(Token::String(Path::new(&s[0]).extension().map(|ext| ext.to_string_lossy().into_owned()).unwrap_or_default())),
+		Err(e) => e
+	}
+}
+
+pub fn stem(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => Some(Token::String(Path::new(&s[0]).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default())),
+		Err(e) => e
+	}
+}
+
+// Purely lexical: `.` is dropped and `..` eats the previous component, the filesystem is never consulted
+fn normalize_path(path: &Path) -> PathBuf {
+	let mut result = PathBuf::new();
+	let mut depth = 0;
+
+	for component in path.components() {
+		match component {
+			Component::CurDir => (),
+			Component::ParentDir => {
+				if depth > 0 {
+					result.pop();
+					depth -= 1;
+				} else if !result.has_root() {
+					result.push("..");
+				}
+			},
+			Component::Normal(name) => {
+				result.push(name);
+				depth += 1;
+			},
+			other => result.push(other.as_os_str()),
+		}
+	}
+
+	if result.as_os_str().is_empty() {
+		result.push(".");
+	}
+
+	result
+}
+
+fn absolute_path(path: &Path) -> Result<PathBuf, CtToken> {
+	if path.is_absolute() {
+		Ok(normalize_path(path))
+	} else {
+		match env::current_dir() {
+			Ok(cwd) => Ok(normalize_path(&cwd.join(path))),
+			Err(e) => Err(Some(Token::Error(e.to_string())))
+		}
+	}
+}
+
+pub fn normalize(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => to_token(&normalize_path(Path::new(&s[0]))),
+		Err(e) => e
+	}
+}
+
+pub fn absolute(args: Vec<CtToken>) -> CtToken {
+	match string_args(&args, 1) {
+		Ok(s) => match absolute_path(Path::new(&s[0])) {
+			Ok(path) => to_token(&path),
+			Err(e) => e
+		},
+		Err(e) => e
+	}
+}
+
+// The path of the first argument as seen from the second, e.g. relative_to("/a/b/c", "/a/d") is "../b/c"
+pub fn relative_to(args: Vec<CtToken>) -> CtToken {
+	let s = match string_args(&args, 2) {
+		Ok(s) => s,
+		Err(e) => return e
+	};
+
+	let (path, base) = match (absolute_path(Path::new(&s[0])), absolute_path(Path::new(&s[1]))) {
+		(Ok(path), Ok(base)) => (path, base),
+		(Err(e), _) | (_, Err(e)) => return e
+	};
+
+	let path: Vec<Component> = path.components().collect();
+	let base: Vec<Component> = base.components().collect();
+	let shared = path.iter().zip(base.iter()).take_while(|(a, b)| a == b).count();
+
+	let mut result = PathBuf::new();
+	for _ in shared..base.len() {
+		result.push("..");
+	}
+	for component in &path[shared..] {
+		result.push(component.as_os_str());
+	}
+
+	if result.as_os_str().is_empty() {
+		result.push(".");
+	}
+
+	to_token(&result)
+}
+
+// Supports `*`, `?`, `[abc]`, `[a-z]`, `[!abc]` within a component and `**` for any number of directories
+pub fn glob(args: Vec<CtToken>) -> CtToken {
+	let s = match string_args(&args, 1) {
+		Ok(s) => s,
+		Err(e) => return e
+	};
+
+	let pattern = Path::new(&s[0]);
+	let mut components = Vec::new();
+	let mut start = PathBuf::new();
+
+	for component in pattern.components() {
+		match component {
+			Component::Normal(name) => components.push(name.to_string_lossy().into_owned()),
+			Component::CurDir => (),
+			Component::ParentDir => components.push(String::from("..")),
+			other => start.push(other.as_os_str()),
+		}
+	}
+
+	let mut matches = Vec::new();
+	glob_walk(&start, &components, &mut matches);
+	matches.sort();
+	matches.dedup();
+
+	Some(Token::Array(matches.iter().map(|path| to_token(path)).collect()))
+}
+
+fn glob_walk(current: &Path, components: &[String], matches: &mut Vec<PathBuf>) {
+	if components.is_empty() {
+		if !current.as_os_str().is_empty() && fs::metadata(current).is_ok() {
+			matches.push(current.to_path_buf());
+		}
+		return;
+	}
+
+	let component = &components[0];
+	let dir = if current.as_os_str().is_empty() { Path::new(".") } else { current };
+
+	if component == "**" {
+		glob_walk(current, &components[1..], matches);
+		for child in glob_children(dir, current) {
+			// Symlinked directories are not followed, a link back up would make the walk endless
+			if fs::symlink_metadata(&child).map(|meta| meta.is_dir()).unwrap_or(false) {
+				glob_walk(&child, components, matches);
+			}
+		}
+	} else if !component.contains(['*', '?', '[']) {
+		glob_walk(&current.join(component), &components[1..], matches);
+	} else {
+		let pattern: Vec<char> = component.chars().collect();
+		for child in glob_children(dir, current) {
+			let name: Vec<char> = match child.file_name() {
+				Some(name) => name.to_string_lossy().chars().collect(),
+				None => continue,
+			};
+
+			// Hidden entries only match patterns that spell out the dot
+			if name.first() == Some(&'.') && pattern.first() != Some(&'.') {
+				continue;
+			}
+
+			if wildcard(&pattern, &name) {
+				glob_walk(&child, &components[1..], matches);
+			}
+		}
+	}
+}
+
+fn glob_children(dir: &Path, current: &Path) -> Vec<PathBuf> {
+	match fs::read_dir(dir) {
+		Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| current.join(entry.file_name())).collect(),
+		Err(_) => Vec::new(),
+	}
+}
+
+fn wildcard(pattern: &[char], name: &[char]) -> bool {
+	if pattern.is_empty() {
+		return name.is_empty();
+	}
+
+	match pattern[0] {
+		'*' => (0..=name.len()).any(|i| wildcard(&pattern[1..], &name[i..])),
+		'?' => !name.is_empty() && wildcard(&pattern[1..], &name[1..]),
+		'[' => {
+			if name.is_empty() {
+				return false;
+			}
+
+			match pattern.iter().skip(1).position(|c| *c == ']') {
+				Some(end) => {
+					let class = &pattern[1..=end];
+					let (negated, class) = if class.first() == Some(&'!') { (true, &class[1..]) } else { (false, class) };
+
+					let mut found = false;
+					let mut i = 0;
+					while i < class.len() {
+						if i + 2 < class.len() && class[i + 1] == '-' {
+							found |= class[i] <= name[0] && name[0] <= class[i + 2];
+							i += 3;
+						} else {
+							found |= class[i] == name[0];
+							i += 1;
+						}
+					}
+
+					found != negated && wildcard(&pattern[end + 2..], &name[1..])
+				},
+				None => name[0] == '[' && wildcard(&pattern[1..], &name[1..])
+			}
+		},
+		c => !name.is_empty() && name[0] == c && wildcard(&pattern[1..], &name[1..])
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn call(function: fn(Vec<CtToken>) -> CtToken, args: &[&str]) -> String {
+		match function(args.iter().map(|arg| Some(Token::String(arg.to_string()))).collect()) {
+			Some(Token::String(s)) => s,
+			t => panic!("expected a string, received {:?}", t),
+		}
+	}
+
+	#[test]
+	fn components() {
+		assert_eq!(call(join, &["a", "b", "c.txt"]), "a/b/c.txt");
+		assert_eq!(call(join, &["a", "/b"]), "/b");
+		assert_eq!(call(dirname, &["a/b/c.txt"]), "a/b");
+		assert_eq!(call(dirname, &["c.txt"]), ".");
+		assert_eq!(call(basename, &["a/b/c.tar.gz"]), "c.tar.gz");
+		assert_eq!(call(extension, &["a/b/c.tar.gz"]), "gz");
+		assert_eq!(call(extension, &["a/b/c"]), "");
+		assert_eq!(call(stem, &["a/b/c.tar.gz"]), "c.tar");
+	}
+
+	#[test]
+	fn lexical_normalization() {
+		assert_eq!(call(normalize, &["a/./b/../c"]), "a/c");
+		assert_eq!(call(normalize, &["../a/.."]), "..");
+		assert_eq!(call(normalize, &["/.."]), "/");
+		assert_eq!(call(normalize, &["a/.."]), ".");
+		assert_eq!(call(relative_to, &["/a/b/c", "/a/d"]), "../b/c");
+		assert_eq!(call(relative_to, &["/a", "/a"]), ".");
+	}
+
+	#[test]
+	fn wildcards() {
+		let matches = |pattern: &str, name: &str| {
+			wildcard(&pattern.chars().collect::<Vec<_>>(), &name.chars().collect::<Vec<_>>())
+		};
+		assert!(matches("*.rs", "main.rs"));
+		assert!(!matches("*.rs", "main.rc"));
+		assert!(matches("m?in.[rx]s", "main.rs"));
+		assert!(matches("[a-c]x", "bx"));
+		assert!(!matches("[!a-c]x", "bx"));
+	}
+
+	#[test]
+	fn glob_walks_directories() {
+		let dir = std::env::temp_dir().join(format!("corten-path-{}", std::process::id()));
+		let _ = fs::remove_dir_all(&dir);
+		fs::create_dir_all(dir.join("x/y")).unwrap();
+		for file in ["a.ct", "x/b.ct", "x/y/c.ct", "x/y/.d.ct", "x/e.txt"].iter() {
+			fs::write(dir.join(file), "").unwrap();
+		}
+		#[cfg(unix)]
+		std::os::unix::fs::symlink("..", dir.join("x/up")).unwrap();
+
+		let found = match glob(vec![Some(Token::String(format!("{}/**/*.ct", dir.to_string_lossy())))]) {
+			Some(Token::Array(found)) => found.len(),
+			t => panic!("expected an array, received {:?}", t),
+		};
+		let _ = fs::remove_dir_all(&dir);
+		assert_eq!(found, 3);
+		assert!(matches!(join(Vec::new()), Some(Token::Error(_))));
+	}
+}
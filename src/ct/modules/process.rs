@@ -0,0 +1,241 @@
+use ct::utils::limits::Interrupt;
+use ct::utils::module::Module;
+use ct::utils::predefs::Predefs;
+use ct::syntax::std::*;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+pub struct Process;
+
+impl Module for Process {
+	fn extend(&self, predefs: &mut Predefs) {
+		let interrupt = predefs.interrupt();
+		predefs.insert(String::from("run"), Box::new(move |args| run(&interrupt, args)));
+	}
+}
+
+struct Options {
+	cwd: Option<String>,
+	env: Vec<(String, String)>,
+	stdin: Option<String>,
+	timeout: Option<Duration>,
+}
+
+// Recognised keys: `cwd`, `env` (a map of overrides), `stdin` and `timeout` in milliseconds
+fn parse_options(options: &BTreeMap<String, CtToken>) -> Result<Options, String> {
+	let mut parsed = Options { cwd: None, env: Vec::new(), stdin: None, timeout: None };
+
+	for (key, value) in options {
+		match (&key[..], value) {
+			("cwd", Some(Token::String(cwd))) => parsed.cwd = Some(cwd.clone()),
+			("stdin", Some(Token::String(input))) => parsed.stdin = Some(input.clone()),
+			("timeout", Some(Token::Integer(ms))) if *ms >= 0 => parsed.timeout = Some(Duration::from_millis(*ms as u64)),
+			("env", Some(Token::Map(vars))) => {
+				for (name, value) in vars {
+					match value {
+						Some(value) => parsed.env.push((name.clone(), value.to_string())),
+						None => return Err(format!("Environment variable {} has no value", name))
+					}
+				}
+			},
+			(key, value) => return Err(format!("Invalid process option {}: {}", key, match value { Some(t) => t.to_string(), None => String::from(types::NULL) }))
+		}
+	}
+
+	Ok(parsed)
+}
+
+// run(cmd), run(cmd, args) or run(cmd, args, options); returns a `Process` struct with `status`, `stdout` and `stderr`
+// and kills the child when the script is interrupted while it runs
+pub fn run(interrupt: &Interrupt, args: Vec<CtToken>) -> CtToken {
+	if args.is_empty() || args.len() > 3 || args.iter().any(|arg| arg.is_none()) { return Some(Token::Error(String::from("Expected a command with optional arguments and options"))); }
+
+	let program = match args[0].as_ref().unwrap() {
+		Token::String(program) => program.clone(),
+		t => return Some(Token::Error(format!("Expected command to be of type string, but received: {}", t)))
+	};
+
+	let mut command_args = Vec::new();
+	if args.len() > 1 {
+		match args[1].as_ref().unwrap() {
+			Token::Array(items) => {
+				for item in items {
+					match item {
+						Some(item) => command_args.push(item.to_string()),
+						None => return Some(Token::Error(String::from("Command arguments cannot be empty")))
+					}
+				}
+			},
+			t => return Some(Token::Error(format!("Expected command arguments to be an array, but received: {}", t)))
+		}
+	}
+
+	let options = if args.len() > 2 {
+		match args[2].as_ref().unwrap() {
+			Token::Map(options) => match parse_options(options) {
+				Ok(options) => options,
+				Err(e) => return Some(Token::Error(e))
+			},
+			t => return Some(Token::Error(format!("Expected process options to be a map, but received: {}", t)))
+		}
+	} else {
+		Options { cwd: None, env: Vec::new(), stdin: None, timeout: None }
+	};
+
+	let mut command = Command::new(&program);
+	command.args(&command_args)
+		.stdin(if options.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped());
+	if let Some(cwd) = &options.cwd {
+		command.current_dir(cwd);
+	}
+	for (name, value) in &options.env {
+		command.env(name, value);
+	}
+
+	let mut child = match command.spawn() {
+		Ok(child) => child,
+		Err(e) => return Some(Token::Error(format!("Unable to run {}: {}", program, e)))
+	};
+
+	// Pipes are drained on their own threads so a chatty child never blocks on a full buffer
+	let _stdin = options.stdin.clone().and_then(|input| child.stdin.take().map(|mut pipe| thread::spawn(move || { let _ = pipe.write_all(input.as_bytes()); })));
+	let stdout = drain(child.stdout.take());
+	let stderr = drain(child.stderr.take());
+
+	// The timeout and the interrupt cover the whole run, a background grandchild can keep the pipes open after the child exits
+	let started = Instant::now();
+	let expired = || -> CtToken {
+		if interrupt.is_interrupted() {
+			return Some(Token::Error(format!("{} was interrupted", program)));
+		}
+		match options.timeout {
+			Some(timeout) if started.elapsed() >= timeout => Some(Token::Error(format!("{} timed out after {} ms", program, timeout.as_millis()))),
+			_ => None
+		}
+	};
+
+	let status = loop {
+		match child.try_wait() {
+			Ok(Some(status)) => break status,
+			Ok(None) => {
+				if let Some(error) = expired() {
+					let _ = child.kill();
+					let _ = child.wait();
+					return Some(error);
+				}
+				thread::sleep(Duration::from_millis(1));
+			},
+			Err(e) => return Some(Token::Error(e.to_string()))
+		}
+	};
+
+	let collect = |output: Option<mpsc::Receiver<Vec<u8>>>| -> Result<String, Token> {
+		let output = match output {
+			Some(output) => output,
+			None => return Ok(String::new())
+		};
+		loop {
+			match output.recv_timeout(Duration::from_millis(1)) {
+				Ok(out) => return Ok(String::from_utf8_lossy(&out).into_owned()),
+				Err(mpsc::RecvTimeoutError::Disconnected) => return Ok(String::new()),
+				Err(mpsc::RecvTimeoutError::Timeout) => if let Some(error) = expired() { return Err(error); }
+			}
+		}
+	};
+
+	let mut fields = HashMap::new();
+	// Processes terminated by a signal have no exit code
+	fields.insert(String::from("status"), Some(Token::Integer(i64::from(status.code().unwrap_or(-1)))));
+	for (name, output) in [("stdout", stdout), ("stderr", stderr)] {
+		match collect(output) {
+			Ok(text) => fields.insert(String::from(name), Some(Token::String(text))),
+			Err(error) => return Some(error)
+		};
+	}
+
+	Some(Token::Structure { name: String::from("Process"), fields })
+}
+
+// Reads a pipe to its end on a thread of its own, so the caller can stop waiting for it
+fn drain<R: Read + Send + 'static>(pipe: Option<R>) -> Option<mpsc::Receiver<Vec<u8>>> {
+	pipe.map(|mut pipe| {
+		let (sender, receiver) = mpsc::channel();
+		thread::spawn(move || {
+			let mut out = Vec::new();
+			let _ = pipe.read_to_end(&mut out);
+			let _ = sender.send(out);
+		});
+		receiver
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn text(s: &str) -> CtToken {
+		Some(Token::String(String::from(s)))
+	}
+
+	fn shell(script: &str, options: Vec<(&str, CtToken)>) -> CtToken {
+		let options = options.into_iter().map(|(key, value)| (String::from(key), value)).collect();
+		run(&Interrupt::new(), vec![text("sh"), Some(Token::Array(vec![text("-c"), text(script)])), Some(Token::Map(options))])
+	}
+
+	fn field(process: &CtToken, name: &str) -> String {
+		match process {
+			Some(Token::Structure { fields, .. }) => fields[name].as_ref().unwrap().to_string(),
+			t => panic!("expected a process, received {:?}", t),
+		}
+	}
+
+	#[test]
+	fn captures_status_and_output() {
+		let process = shell("echo out; echo err >&2; exit 3", Vec::new());
+		assert_eq!(field(&process, "status"), "3");
+		assert_eq!(field(&process, "stdout"), "out\n");
+		assert_eq!(field(&process, "stderr"), "err\n");
+	}
+
+	#[test]
+	fn options_reach_the_child() {
+		let mut env = BTreeMap::new();
+		env.insert(String::from("CORTEN_PROCESS_TEST"), text("set"));
+		let process = shell("cat; echo $CORTEN_PROCESS_TEST; pwd", vec![
+			("stdin", text("in ")),
+			("env", Some(Token::Map(env))),
+			("cwd", text("/")),
+		]);
+		assert_eq!(field(&process, "stdout"), "in set\n/\n");
+	}
+
+	#[test]
+	fn failures_are_errors() {
+		let timed_out = shell("sleep 5", vec![("timeout", Some(Token::Integer(50)))]);
+		assert!(matches!(timed_out, Some(Token::Error(ref e)) if e.contains("timed out")));
+		let started = Instant::now();
+		let background = shell("sleep 3 & echo started", vec![("timeout", Some(Token::Integer(200)))]);
+		assert!(matches!(background, Some(Token::Error(ref e)) if e.contains("timed out")));
+		assert!(started.elapsed() < Duration::from_secs(2));
+		assert!(matches!(run(&Interrupt::new(), vec![text("corten-no-such-program")]), Some(Token::Error(_))));
+		assert!(matches!(shell("true", vec![("unknown", text("x"))]), Some(Token::Error(_))));
+		assert!(matches!(run(&Interrupt::new(), Vec::new()), Some(Token::Error(_))));
+	}
+
+	#[test]
+	fn interrupt_kills_the_child() {
+		let interrupt = Interrupt::new();
+		let _timer = interrupt.after(Duration::from_millis(50));
+		let started = Instant::now();
+		let process = run(&interrupt, vec![text("sleep"), Some(Token::Array(vec![text("5")]))]);
+		assert!(matches!(process, Some(Token::Error(ref e)) if e.contains("interrupted")));
+		assert!(started.elapsed() < Duration::from_secs(5));
+	}
+}
@@ -0,0 +1,219 @@
+use ct::utils::module::Module;
+use ct::utils::predefs::Predefs;
+use ct::syntax::std::*;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// SplitMix64, small and fully specified so a seed gives the same sequence on every machine
+pub struct Rng {
+	state: u64,
+}
+
+impl Rng {
+	pub fn new(seed: u64) -> Rng {
+		Rng { state: seed }
+	}
+
+	pub fn from_time() -> Rng {
+		let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+			Ok(duration) => duration.as_nanos() as u64,
+			Err(_) => 0,
+		};
+		Rng::new(nanos)
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+		let mut z = self.state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+		z ^ (z >> 31)
+	}
+
+	// Uniform float in [0, 1) built from the top 53 bits
+	pub fn next_f64(&mut self) -> f64 {
+		(self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+	}
+
+	// Uniform integer in [0, bound), rejecting the biased tail
+	pub fn below(&mut self, bound: u64) -> u64 {
+		let zone = u64::MAX - (u64::MAX % bound);
+		loop {
+			let value = self.next_u64();
+			if value < zone {
+				return value % bound;
+			}
+		}
+	}
+}
+
+pub struct Random;
+
+impl Module for Random {
+	fn extend(&self, predefs: &mut Predefs) {
+		let rng = Rc::new(RefCell::new(Rng::from_time()));
+
+		let state = rng.clone();
+		predefs.insert(String::from("seed"), Box::new(move |args| seed(&state, args)));
+		let state = rng.clone();
+		predefs.insert(String::from("rand"), Box::new(move |args| rand(&state, args)));
+		let state = rng.clone();
+		predefs.insert(String::from("rand_int"), Box::new(move |args| rand_int(&state, args)));
+		let state = rng.clone();
+		predefs.insert(String::from("choice"), Box::new(move |args| choice(&state, args)));
+		let state = rng.clone();
+		predefs.insert(String::from("shuffle"), Box::new(move |args| shuffle(&state, args)));
+		predefs.insert(String::from("sample"), Box::new(move |args| sample(&rng, args)));
+	}
+}
+
+pub fn seed(rng: &RefCell<Rng>, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 1 || args[0].is_none() { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	if let Token::Integer(n) = args[0].as_ref().unwrap() {
+		*rng.borrow_mut() = Rng::new(*n as u64);
+		None
+	} else {
+		Some(Token::Error(format!("Expected seed to be of type int, but received: {}", args[0].as_ref().unwrap())))
+	}
+}
+
+pub fn rand(rng: &RefCell<Rng>, args: Vec<CtToken>) -> CtToken {
+	if !args.is_empty() { return Some(Token::Error(String::from("Expected no arguments"))); }
+	Some(Token::Float(rng.borrow_mut().next_f64()))
+}
+
+// Both bounds are inclusive
+pub fn rand_int(rng: &RefCell<Rng>, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 2 || args[0].is_none() || args[1].is_none() { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	if let (Token::Integer(lo), Token::Integer(hi)) = (args[0].as_ref().unwrap(), args[1].as_ref().unwrap()) {
+		if lo > hi {
+			return Some(Token::Error(format!("Expected lower bound {} to be at most upper bound {}", lo, hi)));
+		}
+
+		// The span wraps for the full i64 range, in which case every u64 is a valid offset
+		let span = hi.wrapping_sub(*lo) as u64;
+		let offset = if span == u64::MAX { rng.borrow_mut().next_u64() } else { rng.borrow_mut().below(span + 1) };
+		Some(Token::Integer(lo.wrapping_add(offset as i64)))
+	} else {
+		Some(Token::Error(format!("Expected bounds to be of type int, but received: {} and {}", args[0].as_ref().unwrap(), args[1].as_ref().unwrap())))
+	}
+}
+
+pub fn choice(rng: &RefCell<Rng>, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 1 || args[0].is_none() { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	if let Token::Array(items) = args[0].as_ref().unwrap() {
+		if items.is_empty() {
+			return Some(Token::Error(String::from("Cannot choose from an empty array")));
+		}
+
+		let index = rng.borrow_mut().below(items.len() as u64) as usize;
+		items[index].clone()
+	} else {
+		Some(Token::Error(format!("Expected an array, but received: {}", args[0].as_ref().unwrap())))
+	}
+}
+
+pub fn shuffle(rng: &RefCell<Rng>, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 1 || args[0].is_none() { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	if let Token::Array(items) = args[0].as_ref().unwrap() {
+		let mut items = items.clone();
+		shuffle_slice(&mut rng.borrow_mut(), &mut items);
+		Some(Token::Array(items))
+	} else {
+		Some(Token::Error(format!("Expected an array, but received: {}", args[0].as_ref().unwrap())))
+	}
+}
+
+pub fn sample(rng: &RefCell<Rng>, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 2 || args[0].is_none() || args[1].is_none() { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	match (args[0].as_ref().unwrap(), args[1].as_ref().unwrap()) {
+		(Token::Array(items), Token::Integer(k)) => {
+			if *k < 0 || *k as usize > items.len() {
+				return Some(Token::Error(format!("Cannot sample {} elements from an array of length {}", k, items.len())));
+			}
+
+			let mut items = items.clone();
+			shuffle_slice(&mut rng.borrow_mut(), &mut items);
+			items.truncate(*k as usize);
+			Some(Token::Array(items))
+		},
+		(a, b) => Some(Token::Error(format!("Expected an array and an int, but received: {} and {}", a, b)))
+	}
+}
+
+// Fisher-Yates, walking down from the end
+fn shuffle_slice(rng: &mut Rng, items: &mut [CtToken]) {
+	for i in (1..items.len()).rev() {
+		let j = rng.below(i as u64 + 1) as usize;
+		items.swap(i, j);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn ints(values: &[i64]) -> CtToken {
+		Some(Token::Array(values.iter().map(|n| Some(Token::Integer(*n))).collect()))
+	}
+
+	#[test]
+	fn seeded_sequences_repeat() {
+		let (a, b) = (RefCell::new(Rng::new(7)), RefCell::new(Rng::new(7)));
+		for _ in 0..20 {
+			assert_eq!(a.borrow_mut().next_u64(), b.borrow_mut().next_u64());
+		}
+
+		let rng = RefCell::new(Rng::new(0));
+		seed(&rng, vec![Some(Token::Integer(7))]);
+		assert_eq!(rng.borrow_mut().next_u64(), Rng::new(7).next_u64());
+	}
+
+	#[test]
+	fn rand_int_stays_in_bounds() {
+		let rng = RefCell::new(Rng::new(1));
+		for _ in 0..200 {
+			match rand_int(&rng, vec![Some(Token::Integer(-3)), Some(Token::Integer(3))]) {
+				Some(Token::Integer(n)) => assert!((-3..=3).contains(&n)),
+				t => panic!("expected an int, received {:?}", t),
+			}
+		}
+		match rand_int(&rng, vec![Some(Token::Integer(i64::MIN)), Some(Token::Integer(i64::MAX))]) {
+			Some(Token::Integer(_)) => (),
+			t => panic!("expected an int, received {:?}", t),
+		}
+	}
+
+	#[test]
+	fn shuffle_and_sample_keep_items() {
+		let rng = RefCell::new(Rng::new(3));
+		let mut shuffled: Vec<String> = match shuffle(&rng, vec![ints(&[1, 2, 3, 4, 5])]) {
+			Some(Token::Array(items)) => items.iter().map(|t| t.as_ref().unwrap().to_string()).collect(),
+			t => panic!("expected an array, received {:?}", t),
+		};
+		shuffled.sort();
+		assert_eq!(shuffled, ["1", "2", "3", "4", "5"]);
+
+		match sample(&rng, vec![ints(&[1, 2, 3]), Some(Token::Integer(2))]) {
+			Some(Token::Array(items)) => assert_eq!(items.len(), 2),
+			t => panic!("expected an array, received {:?}", t),
+		}
+	}
+
+	#[test]
+	fn invalid_arguments_are_errors() {
+		let rng = RefCell::new(Rng::new(3));
+		let cases = vec![
+			rand_int(&rng, vec![Some(Token::Integer(2)), Some(Token::Integer(1))]),
+			choice(&rng, vec![ints(&[])]),
+			sample(&rng, vec![ints(&[1]), Some(Token::Integer(2))]),
+			seed(&rng, vec![Some(Token::String(String::from("x")))]),
+			rand(&rng, vec![Some(Token::Integer(1))]),
+		];
+		for case in cases {
+			assert!(matches!(case, Some(Token::Error(_))), "expected an error, received {:?}", case);
+		}
+	}
+}
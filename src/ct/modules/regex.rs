@@ -0,0 +1,388 @@
+use ct::utils::limits::Interrupt;
+use ct::utils::module::Module;
+use ct::utils::predefs::Predefs;
+use ct::utils::regex::{Captures, Regex};
+use ct::syntax::std::*;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+const CACHE_SIZE: usize = 64;
+
+pub struct RegexModule;
+
+impl Module for RegexModule {
+	fn extend(&self, predefs: &mut Predefs) {
+		let cache = Rc::new(RefCell::new(Compiled::new(predefs.interrupt())));
+
+		let compiled = cache.clone();
+		predefs.insert(String::from("regex"), Box::new(move |args| regex(&compiled, args)));
+		let compiled = cache.clone();
+		predefs.insert(String::from("regex_matches"), Box::new(move |args| regex_matches(&compiled, args)));
+		let compiled = cache.clone();
+		predefs.insert(String::from("regex_find"), Box::new(move |args| regex_find(&compiled, args)));
+		let compiled = cache.clone();
+		predefs.insert(String::from("regex_find_all"), Box::new(move |args| regex_find_all(&compiled, args)));
+		let compiled = cache.clone();
+		predefs.insert(String::from("regex_captures"), Box::new(move |args| regex_captures(&compiled, args)));
+		let compiled = cache.clone();
+		predefs.insert(String::from("regex_named_captures"), Box::new(move |args| regex_named_captures(&compiled, args)));
+		let compiled = cache.clone();
+		predefs.insert(String::from("regex_replace"), Box::new(move |args| regex_replace(&compiled, args, false)));
+		let compiled = cache.clone();
+		predefs.insert(String::from("regex_replace_all"), Box::new(move |args| regex_replace(&compiled, args, true)));
+		predefs.insert(String::from("regex_split"), Box::new(move |args| regex_split(&cache, args)));
+	}
+}
+
+// Recently compiled patterns, so a `Regex` struct or a pattern used in a loop is only compiled once
+pub struct Compiled {
+	regexes: HashMap<String, Rc<Regex>>,
+	interrupt: Interrupt,
+}
+
+impl Compiled {
+	// Searches with the compiled patterns stop when the script is interrupted
+	pub fn new(interrupt: Interrupt) -> Compiled {
+		Compiled { regexes: HashMap::new(), interrupt }
+	}
+
+	fn get(&mut self, pattern: &str) -> Result<Rc<Regex>, String> {
+		if let Some(regex) = self.regexes.get(pattern) {
+			return Ok(regex.clone());
+		}
+
+		let regex = Rc::new(Regex::new(pattern)?.interruptible(self.interrupt.clone()));
+		if self.regexes.len() >= CACHE_SIZE {
+			self.regexes.clear();
+		}
+		self.regexes.insert(String::from(pattern), regex.clone());
+		Ok(regex)
+	}
+}
+
+// Compiles a pattern into a `Regex` struct, or an error describing where the pattern is invalid
+pub fn regex(cache: &RefCell<Compiled>, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 1 || args[0].is_none() { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	if let Token::String(pattern) = args[0].as_ref().unwrap() {
+		match cache.borrow_mut().get(pattern) {
+			Ok(_) => {
+				let mut fields = HashMap::new();
+				fields.insert(String::from("pattern"), Some(Token::String(pattern.clone())));
+				Some(Token::Structure { name: String::from("Regex"), fields })
+			},
+			Err(e) => Some(Token::Error(e))
+		}
+	} else {
+		Some(Token::Error(format!("Expected pattern to be of type string, but received: {}", args[0].as_ref().unwrap())))
+	}
+}
+
+// Every native takes either a `Regex` struct or a pattern string first, followed by the text
+fn compile_args(cache: &RefCell<Compiled>, args: &[CtToken], count: usize) -> Result<(Rc<Regex>, Vec<char>), CtToken> {
+	if args.len() != count || args.iter().any(|arg| arg.is_none()) { return Err(Some(Token::Error(String::from("Not enough arguments provided")))); }
+
+	let pattern = match args[0].as_ref().unwrap() {
+		Token::String(pattern) => pattern.clone(),
+		Token::Structure { name, fields } if name == "Regex" => match fields.get("pattern") {
+			Some(Some(Token::String(pattern))) => pattern.clone(),
+			_ => return Err(Some(Token::Error(String::from("Regex has no pattern"))))
+		},
+		t => return Err(Some(Token::Error(format!("Expected a regex or pattern string, but received: {}", t))))
+	};
+
+	let text = match args[1].as_ref().unwrap() {
+		Token::String(text) => text.chars().collect(),
+		t => return Err(Some(Token::Error(format!("Expected text to be of type string, but received: {}", t))))
+	};
+
+	match cache.borrow_mut().get(&pattern) {
+		Ok(regex) => Ok((regex, text)),
+		Err(e) => Err(Some(Token::Error(e)))
+	}
+}
+
+fn slice(text: &[char], start: usize, end: usize) -> String {
+	text[start..end].iter().collect()
+}
+
+fn group_token(text: &[char], group: &Option<(usize, usize)>) -> CtToken {
+	match group {
+		Some((start, end)) => Some(Token::String(slice(text, *start, *end))),
+		None => Some(Token::Null)
+	}
+}
+
+// True when the pattern matches anywhere in the text; anchor with ^ and $ for a full match
+pub fn regex_matches(cache: &RefCell<Compiled>, args: Vec<CtToken>) -> CtToken {
+	match compile_args(cache, &args, 2) {
+		Ok((regex, text)) => match regex.captures_from(&text, 0) {
+			Ok(found) => Some(Token::Boolean(found.is_some())),
+			Err(e) => Some(Token::Error(e))
+		},
+		Err(e) => e
+	}
+}
+
+// A map with the matched `text` and its `start` and `end` char positions, or null
+pub fn regex_find(cache: &RefCell<Compiled>, args: Vec<CtToken>) -> CtToken {
+	match compile_args(cache, &args, 2) {
+		Ok((regex, text)) => match regex.captures_from(&text, 0) {
+			Ok(Some(captures)) => {
+				let (start, end) = captures[0].unwrap();
+				let mut found = BTreeMap::new();
+				found.insert(String::from("text"), Some(Token::String(slice(&text, start, end))));
+				found.insert(String::from("start"), Some(Token::Integer(start as i64)));
+				found.insert(String::from("end"), Some(Token::Integer(end as i64)));
+				Some(Token::Map(found))
+			},
+			Ok(None) => Some(Token::Null),
+			Err(e) => Some(Token::Error(e))
+		},
+		Err(e) => e
+	}
+}
+
+pub fn regex_find_all(cache: &RefCell<Compiled>, args: Vec<CtToken>) -> CtToken {
+	match compile_args(cache, &args, 2) {
+		Ok((regex, text)) => match regex.captures_all(&text) {
+			Ok(all) => Some(Token::Array(all.iter().map(|captures| group_token(&text, &captures[0])).collect())),
+			Err(e) => Some(Token::Error(e))
+		},
+		Err(e) => e
+	}
+}
+
+// The whole match followed by each group, unmatched groups are null; null when nothing matches
+pub fn regex_captures(cache: &RefCell<Compiled>, args: Vec<CtToken>) -> CtToken {
+	match compile_args(cache, &args, 2) {
+		Ok((regex, text)) => match regex.captures_from(&text, 0) {
+			Ok(Some(captures)) => Some(Token::Array(captures.iter().map(|group| group_token(&text, group)).collect())),
+			Ok(None) => Some(Token::Null),
+			Err(e) => Some(Token::Error(e))
+		},
+		Err(e) => e
+	}
+}
+
+// Named groups, `(?<name>...)` or `(?P<name>...)`, keyed by name
+pub fn regex_named_captures(cache: &RefCell<Compiled>, args: Vec<CtToken>) -> CtToken {
+	match compile_args(cache, &args, 2) {
+		Ok((regex, text)) => match regex.captures_from(&text, 0) {
+			Ok(Some(captures)) => Some(Token::Map(regex.names().iter().map(|(name, index)| (name.clone(), group_token(&text, &captures[*index]))).collect())),
+			Ok(None) => Some(Token::Null),
+			Err(e) => Some(Token::Error(e))
+		},
+		Err(e) => e
+	}
+}
+
+// Replacements may refer to groups with $1, ${1} or ${name}; $$ is a literal dollar sign
+fn expand(replacement: &[char], regex: &Regex, text: &[char], captures: &Captures) -> Result<String, String> {
+	let mut out = String::new();
+	let mut i = 0;
+
+	while i < replacement.len() {
+		if replacement[i] != '$' {
+			out.push(replacement[i]);
+			i += 1;
+			continue;
+		}
+
+		i += 1;
+		let reference: String = match replacement.get(i) {
+			Some('$') => {
+				out.push('$');
+				i += 1;
+				continue;
+			},
+			Some('{') => {
+				let end = match replacement[i..].iter().position(|c| *c == '}') {
+					Some(end) => i + end,
+					None => return Err(String::from("Unterminated ${ in replacement"))
+				};
+				let name = replacement[i + 1..end].iter().collect();
+				i = end + 1;
+				name
+			},
+			Some(c) if c.is_ascii_digit() => {
+				let start = i;
+				while i < replacement.len() && replacement[i].is_ascii_digit() {
+					i += 1;
+				}
+				replacement[start..i].iter().collect()
+			},
+			_ => {
+				out.push('$');
+				continue;
+			}
+		};
+
+		let index = match reference.parse::<usize>() {
+			Ok(index) => index,
+			Err(_) => match regex.names().iter().find(|(name, _)| *name == reference) {
+				Some((_, index)) => *index,
+				None => return Err(format!("Unknown group in replacement: {}", reference))
+			}
+		};
+
+		if index > regex.groups() {
+			return Err(format!("Unknown group in replacement: {}", reference));
+		}
+		if let Some((start, end)) = captures[index] {
+			out.push_str(&slice(text, start, end));
+		}
+	}
+
+	Ok(out)
+}
+
+pub fn regex_replace(cache: &RefCell<Compiled>, args: Vec<CtToken>, all: bool) -> CtToken {
+	if args.len() != 3 { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	let replacement: Vec<char> = match &args[2] {
+		Some(Token::String(replacement)) => replacement.chars().collect(),
+		t => return Some(Token::Error(format!("Expected replacement to be of type string, but received: {}", match t { Some(t) => t.to_string(), None => String::from(types::NULL) })))
+	};
+
+	let (regex, text) = match compile_args(cache, &args[..2], 2) {
+		Ok(compiled) => compiled,
+		Err(e) => return e
+	};
+
+	let matches = if all {
+		regex.captures_all(&text)
+	} else {
+		regex.captures_from(&text, 0).map(|found| found.into_iter().collect())
+	};
+
+	let matches = match matches {
+		Ok(matches) => matches,
+		Err(e) => return Some(Token::Error(e))
+	};
+
+	let mut out = String::new();
+	let mut last = 0;
+	for captures in &matches {
+		let (start, end) = captures[0].unwrap();
+		out.push_str(&slice(&text, last, start));
+		match expand(&replacement, &regex, &text, captures) {
+			Ok(expanded) => out.push_str(&expanded),
+			Err(e) => return Some(Token::Error(e))
+		}
+		last = end;
+	}
+	out.push_str(&slice(&text, last, text.len()));
+
+	Some(Token::String(out))
+}
+
+pub fn regex_split(cache: &RefCell<Compiled>, args: Vec<CtToken>) -> CtToken {
+	match compile_args(cache, &args, 2) {
+		Ok((regex, text)) => match regex.captures_all(&text) {
+			Ok(all) => {
+				let mut parts = Vec::new();
+				let mut last = 0;
+				for captures in &all {
+					let (start, end) = captures[0].unwrap();
+					// An empty match at the very start or end does not produce an empty piece
+					if end == start && (start == 0 || start == text.len()) {
+						continue;
+					}
+					parts.push(Some(Token::String(slice(&text, last, start))));
+					last = end;
+				}
+				parts.push(Some(Token::String(slice(&text, last, text.len()))));
+				Some(Token::Array(parts))
+			},
+			Err(e) => Some(Token::Error(e))
+		},
+		Err(e) => e
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn text(s: &str) -> CtToken {
+		Some(Token::String(String::from(s)))
+	}
+
+	fn call(native: fn(&RefCell<Compiled>, Vec<CtToken>) -> CtToken, args: &[&str]) -> String {
+		native(&RefCell::new(Compiled::new(Interrupt::new())), args.iter().map(|s| text(s)).collect()).unwrap().to_string()
+	}
+
+	fn assert_error(result: CtToken) {
+		assert!(matches!(result, Some(Token::Error(_))), "expected an error, received {:?}", result);
+	}
+
+	#[test]
+	fn searches() {
+		assert_eq!(call(regex_matches, &["^a+b$", "aaab"]), "true");
+		assert_eq!(call(regex_matches, &["(?i)HELLO", "say hello"]), "true");
+		assert_eq!(call(regex_find, &["\\d+", "ab 42 c"]), r#"{"end": 5, "start": 3, "text": "42"}"#);
+		assert_eq!(call(regex_find_all, &["\\w+", "one, two three"]), call(regex_split, &["[, ]+", "one, two three"]));
+		assert_eq!(call(regex_captures, &["(\\w)(\\d)?", "x"]), Token::Array(vec![text("x"), text("x"), Some(Token::Null)]).to_string());
+		assert_eq!(call(regex_named_captures, &["(?<y>\\d{4})-(?P<m>\\d\\d)", "on 2024-05"]), r#"{"m": "05", "y": "2024"}"#);
+		assert_eq!(call(regex_matches, &["(a+)b\\1", "aabaa"]), "true");
+	}
+
+	#[test]
+	fn replacements() {
+		let compiled = RefCell::new(Compiled::new(Interrupt::new()));
+		let replace = |args: &[&str], all| regex_replace(&compiled, args.iter().map(|s| text(s)).collect(), all).unwrap().to_string();
+		assert_eq!(replace(&["(\\w+)@(?<host>\\w+)", "a@b c@d", "${host}:$1$$"], true), "b:a$ d:c$");
+		assert_eq!(replace(&["o", "foo", "0"], false), "f0o");
+		assert_error(regex_replace(&compiled, vec![text("a"), text("a"), text("$2")], true));
+	}
+
+	#[test]
+	fn compiled_patterns_are_cached() {
+		let mut compiled = Compiled::new(Interrupt::new());
+		let first = compiled.get("a|b").unwrap();
+		assert!(Rc::ptr_eq(&first, &compiled.get("a|b").unwrap()));
+		assert!(compiled.get("(").is_err());
+	}
+
+	#[test]
+	fn large_programs_are_an_error() {
+		let compiled = RefCell::new(Compiled::new(Interrupt::new()));
+		assert_error(regex_matches(&compiled, vec![text("((a{1000}){1000}){100}"), text("a")]));
+		assert_error(regex(&compiled, vec![text("(a{1000}){200}")]));
+		assert_eq!(regex_matches(&compiled, vec![text("a{1000}"), text("a")]).unwrap().to_string(), "false");
+	}
+
+	#[test]
+	fn long_inputs_search_quickly() {
+		let long = "ab ".repeat(20_000);
+		let compiled = RefCell::new(Compiled::new(Interrupt::new()));
+		match regex_find_all(&compiled, vec![text("\\w+"), text(&long)]) {
+			Some(Token::Array(words)) => assert_eq!(words.len(), 20_000),
+			t => panic!("expected an array, received {:?}", t),
+		}
+		match regex_split(&compiled, vec![text(" "), text(&long)]) {
+			Some(Token::Array(parts)) => assert_eq!(parts.len(), 20_001),
+			t => panic!("expected an array, received {:?}", t),
+		}
+	}
+
+	#[test]
+	fn interrupted_searches_are_an_error() {
+		let interrupt = Interrupt::new();
+		let compiled = RefCell::new(Compiled::new(interrupt.clone()));
+		let long = "ab ".repeat(100_000);
+		interrupt.interrupt();
+		assert_error(regex_find_all(&compiled, vec![text("\\w+"), text(&long)]));
+		assert_error(regex_matches(&compiled, vec![text("(a|b)*c"), text(&long)]));
+	}
+
+	#[test]
+	fn malformed_patterns_are_an_error() {
+		let compiled = RefCell::new(Compiled::new(Interrupt::new()));
+		for pattern in ["(", "a)", "[b-a]", "*", "a{2,1}", "\\2(a)"].iter() {
+			assert_error(regex(&compiled, vec![text(pattern)]));
+		}
+		assert_error(regex_find(&compiled, vec![text("a"), Some(Token::Integer(1))]));
+	}
+}
@@ -13,7 +13,7 @@ impl Module for Str {
 pub fn len(args: Vec<CtToken>) -> CtToken {
 	if args.len() != 1 || args[0].is_none() { return Some(Token::Error(String::from("Not enough arguments provided"))); }
 	if let Token::String(s) = args[0].as_ref().unwrap() {
-        Some(Token::Integer(s.len() as i32))
+        Some(Token::Integer(s.len() as i64))
     } else {
 		Some(Token::Error(format!("Expected structure that has function length, but received: {}", args[0].as_ref().unwrap())))
 	}
@@ -0,0 +1,580 @@
+use ct::utils::limits::Interrupt;
+use ct::utils::module::Module;
+use ct::utils::predefs::Predefs;
+use ct::syntax::std::*;
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+const MS_PER_DAY: i64 = 86_400_000;
+// DateTimes stay within 100 million days of the epoch, so calendar math cannot overflow
+const MAX_MILLIS: i64 = 100_000_000 * MS_PER_DAY;
+const MAX_OFFSET: i64 = 24 * 60;
+
+const MONTHS: [&str; 12] = ["January", "February", "March", "April", "May", "June", "July", "August", "September", "October", "November", "December"];
+const WEEKDAYS: [&str; 7] = ["Sunday", "Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday"];
+
+const COMPONENTS: [&str; 9] = ["year", "month", "day", "hour", "minute", "second", "millisecond", "weekday", "yearday"];
+
+pub struct Time;
+
+impl Module for Time {
+	fn extend(&self, predefs: &mut Predefs) {
+		let start = Instant::now();
+
+		predefs.insert(String::from("now"), Box::new(now));
+		predefs.insert(String::from("clock"), Box::new(move |args| clock(start, args)));
+		let interrupt = predefs.interrupt();
+		predefs.insert(String::from("sleep"), Box::new(move |args| sleep(&interrupt, args)));
+
+		predefs.insert(String::from("seconds"), Box::new(|args| duration(1_000, args)));
+		predefs.insert(String::from("minutes"), Box::new(|args| duration(60_000, args)));
+		predefs.insert(String::from("hours"), Box::new(|args| duration(3_600_000, args)));
+		predefs.insert(String::from("days"), Box::new(|args| duration(MS_PER_DAY, args)));
+
+		predefs.insert(String::from("datetime"), Box::new(datetime));
+		predefs.insert(String::from("datetime_now"), Box::new(datetime_now));
+		predefs.insert(String::from("datetime_from_millis"), Box::new(datetime_from_millis));
+		predefs.insert(String::from("datetime_parse"), Box::new(datetime_parse));
+		predefs.insert(String::from("datetime_format"), Box::new(datetime_format));
+		predefs.insert(String::from("datetime_add"), Box::new(datetime_add));
+		predefs.insert(String::from("datetime_sub"), Box::new(datetime_sub));
+		predefs.insert(String::from("datetime_with_offset"), Box::new(datetime_with_offset));
+
+		for component in COMPONENTS.iter() {
+			predefs.insert(format!("datetime_{}", component), Box::new(move |args| datetime_component(component, args)));
+		}
+	}
+}
+
+fn epoch_millis() -> i64 {
+	match SystemTime::now().duration_since(UNIX_EPOCH) {
+		Ok(duration) => duration.as_millis() as i64,
+		Err(e) => -(e.duration().as_millis() as i64),
+	}
+}
+
+fn int_args(args: &[CtToken], min: usize, max: usize) -> Result<Vec<i64>, CtToken> {
+	if args.len() < min || args.len() > max { return Err(Some(Token::Error(String::from("Not enough arguments provided")))); }
+
+	let mut ints = Vec::new();
+	for arg in args {
+		match arg {
+			Some(Token::Integer(n)) => ints.push(*n),
+			Some(t) => return Err(Some(Token::Error(format!("Expected argument to be of type int, but received: {}", t)))),
+			None => return Err(Some(Token::Error(String::from("Not enough arguments provided"))))
+		}
+	}
+
+	Ok(ints)
+}
+
+// Milliseconds since the Unix epoch
+pub fn now(args: Vec<CtToken>) -> CtToken {
+	if !args.is_empty() { return Some(Token::Error(String::from("Expected no arguments"))); }
+	Some(Token::Integer(epoch_millis()))
+}
+
+// Monotonic milliseconds since the interpreter started, only meaningful as a difference
+pub fn clock(start: Instant, args: Vec<CtToken>) -> CtToken {
+	if !args.is_empty() { return Some(Token::Error(String::from("Expected no arguments"))); }
+	Some(Token::Float(start.elapsed().as_secs_f64() * 1000.0))
+}
+
+// Wakes up early when the script is interrupted
+pub fn sleep(interrupt: &Interrupt, args: Vec<CtToken>) -> CtToken {
+	match int_args(&args, 1, 1) {
+		Ok(ms) if ms[0] >= 0 => {
+			if interrupt.sleep(Duration::from_millis(ms[0] as u64)) { None } else { Some(Token::Error(String::from("Sleep interrupted"))) }
+		},
+		Ok(ms) => Some(Token::Error(format!("Cannot sleep for a negative duration: {}", ms[0]))),
+		Err(e) => e
+	}
+}
+
+// Durations are plain millisecond ints, so `dt.add(days(2) + hours(3))` works
+fn duration(unit: i64, args: Vec<CtToken>) -> CtToken {
+	match args.first() {
+		Some(Some(Token::Integer(n))) if args.len() == 1 => match n.checked_mul(unit) {
+			Some(ms) => Some(Token::Integer(ms)),
+			None => Some(Token::Error(format!("Duration overflow: {}", n)))
+		},
+		Some(Some(Token::Float(n))) if args.len() == 1 => Some(Token::Integer((n * unit as f64).round() as i64)),
+		_ => Some(Token::Error(String::from("Expected a single number")))
+	}
+}
+
+// Days since 1970-01-01 for a proleptic Gregorian date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	let y = if month <= 2 { year - 1 } else { year };
+	let era = if y >= 0 { y } else { y - 399 } / 400;
+	let yoe = y - era * 400;
+	let mp = (month + 9) % 12;
+	let doy = (153 * mp + 2) / 5 + day - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	era * 146_097 + doe - 719_468
+}
+
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+	let z = days + 719_468;
+	let era = if z >= 0 { z } else { z - 146_096 } / 146_097;
+	let doe = z - era * 146_097;
+	let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	(if month <= 2 { yoe + era * 400 + 1 } else { yoe + era * 400 }, month, day)
+}
+
+fn is_leap(year: i64) -> bool {
+	(year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+}
+
+fn days_in_month(year: i64, month: i64) -> i64 {
+	match month {
+		2 => if is_leap(year) { 29 } else { 28 },
+		4 | 6 | 9 | 11 => 30,
+		_ => 31,
+	}
+}
+
+// Broken-down wall clock time at a fixed offset from UTC
+struct Civil {
+	year: i64,
+	month: i64,
+	day: i64,
+	hour: i64,
+	minute: i64,
+	second: i64,
+	millisecond: i64,
+	weekday: i64,
+	yearday: i64,
+}
+
+impl Civil {
+	fn from_millis(millis: i64, offset: i64) -> Civil {
+		let local = millis + offset * 60_000;
+		let days = local.div_euclid(MS_PER_DAY);
+		let ms = local.rem_euclid(MS_PER_DAY);
+		let (year, month, day) = civil_from_days(days);
+
+		Civil {
+			year,
+			month,
+			day,
+			hour: ms / 3_600_000,
+			minute: ms / 60_000 % 60,
+			second: ms / 1000 % 60,
+			millisecond: ms % 1000,
+			// 1970-01-01 was a Thursday
+			weekday: (days + 4).rem_euclid(7),
+			yearday: days - days_from_civil(year, 1, 1) + 1,
+		}
+	}
+
+	fn get(&self, component: &str) -> i64 {
+		match component {
+			"year" => self.year,
+			"month" => self.month,
+			"day" => self.day,
+			"hour" => self.hour,
+			"minute" => self.minute,
+			"second" => self.second,
+			"millisecond" => self.millisecond,
+			"weekday" => self.weekday,
+			_ => self.yearday,
+		}
+	}
+}
+
+fn in_range(millis: i64) -> Result<i64, String> {
+	if millis.unsigned_abs() > MAX_MILLIS as u64 { return Err(format!("DateTime out of range: {} milliseconds", millis)); }
+	Ok(millis)
+}
+
+fn to_millis(year: i64, month: i64, day: i64, hour: i64, minute: i64, second: i64, millisecond: i64) -> Result<i64, String> {
+	if year.unsigned_abs() > 300_000 { return Err(format!("Year out of range: {}", year)); }
+	if !(1..=12).contains(&month) { return Err(format!("Invalid month: {}", month)); }
+	if !(1..=days_in_month(year, month)).contains(&day) { return Err(format!("Invalid day: {}", day)); }
+	if !(0..=23).contains(&hour) { return Err(format!("Invalid hour: {}", hour)); }
+	if !(0..=59).contains(&minute) { return Err(format!("Invalid minute: {}", minute)); }
+	if !(0..=59).contains(&second) { return Err(format!("Invalid second: {}", second)); }
+	if !(0..=999).contains(&millisecond) { return Err(format!("Invalid millisecond: {}", millisecond)); }
+
+	in_range(days_from_civil(year, month, day) * MS_PER_DAY + ((hour * 60 + minute) * 60 + second) * 1000 + millisecond)
+}
+
+fn offset_error(t: &CtToken) -> CtToken {
+	Some(Token::Error(format!("Expected an offset in minutes under a day, but received: {}", match t { Some(t) => t.to_string(), None => String::from(types::NULL) })))
+}
+
+fn checked_datetime(millis: Option<i64>, offset: i64) -> CtToken {
+	match millis.ok_or_else(|| String::from("DateTime out of range")).and_then(in_range) {
+		Ok(millis) => new_datetime(millis, offset),
+		Err(e) => Some(Token::Error(e))
+	}
+}
+
+fn new_datetime(millis: i64, offset: i64) -> CtToken {
+	let mut fields = HashMap::new();
+	fields.insert(String::from("millis"), Some(Token::Integer(millis)));
+	fields.insert(String::from("offset"), Some(Token::Integer(offset)));
+	Some(Token::Structure { name: String::from("DateTime"), fields })
+}
+
+// (millis, offset in minutes east of UTC)
+fn unpack(token: &CtToken) -> Result<(i64, i64), CtToken> {
+	if let Some(Token::Structure { name, fields }) = token {
+		if name == "DateTime" {
+			if let (Some(Some(Token::Integer(millis))), Some(Some(Token::Integer(offset)))) = (fields.get("millis"), fields.get("offset")) {
+				if millis.unsigned_abs() <= MAX_MILLIS as u64 && offset.unsigned_abs() < MAX_OFFSET as u64 {
+					return Ok((*millis, *offset));
+				}
+			}
+		}
+	}
+
+	Err(Some(Token::Error(format!("Expected a DateTime, but received: {}", match token { Some(t) => t.to_string(), None => String::from(types::NULL) }))))
+}
+
+// datetime(year, month, day [, hour, minute, second, millisecond]) in UTC
+pub fn datetime(args: Vec<CtToken>) -> CtToken {
+	let mut parts = match int_args(&args, 3, 7) {
+		Ok(parts) => parts,
+		Err(e) => return e
+	};
+	parts.resize(7, 0);
+
+	match to_millis(parts[0], parts[1], parts[2], parts[3], parts[4], parts[5], parts[6]) {
+		Ok(millis) => new_datetime(millis, 0),
+		Err(e) => Some(Token::Error(e))
+	}
+}
+
+pub fn datetime_now(args: Vec<CtToken>) -> CtToken {
+	if !args.is_empty() { return Some(Token::Error(String::from("Expected no arguments"))); }
+	new_datetime(epoch_millis(), 0)
+}
+
+// datetime_from_millis(millis [, offset_minutes])
+pub fn datetime_from_millis(args: Vec<CtToken>) -> CtToken {
+	match int_args(&args, 1, 2) {
+		Ok(parts) => match parts.get(1) {
+			Some(offset) if offset.unsigned_abs() >= MAX_OFFSET as u64 => offset_error(&args[1]),
+			offset => checked_datetime(Some(parts[0]), *offset.unwrap_or(&0))
+		},
+		Err(e) => e
+	}
+}
+
+pub fn datetime_component(component: &str, args: Vec<CtToken>) -> CtToken {
+	if args.len() != 1 { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	match unpack(&args[0]) {
+		Ok((millis, offset)) => Some(Token::Integer(Civil::from_millis(millis, offset).get(component))),
+		Err(e) => e
+	}
+}
+
+// A DateTime shifted by a duration in milliseconds
+pub fn datetime_add(args: Vec<CtToken>) -> CtToken {
+	if args.len() != 2 { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	match (unpack(&args[0]), &args[1]) {
+		(Ok((millis, offset)), Some(Token::Integer(ms))) => checked_datetime(millis.checked_add(*ms), offset),
+		(Err(e), _) => e,
+		(_, t) => Some(Token::Error(format!("Expected a duration in milliseconds, but received: {}", match t { Some(t) => t.to_string(), None => String::from(types::NULL) })))
+	}
+}
+
+// Subtracting a DateTime yields the duration between them, subtracting a duration yields a DateTime
+pub fn datetime_sub(args: Vec<CtToken>) -> CtToken {
+	if args.len() != 2 { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	let (millis, offset) = match unpack(&args[0]) {
+		Ok(unpacked) => unpacked,
+		Err(e) => return e
+	};
+
+	match &args[1] {
+		Some(Token::Integer(ms)) => checked_datetime(millis.checked_sub(*ms), offset),
+		other => match unpack(other) {
+			Ok((other, _)) => Some(Token::Integer(millis - other)),
+			Err(e) => e
+		}
+	}
+}
+
+// The same instant viewed at another offset; an offset of 0 is UTC
+pub fn datetime_with_offset(args: Vec<CtToken>) -> CtToken {
+	if args.len() != 2 { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	match (unpack(&args[0]), &args[1]) {
+		(Ok((millis, _)), Some(Token::Integer(offset))) if offset.unsigned_abs() < MAX_OFFSET as u64 => new_datetime(millis, *offset),
+		(Err(e), _) => e,
+		(_, t) => offset_error(t)
+	}
+}
+
+fn format_offset(offset: i64) -> String {
+	format!("{}{:02}:{:02}", if offset < 0 { '-' } else { '+' }, offset.abs() / 60, offset.abs() % 60)
+}
+
+// strftime-style: %Y %y %m %d %e %H %I %M %S %f (milliseconds) %p %z %a %A %b %B %j %u %w %s %%
+pub fn datetime_format(args: Vec<CtToken>) -> CtToken {
+	if args.len() != 2 { return Some(Token::Error(String::from("Not enough arguments provided"))); }
+	let (millis, offset) = match unpack(&args[0]) {
+		Ok(unpacked) => unpacked,
+		Err(e) => return e
+	};
+	let pattern = match &args[1] {
+		Some(Token::String(pattern)) => pattern,
+		t => return Some(Token::Error(format!("Expected pattern to be of type string, but received: {}", match t { Some(t) => t.to_string(), None => String::from(types::NULL) })))
+	};
+
+	let c = Civil::from_millis(millis, offset);
+	let mut out = String::new();
+	let mut chars = pattern.chars();
+
+	while let Some(ch) = chars.next() {
+		if ch != '%' {
+			out.push(ch);
+			continue;
+		}
+
+		match chars.next() {
+			Some('Y') => out.push_str(&format!("{:04}", c.year)),
+			Some('y') => out.push_str(&format!("{:02}", c.year.rem_euclid(100))),
+			Some('m') => out.push_str(&format!("{:02}", c.month)),
+			Some('d') => out.push_str(&format!("{:02}", c.day)),
+			Some('e') => out.push_str(&format!("{:2}", c.day)),
+			Some('H') => out.push_str(&format!("{:02}", c.hour)),
+			Some('I') => out.push_str(&format!("{:02}", if c.hour % 12 == 0 { 12 } else { c.hour % 12 })),
+			Some('M') => out.push_str(&format!("{:02}", c.minute)),
+			Some('S') => out.push_str(&format!("{:02}", c.second)),
+			Some('f') => out.push_str(&format!("{:03}", c.millisecond)),
+			Some('p') => out.push_str(if c.hour < 12 { "AM" } else { "PM" }),
+			Some('z') => out.push_str(&format_offset(offset)),
+			Some('a') => out.push_str(&WEEKDAYS[c.weekday as usize][..3]),
+			Some('A') => out.push_str(WEEKDAYS[c.weekday as usize]),
+			Some('b') => out.push_str(&MONTHS[c.month as usize - 1][..3]),
+			Some('B') => out.push_str(MONTHS[c.month as usize - 1]),
+			Some('j') => out.push_str(&format!("{:03}", c.yearday)),
+			Some('u') => out.push_str(&(if c.weekday == 0 { 7 } else { c.weekday }).to_string()),
+			Some('w') => out.push_str(&c.weekday.to_string()),
+			Some('s') => out.push_str(&millis.div_euclid(1000).to_string()),
+			Some('%') => out.push('%'),
+			Some(other) => return Some(Token::Error(format!("Unknown format directive: %{}", other))),
+			None => return Some(Token::Error(String::from("Format pattern ends with a lone %")))
+		}
+	}
+
+	Some(Token::String(out))
+}
+
+struct Scanner {
+	chars: Vec<char>,
+	index: usize,
+}
+
+impl Scanner {
+	fn number(&mut self, max_digits: usize, signed: bool) -> Result<i64, String> {
+		let start = self.index;
+		if signed && self.index < self.chars.len() && (self.chars[self.index] == '-' || self.chars[self.index] == '+') {
+			self.index += 1;
+		}
+		let digits = self.index;
+		while self.index < self.chars.len() && self.index - digits < max_digits && self.chars[self.index].is_ascii_digit() {
+			self.index += 1;
+		}
+		if self.index == digits {
+			return Err(format!("Expected a number at position {}", start + 1));
+		}
+		let text: String = self.chars[start..self.index].iter().collect();
+		text.parse::<i64>().map_err(|_| format!("Invalid number {}", text))
+	}
+
+	fn name(&mut self, names: &[&str], abbreviated: bool) -> Result<i64, String> {
+		let rest: String = self.chars[self.index..].iter().collect::<String>().to_lowercase();
+		for (i, name) in names.iter().enumerate() {
+			let name = if abbreviated { &name[..3] } else { name };
+			if rest.starts_with(&name.to_lowercase()) {
+				self.index += name.chars().count();
+				return Ok(i as i64);
+			}
+		}
+		Err(format!("Expected a name at position {}", self.index + 1))
+	}
+
+	fn offset(&mut self) -> Result<i64, String> {
+		if self.index < self.chars.len() && (self.chars[self.index] == 'Z' || self.chars[self.index] == 'z') {
+			self.index += 1;
+			return Ok(0);
+		}
+
+		let sign = match self.chars.get(self.index) {
+			Some('+') => 1,
+			Some('-') => -1,
+			_ => return Err(format!("Expected an offset at position {}", self.index + 1))
+		};
+		self.index += 1;
+		let hours = self.number(2, false)?;
+		if self.chars.get(self.index) == Some(&':') {
+			self.index += 1;
+		}
+		let minutes = self.number(2, false)?;
+		if minutes > 59 || hours * 60 + minutes >= MAX_OFFSET {
+			return Err(format!("Invalid offset {:02}:{:02}", hours, minutes));
+		}
+		Ok(sign * (hours * 60 + minutes))
+	}
+}
+
+// Uses the directives of datetime_format; missing date parts default to 1970-01-01 and the offset to UTC
+pub fn datetime_parse(args: Vec<CtToken>) -> CtToken {
+	let (text, pattern) = match (args.first(), args.get(1)) {
+		(Some(Some(Token::String(text))), Some(Some(Token::String(pattern)))) if args.len() == 2 => (text, pattern),
+		_ => return Some(Token::Error(String::from("Expected text and a pattern of type string")))
+	};
+
+	let mut scanner = Scanner { chars: text.chars().collect(), index: 0 };
+	let (mut year, mut month, mut day, mut hour, mut minute, mut second, mut millisecond, mut offset) = (1970, 1, 1, 0, 0, 0, 0, 0);
+	let mut pm = None;
+	let mut pattern = pattern.chars();
+
+	while let Some(ch) = pattern.next() {
+		let result = if ch != '%' {
+			if scanner.chars.get(scanner.index) == Some(&ch) {
+				scanner.index += 1;
+				Ok(())
+			} else {
+				Err(format!("Expected '{}' at position {}", ch, scanner.index + 1))
+			}
+		} else {
+			match pattern.next() {
+				Some('Y') => scanner.number(4, true).map(|n| year = n),
+				Some('y') => scanner.number(2, false).map(|n| year = if n < 69 { 2000 + n } else { 1900 + n }),
+				Some('m') => scanner.number(2, false).map(|n| month = n),
+				Some('d') | Some('e') => {
+					if scanner.chars.get(scanner.index) == Some(&' ') { scanner.index += 1; }
+					scanner.number(2, false).map(|n| day = n)
+				},
+				Some('H') | Some('I') => scanner.number(2, false).map(|n| hour = n),
+				Some('M') => scanner.number(2, false).map(|n| minute = n),
+				Some('S') => scanner.number(2, false).map(|n| second = n),
+				Some('f') => scanner.number(3, false).map(|n| millisecond = n),
+				Some('p') => scanner.name(&["AM", "PM"], false).map(|n| pm = Some(n == 1)),
+				Some('z') => scanner.offset().map(|n| offset = n),
+				Some('a') => scanner.name(&WEEKDAYS, true).map(|_| ()),
+				Some('A') => scanner.name(&WEEKDAYS, false).map(|_| ()),
+				Some('b') => scanner.name(&MONTHS, true).map(|n| month = n + 1),
+				Some('B') => scanner.name(&MONTHS, false).map(|n| month = n + 1),
+				Some('%') => if scanner.chars.get(scanner.index) == Some(&'%') { scanner.index += 1; Ok(()) } else { Err(String::from("Expected '%'")) },
+				Some(other) => Err(format!("Unknown parse directive: %{}", other)),
+				None => Err(String::from("Parse pattern ends with a lone %"))
+			}
+		};
+
+		if let Err(e) = result {
+			return Some(Token::Error(format!("Unable to parse \"{}\": {}", text, e)));
+		}
+	}
+
+	if scanner.index != scanner.chars.len() {
+		return Some(Token::Error(format!("Unable to parse \"{}\": unexpected trailing text at position {}", text, scanner.index + 1)));
+	}
+
+	if let Some(pm) = pm {
+		if !(1..=12).contains(&hour) {
+			return Some(Token::Error(format!("Invalid 12-hour clock hour: {}", hour)));
+		}
+		hour = hour % 12 + if pm { 12 } else { 0 };
+	}
+
+	match to_millis(year, month, day, hour, minute, second, millisecond) {
+		Ok(local) => checked_datetime(Some(local - offset * 60_000), offset),
+		Err(e) => Some(Token::Error(e))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn int(n: i64) -> CtToken {
+		Some(Token::Integer(n))
+	}
+
+	fn text(s: &str) -> CtToken {
+		Some(Token::String(String::from(s)))
+	}
+
+	fn formatted(dt: CtToken) -> String {
+		datetime_format(vec![dt, text("%Y-%m-%d %H:%M:%S.%f %z")]).unwrap().to_string()
+	}
+
+	fn assert_error(result: CtToken) {
+		assert!(matches!(result, Some(Token::Error(_))), "expected an error, received {:?}", result);
+	}
+
+	#[test]
+	fn calendar_round_trip() {
+		let dt = datetime(vec![int(2024), int(2), int(29), int(23), int(59), int(58), int(7)]);
+		assert_eq!(formatted(dt.clone()), "2024-02-29 23:59:58.007 +00:00");
+		assert_eq!(datetime_weekday(dt.clone()), 4);
+		assert_eq!(datetime_yearday(dt.clone()), 60);
+
+		let shifted = datetime_with_offset(vec![dt.clone(), int(-90)]);
+		assert_eq!(formatted(shifted.clone()), "2024-02-29 22:29:58.007 -01:30");
+		let parsed = datetime_parse(vec![text("2024-02-29 22:29:58.007 -01:30"), text("%Y-%m-%d %H:%M:%S.%f %z")]);
+		assert_eq!(datetime_sub(vec![parsed, dt.clone()]).unwrap().to_string(), "0");
+
+		let later = datetime_add(vec![dt.clone(), duration(MS_PER_DAY, vec![int(1)])]);
+		assert_eq!(formatted(later), "2024-03-01 23:59:58.007 +00:00");
+		assert_eq!(formatted(datetime_from_millis(vec![int(-1)])), "1969-12-31 23:59:59.999 +00:00");
+	}
+
+	fn datetime_weekday(dt: CtToken) -> i64 {
+		match datetime_component("weekday", vec![dt]) { Some(Token::Integer(n)) => n, t => panic!("expected an int, received {:?}", t) }
+	}
+
+	fn datetime_yearday(dt: CtToken) -> i64 {
+		match datetime_component("yearday", vec![dt]) { Some(Token::Integer(n)) => n, t => panic!("expected an int, received {:?}", t) }
+	}
+
+	#[test]
+	fn overflow_is_an_error() {
+		let dt = datetime(vec![int(2000), int(1), int(1)]);
+		assert_error(duration(MS_PER_DAY, vec![int(i64::MAX / 1000)]));
+		assert_error(datetime_add(vec![dt.clone(), int(i64::MAX)]));
+		assert_error(datetime_sub(vec![dt.clone(), int(i64::MIN)]));
+		assert_error(datetime(vec![int(i64::MAX), int(1), int(1)]));
+		assert_error(datetime(vec![int(290_000), int(1), int(1)]));
+		assert_error(datetime_from_millis(vec![int(i64::MAX)]));
+	}
+
+	#[test]
+	fn offsets_are_validated() {
+		assert_error(datetime_from_millis(vec![int(0), int(24 * 60)]));
+		assert_error(datetime_from_millis(vec![int(0), int(i64::MIN)]));
+		assert_error(datetime_with_offset(vec![datetime_from_millis(vec![int(0)]), int(-24 * 60)]));
+		assert_error(datetime_parse(vec![text("+24:00"), text("%z")]));
+		assert_error(datetime_parse(vec![text("+01:60"), text("%z")]));
+		assert_eq!(formatted(datetime_from_millis(vec![int(0), int(23 * 60 + 59)])), "1970-01-01 23:59:00.000 +23:59");
+	}
+
+	#[test]
+	fn malformed_input_is_an_error() {
+		assert_error(datetime(vec![int(2023), int(2), int(29)]));
+		assert_error(datetime_parse(vec![text("2024-01-01x"), text("%Y-%m-%d")]));
+		assert_error(datetime_format(vec![datetime_from_millis(vec![int(0)]), text("%Q")]));
+		assert_error(sleep(&Interrupt::new(), vec![int(-1)]));
+		assert_error(datetime_add(vec![int(0), int(0)]));
+	}
+
+	#[test]
+	fn sleep_wakes_up_when_interrupted() {
+		let interrupt = Interrupt::new();
+		let _timer = interrupt.after(Duration::from_millis(20));
+		let started = Instant::now();
+		assert_error(sleep(&interrupt, vec![int(60_000)]));
+		assert!(started.elapsed() < Duration::from_secs(10));
+	}
+}
@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+
+use ct::utils::structure::Struct;
+
+pub fn array_struct() -> Struct {
+  let mut s = Struct::new(None, HashMap::new());
+
+  for method in ["map", "filter", "any", "all", "find", "sort_by"].iter() {
+    s.define_native(method, method, &["arr", "f"]);
+  }
+  s.define_native("reduce", "reduce", &["arr", "f", "initial"]);
+  for method in ["iter", "enumerate"].iter() {
+    s.define_native(method, method, &["arr"]);
+  }
+  for method in ["take", "skip"].iter() {
+    s.define_native(method, method, &["arr", "n"]);
+  }
+  for method in ["zip", "chain"].iter() {
+    s.define_native(method, method, &["arr", "other"]);
+  }
+
+  s
+}
@@ -0,0 +1,30 @@
+use std::collections::HashMap;
+
+use ct::utils::structure::Struct;
+
+pub fn datetime_struct() -> Struct {
+  let mut s = Struct::new(None, HashMap::new());
+
+  for component in [
+    "year",
+    "month",
+    "day",
+    "hour",
+    "minute",
+    "second",
+    "millisecond",
+    "weekday",
+    "yearday",
+  ]
+  .iter()
+  {
+    s.define_native(component, &format!("datetime_{}", component), &["dt"]);
+  }
+
+  s.define_native("format", "datetime_format", &["dt", "pattern"]);
+  s.define_native("add", "datetime_add", &["dt", "duration"]);
+  s.define_native("sub", "datetime_sub", &["dt", "other"]);
+  s.define_native("with_offset", "datetime_with_offset", &["dt", "offset"]);
+
+  s
+}
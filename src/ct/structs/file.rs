@@ -0,0 +1,14 @@
+use std::collections::HashMap;
+
+use ct::utils::structure::Struct;
+
+pub fn file_struct() -> Struct {
+  let mut s = Struct::new(None, HashMap::new());
+
+  s.define_native("read_line", "file_read_line", &["file"]);
+  s.define_native("eof", "file_eof", &["file"]);
+  s.define_native("write", "file_write", &["file", "value"]);
+  s.define_native("close", "file_close", &["file"]);
+
+  s
+}
@@ -0,0 +1,23 @@
+use std::collections::HashMap;
+
+use ct::utils::structure::Struct;
+
+pub fn iterator_struct() -> Struct {
+  let mut s = Struct::new(None, HashMap::new());
+
+  for method in ["next", "collect", "enumerate"].iter() {
+    s.define_native(method, method, &["it"]);
+  }
+  for method in ["take", "skip"].iter() {
+    s.define_native(method, method, &["it", "n"]);
+  }
+  for method in ["zip", "chain"].iter() {
+    s.define_native(method, method, &["it", "other"]);
+  }
+  for method in ["map", "filter", "any", "all", "find", "sort_by"].iter() {
+    s.define_native(method, method, &["it", "f"]);
+  }
+  s.define_native("reduce", "reduce", &["it", "f", "initial"]);
+
+  s
+}
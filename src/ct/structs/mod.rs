@@ -1 +1,6 @@
-pub mod string;
\ No newline at end of file
+pub mod string;
+pub mod file;
+pub mod datetime;
+pub mod regex;
+pub mod array;
+pub mod iterator;
\ No newline at end of file
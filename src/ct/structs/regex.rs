@@ -0,0 +1,18 @@
+use std::collections::HashMap;
+
+use ct::utils::structure::Struct;
+
+pub fn regex_struct() -> Struct {
+  let mut s = Struct::new(None, HashMap::new());
+
+  s.define_native("matches", "regex_matches", &["re", "text"]);
+  s.define_native("find", "regex_find", &["re", "text"]);
+  s.define_native("find_all", "regex_find_all", &["re", "text"]);
+  s.define_native("captures", "regex_captures", &["re", "text"]);
+  s.define_native("named_captures", "regex_named_captures", &["re", "text"]);
+  s.define_native("replace", "regex_replace", &["re", "text", "replacement"]);
+  s.define_native("replace_all", "regex_replace_all", &["re", "text", "replacement"]);
+  s.define_native("split", "regex_split", &["re", "text"]);
+
+  s
+}
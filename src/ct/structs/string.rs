@@ -14,6 +14,10 @@ pub fn string_struct() -> Struct {
       body: Vec::new(),
     },
     prototype: HashMap::new(),
+    declared: false,
+    name: String::new(),
+    parent: None,
+    interfaces: Vec::new(),
   };
 
   s.prototype.insert(
@@ -27,8 +31,8 @@ pub fn string_struct() -> Struct {
         })],
         return_type: types::INT,
       },
-      body: vec![Some(Token::Return(Box::new(Some(Token::FunctionCall {
-        name: String::from("len"),
+      body: vec![Some(Token::Return(Box::new(Some(Token::Invoke {
+        callee: Box::new(Some(Token::Native(String::from("len")))),
         args: vec![Some(Token::VariableCall(String::from("s")))],
       }))))],
     })),
@@ -1,4 +1,8 @@
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
+use std::rc::Rc;
+
+use ct::utils::iter::SharedIter;
 
 #[derive(Debug, Clone)]
 pub enum Token {
@@ -8,18 +12,31 @@ pub enum Token {
 
   String(String),
   Boolean(bool),
-  Integer(i32),
+  Integer(i64),
   Float(f64),
+  Null,
 
   Array(Vec<CtToken>),
+  Map(BTreeMap<String, CtToken>),
   Structure {
     name: String,
-    args: Vec<CtToken>,
+    fields: HashMap<String, CtToken>,
+  },
+  Enum {
+    name: String,
+    variants: Vec<(String, Vec<String>)>,
   },
-  StructureCall {
+  EnumValue {
     name: String,
+    variant: String,
+    fields: Vec<(String, CtToken)>,
+  },
+  // `value.field` or `value.method(args)` on any expression, `?.` gives null for a null value
+  MemberAccess {
+    target: Box<CtToken>,
     attribute: String,
     args: Option<Vec<CtToken>>,
+    optional: bool,
   },
 
   Binary {
@@ -27,25 +44,93 @@ pub enum Token {
     left: Box<CtToken>,
     right: Box<CtToken>,
   },
+  Conditional {
+    condition: Box<CtToken>,
+    then: Box<CtToken>,
+    otherwise: Box<CtToken>,
+  },
   Return(Box<CtToken>),
+  Yield(Box<CtToken>),
   Break,
   Continue,
   VariableCall(String),
+  Constant(String),
+  // Arrays, maps and structure instances wrapped by `freeze` reject member assignment
+  Frozen(Box<Token>),
   Variable {
     name: String,
     return_type: types::Type,
   },
+  // `struct Name extends Parent implements Interface { let field = default; let f(let self) { ... } }`
+  StructDeclaration {
+    name: String,
+    parent: Option<String>,
+    interfaces: Vec<String>,
+    fields: Vec<CtToken>,
+    methods: Vec<Function>,
+  },
+  // `interface Name { let method(let self); }`, the methods a struct implementing it must have
+  InterfaceDeclaration {
+    name: String,
+    methods: Vec<FunctionHeader>,
+  },
+  // Bound as `super` inside methods of a struct that extends another
+  Super {
+    parent: String,
+    receiver: String,
+  },
+  // `value[index]`
+  Index {
+    target: Box<CtToken>,
+    index: Box<CtToken>,
+  },
+  // `let [a, b]` or `const {x, y}`, declaring every name the pattern binds
+  Destructure {
+    pattern: Box<Pattern>,
+    constant: bool,
+  },
   FunctionCall {
     name: String,
     args: Vec<CtToken>,
   },
+  // Calls what `callee` evaluates to. Built-in methods reach their native this way, so script
+  // functions of the same name cannot get in between
+  Invoke {
+    callee: Box<CtToken>,
+    args: Vec<CtToken>,
+  },
+  // `f(b: 3)` at a call site
+  NamedArgument {
+    name: String,
+    value: Box<CtToken>,
+  },
+  // `let ...rest` as the last parameter
+  Variadic(String),
   FunctionHeader(FunctionHeader),
   Function(Function),
+  // `let (x) { ... }`, evaluates to a closure
+  Lambda(Function),
+  // A callable value, capturing the local variables visible where it was created
+  Closure {
+    function: Function,
+    captured: Rc<HashMap<String, CtToken>>,
+  },
+  // A predef or intrinsic used as a value
+  Native(String),
+  // `return f(x)` with its arguments evaluated, run by the caller in place of the returning call
+  TailCall(Box<Call>),
 
   If(Vec<If>),
+  Match {
+    value: Box<CtToken>,
+    arms: Vec<MatchArm>,
+  },
   For {
+    pattern: Box<Pattern>,
+    iterable: Box<CtToken>,
     body: Vec<CtToken>,
   },
+  Iterator(SharedIter),
   While {
     condition: Box<CtToken>,
     body: Vec<CtToken>,
@@ -63,12 +148,42 @@ pub struct FunctionHeader {
   pub return_type: types::Type,
 }
 
+impl FunctionHeader {
+  // `f(a, b = 2, ...rest)`
+  pub fn signature(&self) -> String {
+    let params: Vec<String> = self
+      .args
+      .iter()
+      .map(|param| match param {
+        Some(Token::Variable { name, .. }) => name.clone(),
+        Some(Token::Binary { left, right, .. }) => match (&**left, &**right) {
+          (Some(Token::Variable { name, .. }), Some(default)) => format!("{} = {}", name, default),
+          _ => String::from("?"),
+        },
+        Some(Token::Variadic(name)) => format!("...{}", name),
+        _ => String::from("?"),
+      })
+      .collect();
+
+    format!("{}({})", self.name, params.join(", "))
+  }
+}
+
 #[derive(Debug, Clone)]
 pub struct Function {
   pub header: FunctionHeader,
   pub body: Vec<CtToken>,
 }
 
+// A script function call with its arguments evaluated
+#[derive(Debug, Clone)]
+pub struct Call {
+  pub function: Function,
+  pub captured: Option<Rc<HashMap<String, CtToken>>>,
+  pub positional: Vec<CtToken>,
+  pub named: Vec<(String, CtToken)>,
+}
+
 #[derive(Debug, Clone)]
 pub struct If {
   pub condition: Box<CtToken>,
@@ -76,6 +191,38 @@ pub struct If {
   pub return_type: types::Type,
 }
 
+#[derive(Debug, Clone)]
+pub enum Pattern {
+  Wildcard,
+  Binding(String),
+  Literal(Token),
+  Range {
+    start: Token,
+    end: Token,
+    inclusive: bool,
+  },
+  Alternative(Vec<Pattern>),
+  Array(Vec<Pattern>),
+  // `Shape.Circle(r)`, the enum name may be left out and without parentheses the fields are not checked
+  Variant {
+    name: Option<String>,
+    variant: String,
+    fields: Option<Vec<Pattern>>,
+  },
+  // A struct pattern without a name matches map keys, or the fields of any struct
+  Structure {
+    name: Option<String>,
+    fields: Vec<(String, Pattern)>,
+  },
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+  pub pattern: Pattern,
+  pub guard: Box<CtToken>,
+  pub body: Vec<CtToken>,
+}
+
 impl fmt::Display for Token {
   fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
     match self {
@@ -83,11 +230,162 @@ impl fmt::Display for Token {
       Token::Boolean(value) => write!(f, "{}", value),
       Token::Integer(value) => write!(f, "{}", value),
       Token::Float(value) => write!(f, "{}", value),
+      Token::Null => write!(f, "{}", types::NULL),
+      Token::Array(items) => {
+        write!(f, "[")?;
+        for (i, item) in items.iter().enumerate() {
+          if i > 0 {
+            write!(f, ", ")?;
+          }
+          write_nested(f, item)?;
+        }
+        write!(f, "]")
+      }
+      Token::Map(entries) => {
+        write!(f, "{{")?;
+        for (i, (key, value)) in entries.iter().enumerate() {
+          write!(f, "{}{:?}: ", if i > 0 { ", " } else { "" }, key)?;
+          write_nested(f, value)?;
+        }
+        write!(f, "}}")
+      }
+      Token::Structure { name, fields } => {
+        let mut keys: Vec<&String> = fields.keys().collect();
+        keys.sort();
+
+        write!(f, "{} {{", name)?;
+        for (i, key) in keys.iter().enumerate() {
+          write!(f, "{}{}: ", if i > 0 { ", " } else { " " }, key)?;
+          write_nested(f, &fields[*key])?;
+        }
+        write!(f, "{}}}", if keys.is_empty() { "" } else { " " })
+      }
+      Token::Frozen(value) => write!(f, "{}", value),
+      Token::Iterator(_) => write!(f, "{}", types::ITERATOR),
+      Token::Closure { function, .. } => write!(f, "{} {}", types::FUNCTION, function.header.signature()),
+      Token::Native(name) => write!(f, "{} {}(...)", types::FUNCTION, name),
+      Token::Enum { name, variants: _ } => write!(f, "enum {}", name),
+      Token::EnumValue {
+        name,
+        variant,
+        fields,
+      } => {
+        write!(f, "{}.{}", name, variant)?;
+        if !fields.is_empty() {
+          write!(f, "(")?;
+          for (i, (_, value)) in fields.iter().enumerate() {
+            if i > 0 {
+              write!(f, ", ")?;
+            }
+            write_nested(f, value)?;
+          }
+          write!(f, ")")?;
+        }
+        Ok(())
+      }
       _ => fmt::Debug::fmt(self, f),
     }
   }
 }
 
+// Strings nested inside collections are quoted so `["1", 1]` stays readable
+fn write_nested(f: &mut fmt::Formatter, token: &CtToken) -> fmt::Result {
+  match token {
+    Some(Token::String(value)) => write!(f, "{:?}", value),
+    Some(t) => write!(f, "{}", t),
+    None => write!(f, "{}", types::NULL),
+  }
+}
+
+// Reading from a frozen value is the same as reading from the value itself
+pub fn thaw(token: CtToken) -> CtToken {
+  match token {
+    Some(Token::Frozen(value)) => Some(*value),
+    token => token,
+  }
+}
+
+// Strips frozen wrappers at any depth, natives only ever see plain values
+pub fn thaw_all(token: CtToken) -> CtToken {
+  match thaw(token) {
+    Some(Token::Array(items)) => Some(Token::Array(items.into_iter().map(thaw_all).collect())),
+    Some(Token::Map(entries)) => Some(Token::Map(
+      entries
+        .into_iter()
+        .map(|(key, value)| (key, thaw_all(value)))
+        .collect(),
+    )),
+    Some(Token::Structure { name, fields }) => Some(Token::Structure {
+      name,
+      fields: fields
+        .into_iter()
+        .map(|(key, value)| (key, thaw_all(value)))
+        .collect(),
+    }),
+    token => token,
+  }
+}
+
+// Structural equality between runtime values, a missing value equals `null`
+pub fn values_equal(left: &CtToken, right: &CtToken) -> bool {
+  if let Some(Token::Frozen(value)) = left {
+    return values_equal(&Some((**value).clone()), right);
+  }
+  if let Some(Token::Frozen(value)) = right {
+    return values_equal(left, &Some((**value).clone()));
+  }
+
+  match (left, right) {
+    (None, None) | (None, Some(Token::Null)) | (Some(Token::Null), None) => true,
+    (Some(left), Some(right)) => match (left, right) {
+      (Token::Null, Token::Null) => true,
+      (Token::Integer(a), Token::Integer(b)) => a == b,
+      (Token::Float(a), Token::Float(b)) => a == b,
+      (Token::Integer(a), Token::Float(b)) | (Token::Float(b), Token::Integer(a)) => *a as f64 == *b,
+      (Token::Boolean(a), Token::Boolean(b)) => a == b,
+      (Token::String(a), Token::String(b)) => a == b,
+      (Token::Array(a), Token::Array(b)) => {
+        a.len() == b.len() && a.iter().zip(b.iter()).all(|(a, b)| values_equal(a, b))
+      }
+      (Token::Map(a), Token::Map(b)) => {
+        a.len() == b.len()
+          && a
+            .iter()
+            .all(|(key, value)| b.get(key).is_some_and(|other| values_equal(value, other)))
+      }
+      (
+        Token::Structure { name: a, fields: x },
+        Token::Structure { name: b, fields: y },
+      ) => {
+        a == b
+          && x.len() == y.len()
+          && x
+            .iter()
+            .all(|(key, value)| y.get(key).is_some_and(|other| values_equal(value, other)))
+      }
+      (
+        Token::EnumValue {
+          name: a,
+          variant: v,
+          fields: x,
+        },
+        Token::EnumValue {
+          name: b,
+          variant: w,
+          fields: y,
+        },
+      ) => {
+        a == b
+          && v == w
+          && x.len() == y.len()
+          && x.iter().zip(y.iter()).all(|((_, a), (_, b))| values_equal(a, b))
+      }
+      _ => false,
+    },
+    _ => false,
+  }
+}
+
 pub fn get_type(token: &CtToken) -> String {
   match token {
     Some(t) => match t {
@@ -96,9 +394,14 @@ pub fn get_type(token: &CtToken) -> String {
       Token::Integer(_) => String::from(types::INT),
       Token::Float(_) => String::from(types::FLOAT),
       Token::Array(_) => String::from(types::ARRAY),
-      Token::Structure { name, args: _ } => name.clone(),
+      Token::Map(_) => String::from(types::MAP),
+      Token::Iterator(_) => String::from(types::ITERATOR),
+      Token::Structure { name, fields: _ } => name.clone(),
+      Token::EnumValue { name, .. } => name.clone(),
+      Token::Frozen(value) => get_type(&Some((**value).clone())),
       Token::Return(_) => String::from(types::RETURN),
-      Token::Function(_) => String::from(types::FUNCTION),
+      Token::Yield(_) => String::from(types::YIELD),
+      Token::Function(_) | Token::Closure { .. } | Token::Native(_) => String::from(types::FUNCTION),
       Token::Error(_) => String::from(types::ERROR),
       _ => String::from(types::NULL),
     },
@@ -109,10 +412,21 @@ pub fn get_type(token: &CtToken) -> String {
 pub mod types {
   pub type Type = &'static str;
   pub const DECLARE: Type = "let";
+  pub const CONST: Type = "const";
+  pub const ANONYMOUS: Type = "<anonymous>";
   pub const IF: Type = "if";
   pub const FOR: Type = "for";
   pub const WHILE: Type = "while";
+  pub const MATCH: Type = "match";
+  pub const ENUM: Type = "enum";
+  pub const IS: Type = "is";
+  pub const INSTANCEOF: Type = "instanceof";
+  pub const INTERFACE: Type = "interface";
+  pub const EXTENDS: Type = "extends";
+  pub const IMPLEMENTS: Type = "implements";
+  pub const SUPER: Type = "super";
   pub const RETURN: Type = "return";
+  pub const YIELD: Type = "yield";
   pub const BREAK: Type = "break";
   pub const CONTINUE: Type = "continue";
   pub const TRUE: Type = "true";
@@ -124,6 +438,10 @@ pub mod types {
   pub const NULL: Type = "null";
   pub const STRING: Type = "String";
   pub const ARRAY: Type = "Array";
+  pub const MAP: Type = "Map";
+  pub const ITERATOR: Type = "Iterator";
+  pub const GENERATOR: Type = "Generator";
+  pub const IN: Type = "in";
   pub const STRUCT: Type = "struct";
   pub const FUNCTION: Type = "fun";
   pub const THIS: Type = "this";
@@ -0,0 +1,114 @@
+use ct::env::Environment;
+use ct::syntax::std::*;
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// Iterators are shared between copies of a value, so an adapter advances the iterator it wraps
+pub type SharedIter = Rc<RefCell<Iter>>;
+
+#[derive(Debug)]
+pub enum Iter {
+  Range {
+    next: i64,
+    end: i64,
+    step: i64,
+    inclusive: bool,
+  },
+  Items(std::vec::IntoIter<CtToken>),
+  // Lines of a `File` struct, read through the filestream natives
+  Lines(CtToken),
+  // A value whose struct implements `next()`, returning `{ done, value }`
+  Custom(CtToken),
+  Enumerate(SharedIter, i64),
+  Zip(SharedIter, SharedIter),
+  Take(SharedIter, i64),
+  Skip(SharedIter, i64),
+  Chain(SharedIter, SharedIter),
+  Map(SharedIter, CtToken),
+  Filter(SharedIter, CtToken),
+  Generator(Box<Generator>),
+  Done,
+}
+
+// A suspended generator call: the scopes of its frame, and where each block it is inside stopped
+#[derive(Debug)]
+pub struct Generator {
+  pub scopes: Vec<Environment>,
+  pub frames: Vec<Frame>,
+}
+
+#[derive(Debug)]
+pub enum Frame {
+  // Scoped blocks pushed an environment of their own, which is popped when they end
+  Block {
+    body: Rc<Vec<CtToken>>,
+    next: usize,
+    scoped: bool,
+  },
+  While {
+    condition: CtToken,
+    body: Rc<Vec<CtToken>>,
+  },
+  For {
+    pattern: Pattern,
+    items: SharedIter,
+    body: Rc<Vec<CtToken>>,
+  },
+}
+
+impl Iter {
+  pub fn shared(self) -> SharedIter {
+    Rc::new(RefCell::new(self))
+  }
+
+  pub fn range(start: i64, end: i64, step: i64, inclusive: bool) -> Result<Iter, String> {
+    if step == 0 {
+      return Err(String::from("Range step cannot be zero"));
+    }
+
+    Ok(Iter::Range {
+      next: start,
+      end,
+      step,
+      inclusive,
+    })
+  }
+
+  // Advances the variants that need no interpreter: `Some(None)` once exhausted, and `None` when
+  // the caller has to advance it instead
+  pub fn advance_plain(&mut self) -> Option<Option<CtToken>> {
+    match self {
+      Iter::Range {
+        next,
+        end,
+        step,
+        inclusive,
+      } => {
+        let in_range = match (*step > 0, *inclusive) {
+          (true, true) => *next <= *end,
+          (true, false) => *next < *end,
+          (false, true) => *next >= *end,
+          (false, false) => *next > *end,
+        };
+        if !in_range {
+          *self = Iter::Done;
+          return Some(None);
+        }
+
+        let item: CtToken = Some(Token::Integer(*next));
+        match next.checked_add(*step) {
+          Some(following) => *next = following,
+          None => *self = Iter::Done,
+        }
+        Some(Some(item))
+      }
+      Iter::Items(items) => Some(items.next().map(|item| match item {
+        None => Some(Token::Null),
+        item => item,
+      })),
+      Iter::Done => Some(None),
+      _ => None,
+    }
+  }
+}
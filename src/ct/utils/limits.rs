@@ -0,0 +1,137 @@
+use ct::syntax::std::*;
+
+use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How long blocking natives wait at most before they look at the interrupt again
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// Bounds for running untrusted scripts, a limit that is not set does not apply
+#[derive(Clone, Debug, Default)]
+pub struct Limits {
+  // Evaluation steps, roughly one per expression and statement
+  pub fuel: Option<u64>,
+  pub time: Option<Duration>,
+  // Bytes the variables of all running scopes may hold together, counting everything they hold.
+  // No single value may take more either
+  pub memory: Option<usize>,
+  // Bytes written by print, input and flush
+  pub output: Option<usize>,
+}
+
+// Why a script was stopped from outside its evaluation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reason {
+  Interrupted,
+  TimedOut,
+  // The script called `exit`, which ends it without ending the host
+  Exit(i32),
+}
+
+// Stops a running script from another thread, at its next evaluation step. Blocking natives
+// share it and give up early once it is set
+#[derive(Clone, Debug, Default)]
+pub struct Interrupt {
+  set: Arc<AtomicBool>,
+  reason: Arc<Mutex<Option<Reason>>>,
+}
+
+impl Interrupt {
+  pub fn new() -> Interrupt {
+    Interrupt::default()
+  }
+
+  pub fn interrupt(&self) {
+    self.stop(Reason::Interrupted);
+  }
+
+  pub fn exit(&self, code: i32) {
+    self.stop(Reason::Exit(code));
+  }
+
+  // The first reason is kept, a script that timed out while exiting still timed out
+  fn stop(&self, reason: Reason) {
+    if let Ok(mut current) = self.reason.lock() {
+      current.get_or_insert(reason);
+    }
+    self.set.store(true, Ordering::Release);
+  }
+
+  pub fn is_interrupted(&self) -> bool {
+    self.set.load(Ordering::Acquire)
+  }
+
+  pub fn reason(&self) -> Option<Reason> {
+    self.reason.lock().ok().and_then(|reason| *reason)
+  }
+
+  // Interrupts after the given time, unless the returned sender is dropped first
+  pub fn after(&self, time: Duration) -> mpsc::Sender<()> {
+    let (sender, receiver) = mpsc::channel::<()>();
+    let interrupt = self.clone();
+    thread::spawn(move || {
+      if let Err(mpsc::RecvTimeoutError::Timeout) = receiver.recv_timeout(time) {
+        interrupt.stop(Reason::TimedOut);
+      }
+    });
+    sender
+  }
+
+  // Sleeps in short slices, false when interrupted before the time is up
+  pub fn sleep(&self, time: Duration) -> bool {
+    let started = Instant::now();
+    loop {
+      if self.is_interrupted() {
+        return false;
+      }
+      let elapsed = started.elapsed();
+      if elapsed >= time {
+        return true;
+      }
+      thread::sleep((time - elapsed).min(POLL_INTERVAL));
+    }
+  }
+
+  // Waits for a value from another thread, None when interrupted or when the sender is gone
+  pub fn wait<T>(&self, receiver: &mpsc::Receiver<T>) -> Option<T> {
+    loop {
+      if self.is_interrupted() {
+        return None;
+      }
+      match receiver.recv_timeout(POLL_INTERVAL) {
+        Ok(value) => return Some(value),
+        Err(mpsc::RecvTimeoutError::Timeout) => (),
+        Err(mpsc::RecvTimeoutError::Disconnected) => return None,
+      }
+    }
+  }
+}
+
+// What a value takes, including the strings, arrays, maps and fields nested in it
+pub fn size_of(value: &Token) -> usize {
+  let item = mem::size_of::<CtToken>();
+  let nested = |value: &CtToken| item.saturating_add(value.as_ref().map_or(0, size_of));
+  let fields = |name: &String, value: &CtToken| name.len().saturating_add(nested(value));
+  match value {
+    Token::String(text) => text.len(),
+    Token::Array(items) => items.iter().map(nested).fold(0, usize::saturating_add),
+    Token::Map(entries) => entries
+      .iter()
+      .map(|(key, value)| fields(key, value))
+      .fold(0, usize::saturating_add),
+    Token::Structure { fields: values, .. } => values
+      .iter()
+      .map(|(name, value)| fields(name, value))
+      .fold(0, usize::saturating_add),
+    Token::EnumValue { fields: values, .. } => values
+      .iter()
+      .map(|(name, value)| fields(name, value))
+      .fold(0, usize::saturating_add),
+    Token::Frozen(value) => size_of(value),
+    _ => 0,
+  }
+}
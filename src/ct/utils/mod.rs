@@ -1,3 +1,5 @@
 pub mod predefs;
 pub mod module;
-pub mod structure;
\ No newline at end of file
+pub mod structure;
+pub mod regex;
+pub mod iter;pub mod limits;
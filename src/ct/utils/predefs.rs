@@ -1,14 +1,18 @@
 use ct::syntax::std::*;
+use ct::utils::limits::Interrupt;
 use std::collections::HashMap;
 
 pub struct Predefs {
-	functions: HashMap<String, Box<Fn(Vec<CtToken>) -> CtToken>>
+	functions: HashMap<String, Box<Fn(Vec<CtToken>) -> CtToken>>,
+	// Shared with the natives that block, so they stop waiting when the script is stopped
+	interrupt: Interrupt
 }
 
 impl Predefs {
 	pub fn new() -> Predefs {
 		Predefs {
-			functions: HashMap::new()
+			functions: HashMap::new(),
+			interrupt: Interrupt::new()
 		}
 	}
 
@@ -27,4 +31,8 @@ impl Predefs {
 	pub fn insert(&mut self, name: String, function: Box<Fn(Vec<CtToken>) -> CtToken>) {
 		self.functions.insert(name, function);
 	}
-}
\ No newline at end of file
+
+	pub fn interrupt(&self) -> Interrupt {
+		self.interrupt.clone()
+	}
+}
@@ -0,0 +1,806 @@
+// A small backtracking regex engine. Patterns compile to a program for a VM that
+// memoises (instruction, position) pairs, so matching stays linear in the input
+// unless the pattern uses backreferences or the memo would grow too large.
+
+const MAX_REPEAT: usize = 1000;
+const MAX_PROGRAM: usize = 100_000;
+// Bits of (instruction, position) memo a single search may allocate
+const MAX_MEMO: usize = 1 << 28;
+const STEP_LIMIT: usize = 10_000_000;
+// Steps between looks at the interrupt, which is also checked as each search starts
+const INTERRUPT_INTERVAL: usize = 1 << 16;
+
+use ct::utils::limits::Interrupt;
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+  Range(char, char),
+  Digit(bool),
+  Word(bool),
+  Space(bool),
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+  Char(char),
+  Any,
+  Class(Vec<ClassItem>, bool),
+  Start,
+  End,
+  WordBoundary(bool),
+  Backref(usize),
+  Group(Box<Node>, Option<usize>),
+  Concat(Vec<Node>),
+  Alternate(Vec<Node>),
+  Repeat {
+    node: Box<Node>,
+    min: usize,
+    max: Option<usize>,
+    greedy: bool,
+  },
+}
+
+#[derive(Debug, Clone)]
+enum Inst {
+  Char(char),
+  Any,
+  Step,
+  Class(Vec<ClassItem>, bool),
+  Start,
+  End,
+  WordBoundary(bool),
+  Backref(usize),
+  Save(usize),
+  Split(usize, usize),
+  Jump(usize),
+  Match,
+}
+
+#[derive(Debug, Clone)]
+pub struct Regex {
+  program: Vec<Inst>,
+  groups: usize,
+  names: Vec<(String, usize)>,
+  ignore_case: bool,
+  has_backrefs: bool,
+  interrupt: Option<Interrupt>,
+}
+
+// Capture positions are char indices, `None` for groups that did not take part
+pub type Captures = Vec<Option<(usize, usize)>>;
+
+struct Parser {
+  chars: Vec<char>,
+  index: usize,
+  groups: usize,
+  names: Vec<(String, usize)>,
+  ignore_case: bool,
+  has_backrefs: bool,
+}
+
+impl Parser {
+  fn error(&self, message: &str) -> String {
+    format!("Invalid regex at position {}: {}", self.index + 1, message)
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.index).cloned()
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    if self.peek() == Some(c) {
+      self.index += 1;
+      true
+    } else {
+      false
+    }
+  }
+
+  fn parse_alternate(&mut self) -> Result<Node, String> {
+    let mut branches = vec![self.parse_concat()?];
+    while self.eat('|') {
+      branches.push(self.parse_concat()?);
+    }
+
+    Ok(if branches.len() == 1 {
+      branches.pop().unwrap()
+    } else {
+      Node::Alternate(branches)
+    })
+  }
+
+  fn parse_concat(&mut self) -> Result<Node, String> {
+    let mut nodes = Vec::new();
+    while let Some(c) = self.peek() {
+      if c == '|' || c == ')' {
+        break;
+      }
+      if let Some(node) = self.parse_repeat()? {
+        nodes.push(node);
+      }
+    }
+
+    Ok(Node::Concat(nodes))
+  }
+
+  fn parse_repeat(&mut self) -> Result<Option<Node>, String> {
+    let atom = match self.parse_atom()? {
+      Some(atom) => atom,
+      None => return Ok(None),
+    };
+
+    let (min, max) = match self.peek() {
+      Some('*') => (0, None),
+      Some('+') => (1, None),
+      Some('?') => (0, Some(1)),
+      Some('{') => match self.parse_bounds()? {
+        Some(bounds) => bounds,
+        None => return Ok(Some(atom)),
+      },
+      _ => return Ok(Some(atom)),
+    };
+    self.index += 1;
+
+    match atom {
+      Node::Start | Node::End | Node::WordBoundary(_) => {
+        return Err(self.error("Nothing to repeat"));
+      }
+      _ => (),
+    }
+
+    let greedy = !self.eat('?');
+    if let Some('*') | Some('+') | Some('?') = self.peek() {
+      return Err(self.error("Nested quantifier"));
+    }
+
+    Ok(Some(Node::Repeat {
+      node: Box::new(atom),
+      min,
+      max,
+      greedy,
+    }))
+  }
+
+  // `{n}`, `{n,}` or `{n,m}`; anything else is a literal brace
+  fn parse_bounds(&mut self) -> Result<Option<(usize, Option<usize>)>, String> {
+    let start = self.index;
+    self.index += 1;
+
+    let read = |parser: &mut Parser| {
+      let mut digits = String::new();
+      while let Some(c) = parser.peek() {
+        if !c.is_ascii_digit() {
+          break;
+        }
+        digits.push(c);
+        parser.index += 1;
+      }
+      digits
+    };
+
+    let low = read(self);
+    let high = if self.eat(',') { Some(read(self)) } else { None };
+
+    if low.is_empty() || !self.eat('}') {
+      self.index = start;
+      return Ok(None);
+    }
+
+    let min = low.parse::<usize>().unwrap_or(usize::MAX);
+    let max = match high {
+      None => Some(min),
+      Some(ref high) if high.is_empty() => None,
+      Some(high) => Some(high.parse::<usize>().unwrap_or(usize::MAX)),
+    };
+
+    if min > MAX_REPEAT || max.map(|max| max > MAX_REPEAT).unwrap_or(false) {
+      return Err(self.error(&format!("Repetition count exceeds {}", MAX_REPEAT)));
+    }
+    if let Some(max) = max {
+      if max < min {
+        return Err(self.error("Repetition range is out of order"));
+      }
+    }
+
+    // Step back onto the closing brace, which the caller skips like any other quantifier
+    self.index -= 1;
+    Ok(Some((min, max)))
+  }
+
+  fn parse_atom(&mut self) -> Result<Option<Node>, String> {
+    let c = match self.peek() {
+      Some(c) => c,
+      None => return Ok(None),
+    };
+    self.index += 1;
+
+    Ok(Some(match c {
+      '.' => Node::Any,
+      '^' => Node::Start,
+      '$' => Node::End,
+      '[' => self.parse_class()?,
+      '(' => return self.parse_group(),
+      '\\' => self.parse_escape()?,
+      '*' | '+' | '?' => return Err(self.error("Nothing to repeat")),
+      c => Node::Char(c),
+    }))
+  }
+
+  fn parse_group(&mut self) -> Result<Option<Node>, String> {
+    let mut index = None;
+
+    if self.eat('?') {
+      if self.eat(':') {
+        // Non-capturing
+      } else if self.eat('i') {
+        if !self.eat(')') {
+          return Err(self.error("Expected ')' after inline flag"));
+        }
+        self.ignore_case = true;
+        return Ok(None);
+      } else if self.eat('<') || (self.eat('P') && self.eat('<')) {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+          if c == '>' {
+            break;
+          }
+          if !(c.is_alphanumeric() || c == '_') {
+            return Err(self.error("Invalid group name"));
+          }
+          name.push(c);
+          self.index += 1;
+        }
+        if name.is_empty() || !self.eat('>') {
+          return Err(self.error("Invalid group name"));
+        }
+
+        self.groups += 1;
+        self.names.push((name, self.groups));
+        index = Some(self.groups);
+      } else {
+        return Err(self.error("Unknown group syntax"));
+      }
+    } else {
+      self.groups += 1;
+      index = Some(self.groups);
+    }
+
+    let inner = self.parse_alternate()?;
+    if !self.eat(')') {
+      return Err(self.error("Missing ')'"));
+    }
+
+    Ok(Some(Node::Group(Box::new(inner), index)))
+  }
+
+  fn parse_escape(&mut self) -> Result<Node, String> {
+    let c = match self.peek() {
+      Some(c) => c,
+      None => return Err(self.error("Pattern ends with a lone backslash")),
+    };
+    self.index += 1;
+
+    Ok(match c {
+      'd' => Node::Class(vec![ClassItem::Digit(true)], false),
+      'D' => Node::Class(vec![ClassItem::Digit(false)], false),
+      'w' => Node::Class(vec![ClassItem::Word(true)], false),
+      'W' => Node::Class(vec![ClassItem::Word(false)], false),
+      's' => Node::Class(vec![ClassItem::Space(true)], false),
+      'S' => Node::Class(vec![ClassItem::Space(false)], false),
+      'b' => Node::WordBoundary(true),
+      'B' => Node::WordBoundary(false),
+      '1'..='9' => {
+        self.has_backrefs = true;
+        Node::Backref(c.to_digit(10).unwrap() as usize)
+      }
+      c => Node::Char(Parser::escaped(c)),
+    })
+  }
+
+  fn escaped(c: char) -> char {
+    match c {
+      'n' => '\n',
+      't' => '\t',
+      'r' => '\r',
+      'f' => '\x0C',
+      'v' => '\x0B',
+      '0' => '\0',
+      c => c,
+    }
+  }
+
+  fn parse_class(&mut self) -> Result<Node, String> {
+    let negated = self.eat('^');
+    let mut items = Vec::new();
+    let mut first = true;
+
+    loop {
+      let c = match self.peek() {
+        Some(c) => c,
+        None => return Err(self.error("Missing ']'")),
+      };
+      self.index += 1;
+
+      if c == ']' && !first {
+        break;
+      }
+      first = false;
+
+      let low = if c == '\\' {
+        let escaped = match self.peek() {
+          Some(escaped) => escaped,
+          None => return Err(self.error("Missing ']'")),
+        };
+        self.index += 1;
+        match escaped {
+          'd' | 'D' => {
+            items.push(ClassItem::Digit(escaped == 'd'));
+            continue;
+          }
+          'w' | 'W' => {
+            items.push(ClassItem::Word(escaped == 'w'));
+            continue;
+          }
+          's' | 'S' => {
+            items.push(ClassItem::Space(escaped == 's'));
+            continue;
+          }
+          other => Parser::escaped(other),
+        }
+      } else {
+        c
+      };
+
+      if self.peek() == Some('-') && self.chars.get(self.index + 1).map(|c| *c != ']').unwrap_or(false) {
+        self.index += 1;
+        let high = match self.peek() {
+          Some('\\') => {
+            self.index += 1;
+            match self.peek() {
+              Some(c) => Parser::escaped(c),
+              None => return Err(self.error("Missing ']'")),
+            }
+          }
+          Some(c) => c,
+          None => return Err(self.error("Missing ']'")),
+        };
+        self.index += 1;
+
+        if high < low {
+          return Err(self.error("Character range is out of order"));
+        }
+        items.push(ClassItem::Range(low, high));
+      } else {
+        items.push(ClassItem::Range(low, low));
+      }
+    }
+
+    Ok(Node::Class(items, negated))
+  }
+}
+
+// Instructions `emit` produces for a node, so nested repeats are rejected before they are expanded
+fn program_size(node: &Node) -> usize {
+  match node {
+    Node::Group(inner, index) => {
+      program_size(inner).saturating_add(if index.is_some() { 2 } else { 0 })
+    }
+    Node::Concat(nodes) => nodes
+      .iter()
+      .fold(0, |total, node| total.saturating_add(program_size(node))),
+    Node::Alternate(branches) => branches
+      .iter()
+      .fold(branches.len().saturating_sub(1) * 2, |total, node| {
+        total.saturating_add(program_size(node))
+      }),
+    Node::Repeat { node, min, max, .. } => {
+      let size = program_size(node);
+      let optional = match max {
+        None => size.saturating_add(2),
+        Some(max) => (max - min).saturating_mul(size.saturating_add(1)),
+      };
+      min.saturating_mul(size).saturating_add(optional)
+    }
+    _ => 1,
+  }
+}
+
+fn emit(node: &Node, program: &mut Vec<Inst>) {
+  match node {
+    Node::Char(c) => program.push(Inst::Char(*c)),
+    Node::Any => program.push(Inst::Any),
+    Node::Class(items, negated) => program.push(Inst::Class(items.clone(), *negated)),
+    Node::Start => program.push(Inst::Start),
+    Node::End => program.push(Inst::End),
+    Node::WordBoundary(b) => program.push(Inst::WordBoundary(*b)),
+    Node::Backref(n) => program.push(Inst::Backref(*n)),
+    Node::Group(inner, index) => match index {
+      Some(index) => {
+        program.push(Inst::Save(index * 2));
+        emit(inner, program);
+        program.push(Inst::Save(index * 2 + 1));
+      }
+      None => emit(inner, program),
+    },
+    Node::Concat(nodes) => {
+      for node in nodes {
+        emit(node, program);
+      }
+    }
+    Node::Alternate(branches) => {
+      let mut jumps = Vec::new();
+      for (i, branch) in branches.iter().enumerate() {
+        if i + 1 < branches.len() {
+          let split = program.len();
+          program.push(Inst::Split(0, 0));
+          emit(branch, program);
+          jumps.push(program.len());
+          program.push(Inst::Jump(0));
+          program[split] = Inst::Split(split + 1, program.len());
+        } else {
+          emit(branch, program);
+        }
+      }
+      let end = program.len();
+      for jump in jumps {
+        program[jump] = Inst::Jump(end);
+      }
+    }
+    Node::Repeat {
+      node,
+      min,
+      max,
+      greedy,
+    } => {
+      for _ in 0..*min {
+        emit(node, program);
+      }
+
+      let split = |program: &mut Vec<Inst>, at: usize, next: usize, out: usize| {
+        program[at] = if *greedy {
+          Inst::Split(next, out)
+        } else {
+          Inst::Split(out, next)
+        };
+      };
+
+      match max {
+        None => {
+          let start = program.len();
+          program.push(Inst::Split(0, 0));
+          emit(node, program);
+          program.push(Inst::Jump(start));
+          let out = program.len();
+          split(program, start, start + 1, out);
+        }
+        Some(max) => {
+          let mut splits = Vec::new();
+          for _ in *min..*max {
+            splits.push(program.len());
+            program.push(Inst::Split(0, 0));
+            emit(node, program);
+          }
+          let out = program.len();
+          for at in splits {
+            split(program, at, at + 1, out);
+          }
+        }
+      }
+    }
+  }
+}
+
+fn is_word(c: char) -> bool {
+  c.is_alphanumeric() || c == '_'
+}
+
+fn class_matches(items: &[ClassItem], negated: bool, c: char, ignore_case: bool) -> bool {
+  let test = |c: char| {
+    items.iter().any(|item| match item {
+      ClassItem::Range(low, high) => *low <= c && c <= *high,
+      ClassItem::Digit(b) => c.is_ascii_digit() == *b,
+      ClassItem::Word(b) => is_word(c) == *b,
+      ClassItem::Space(b) => c.is_whitespace() == *b,
+    })
+  };
+
+  let found = if ignore_case {
+    test(c) || c.to_lowercase().any(test) || c.to_uppercase().any(test)
+  } else {
+    test(c)
+  };
+
+  found != negated
+}
+
+fn chars_equal(a: char, b: char, ignore_case: bool) -> bool {
+  a == b || (ignore_case && a.to_lowercase().eq(b.to_lowercase()))
+}
+
+// Which (instruction, position) pairs a run has explored, cleared between the runs of one search
+struct Memo {
+  bits: Vec<u64>,
+  dirty: Vec<usize>,
+  width: usize,
+}
+
+impl Memo {
+  fn new(regex: &Regex, input: &[char]) -> Option<Memo> {
+    let width = input.len() + 1;
+    match regex.program.len().checked_mul(width) {
+      Some(size) if !regex.has_backrefs && size <= MAX_MEMO => Some(Memo {
+        bits: vec![0; size / 64 + 1],
+        dirty: Vec::new(),
+        width,
+      }),
+      _ => None,
+    }
+  }
+
+  // Marks the pair, false when it was already explored
+  fn visit(&mut self, pc: usize, pos: usize) -> bool {
+    let key = pc * self.width + pos;
+    let (word, bit) = (key / 64, 1u64 << (key % 64));
+    if self.bits[word] & bit != 0 {
+      return false;
+    }
+    if self.bits[word] == 0 {
+      self.dirty.push(word);
+    }
+    self.bits[word] |= bit;
+    true
+  }
+
+  fn clear(&mut self) {
+    for word in self.dirty.drain(..) {
+      self.bits[word] = 0;
+    }
+  }
+}
+
+enum Frame {
+  Thread(usize, usize),
+  Restore(usize, Option<usize>),
+}
+
+impl Regex {
+  pub fn new(pattern: &str) -> Result<Regex, String> {
+    let mut parser = Parser {
+      chars: pattern.chars().collect(),
+      index: 0,
+      groups: 0,
+      names: Vec::new(),
+      ignore_case: false,
+      has_backrefs: false,
+    };
+
+    let node = parser.parse_alternate()?;
+    if parser.index < parser.chars.len() {
+      return Err(parser.error("Unmatched ')'"));
+    }
+
+    let size = program_size(&node);
+    if size > MAX_PROGRAM {
+      return Err(format!(
+        "Invalid regex: pattern is too large ({} instructions, at most {})",
+        size, MAX_PROGRAM
+      ));
+    }
+
+    // A lazy loop over any character lets one run find the leftmost match
+    let mut program = vec![Inst::Split(3, 1), Inst::Step, Inst::Jump(0), Inst::Save(0)];
+    emit(&node, &mut program);
+    program.push(Inst::Save(1));
+    program.push(Inst::Match);
+
+    for inst in &program {
+      if let Inst::Backref(n) = inst {
+        if *n > parser.groups {
+          return Err(format!(
+            "Invalid regex: backreference \\{} to a missing group",
+            n
+          ));
+        }
+      }
+    }
+
+    Ok(Regex {
+      program,
+      groups: parser.groups,
+      names: parser.names,
+      ignore_case: parser.ignore_case,
+      has_backrefs: parser.has_backrefs,
+      interrupt: None,
+    })
+  }
+
+  // Searches give up with an error once the interrupt is set
+  pub fn interruptible(mut self, interrupt: Interrupt) -> Regex {
+    self.interrupt = Some(interrupt);
+    self
+  }
+
+  pub fn groups(&self) -> usize {
+    self.groups
+  }
+
+  pub fn names(&self) -> &[(String, usize)] {
+    &self.names
+  }
+
+  // Leftmost match starting the search at char index `from`
+  pub fn captures_from(&self, input: &[char], from: usize) -> Result<Option<Captures>, String> {
+    self.search(input, from, &mut Memo::new(self, input))
+  }
+
+  fn search(
+    &self,
+    input: &[char],
+    from: usize,
+    memo: &mut Option<Memo>,
+  ) -> Result<Option<Captures>, String> {
+    let slots = self.run(input, from, memo);
+    if let Some(memo) = memo {
+      memo.clear();
+    }
+
+    Ok(slots?.map(|slots| {
+      (0..=self.groups)
+        .map(|group| match (slots[group * 2], slots[group * 2 + 1]) {
+          (Some(start), Some(end)) => Some((start, end)),
+          _ => None,
+        })
+        .collect()
+    }))
+  }
+
+  pub fn captures_all(&self, input: &[char]) -> Result<Vec<Captures>, String> {
+    let mut all = Vec::new();
+    let mut from = 0;
+    let mut memo = Memo::new(self, input);
+
+    while from <= input.len() {
+      match self.search(input, from, &mut memo)? {
+        Some(captures) => {
+          let (start, end) = captures[0].unwrap();
+          // Step past empty matches so the search always moves forward
+          from = if end == start { end + 1 } else { end };
+          all.push(captures);
+        }
+        None => break,
+      }
+    }
+
+    Ok(all)
+  }
+
+  fn run(
+    &self,
+    input: &[char],
+    start: usize,
+    memo: &mut Option<Memo>,
+  ) -> Result<Option<Vec<Option<usize>>>, String> {
+    let mut steps = 0;
+
+    let mut slots = vec![None; (self.groups + 1) * 2];
+    let mut stack = vec![Frame::Thread(0, start)];
+
+    while let Some(frame) = stack.pop() {
+      let (mut pc, mut pos) = match frame {
+        Frame::Restore(slot, value) => {
+          slots[slot] = value;
+          continue;
+        }
+        Frame::Thread(pc, pos) => (pc, pos),
+      };
+
+      loop {
+        steps += 1;
+        if steps % INTERRUPT_INTERVAL == 1
+          && self.interrupt.as_ref().is_some_and(Interrupt::is_interrupted)
+        {
+          return Err(String::from("Regex search interrupted"));
+        }
+
+        match memo {
+          Some(memo) => {
+            if !memo.visit(pc, pos) {
+              break;
+            }
+          }
+          None => {
+            if steps > STEP_LIMIT {
+              return Err(String::from("Regex is too complex to match this input"));
+            }
+          }
+        }
+
+        match &self.program[pc] {
+          Inst::Char(c) => {
+            if pos < input.len() && chars_equal(input[pos], *c, self.ignore_case) {
+              pc += 1;
+              pos += 1;
+            } else {
+              break;
+            }
+          }
+          Inst::Any => {
+            if pos < input.len() && input[pos] != '\n' {
+              pc += 1;
+              pos += 1;
+            } else {
+              break;
+            }
+          }
+          Inst::Step => {
+            if pos < input.len() {
+              pc += 1;
+              pos += 1;
+            } else {
+              break;
+            }
+          }
+          Inst::Class(items, negated) => {
+            if pos < input.len() && class_matches(items, *negated, input[pos], self.ignore_case) {
+              pc += 1;
+              pos += 1;
+            } else {
+              break;
+            }
+          }
+          Inst::Start => {
+            if pos == 0 {
+              pc += 1;
+            } else {
+              break;
+            }
+          }
+          Inst::End => {
+            if pos == input.len() {
+              pc += 1;
+            } else {
+              break;
+            }
+          }
+          Inst::WordBoundary(expected) => {
+            let before = pos > 0 && is_word(input[pos - 1]);
+            let after = pos < input.len() && is_word(input[pos]);
+            if (before != after) == *expected {
+              pc += 1;
+            } else {
+              break;
+            }
+          }
+          Inst::Backref(n) => match (slots[n * 2], slots[n * 2 + 1]) {
+            (Some(s), Some(e)) => {
+              let len = e - s;
+              if pos + len <= input.len()
+                && (0..len).all(|i| chars_equal(input[s + i], input[pos + i], self.ignore_case))
+              {
+                pc += 1;
+                pos += len;
+              } else {
+                break;
+              }
+            }
+            // A group that did not participate matches the empty string
+            _ => pc += 1,
+          },
+          Inst::Save(slot) => {
+            stack.push(Frame::Restore(*slot, slots[*slot]));
+            slots[*slot] = Some(pos);
+            pc += 1;
+          }
+          Inst::Split(first, second) => {
+            stack.push(Frame::Thread(*second, pos));
+            pc = *first;
+          }
+          Inst::Jump(target) => pc = *target,
+          Inst::Match => return Ok(Some(slots)),
+        }
+      }
+    }
+
+    Ok(None)
+  }
+}
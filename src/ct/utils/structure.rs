@@ -7,6 +7,12 @@ use ct::syntax::std::*;
 pub struct Struct {
   pub constructor: Function,
   pub prototype: HashMap<String, CtToken>,
+  // Declared by a script, so calling its name constructs an instance
+  pub declared: bool,
+  pub name: String,
+  // The struct this one extends, as it was when this one was declared
+  pub parent: Option<Box<Struct>>,
+  pub interfaces: Vec<String>,
 }
 
 impl Struct {
@@ -33,39 +39,203 @@ impl Struct {
         }
       },
       prototype: prototype,
+      declared: false,
+      name: String::new(),
+      parent: None,
+      interfaces: Vec::new(),
     }
   }
 
+  // The constructor binds its arguments to the fields like parameters, defaults included. Inherited
+  // fields come first, unless the struct declares them again
+  pub fn declare(
+    name: &str,
+    parent: Option<Struct>,
+    interfaces: Vec<String>,
+    fields: Vec<CtToken>,
+    methods: Vec<Function>,
+  ) -> Struct {
+    let mut args: Vec<CtToken> = match &parent {
+      Some(parent) => parent
+        .constructor
+        .header
+        .args
+        .iter()
+        .filter(|inherited| {
+          !fields
+            .iter()
+            .any(|field| field_name(field) == field_name(inherited))
+        })
+        .cloned()
+        .collect(),
+      None => Vec::new(),
+    };
+    args.extend(fields);
+
+    let constructor = Function {
+      header: FunctionHeader {
+        name: String::from(name),
+        args,
+        return_type: types::THIS,
+      },
+      body: Vec::new(),
+    };
+
+    let mut s = Struct::new(Some(constructor), HashMap::new());
+    s.constructor.header.name = String::from(name);
+    s.declared = true;
+    s.parent = parent.map(Box::new);
+    s.interfaces = interfaces;
+    for method in methods {
+      let name = method.header.name.clone();
+      s.set(&name, Some(Token::Function(method)));
+    }
+
+    s
+  }
+
   pub fn get(&self, name: &String) -> CtToken {
     if self.prototype.contains_key(name) {
       self.prototype[name].clone()
     } else {
-      None
+      match &self.parent {
+        Some(parent) => parent.get(name),
+        None => None,
+      }
+    }
+  }
+
+  // The struct in the chain that defines a method, so `super` inside it can start from its parent
+  pub fn owner(&self, name: &String) -> Option<&Struct> {
+    if self.prototype.contains_key(name) {
+      Some(self)
+    } else {
+      self.parent.as_ref().and_then(|parent| parent.owner(name))
     }
   }
 
+  // Whether this struct is, extends or implements the given type
+  pub fn is_a(&self, name: &str) -> bool {
+    self.name == name
+      || self.interfaces.iter().any(|interface| interface == name)
+      || self.parent.as_ref().is_some_and(|parent| parent.is_a(name))
+  }
+
   pub fn set(&mut self, name: &String, value: CtToken) -> CtToken {
     self.prototype.insert(name.clone(), value);
     self.get(name)
   }
+
+  // Adds a method whose body forwards its arguments, receiver first, to a predef
+  pub fn define_native(&mut self, name: &str, native: &str, args: &[&str]) {
+    let function = Function {
+      header: FunctionHeader {
+        name: String::from(name),
+        args: args
+          .iter()
+          .map(|arg| {
+            Some(Token::Variable {
+              name: String::from(*arg),
+              return_type: types::ANY,
+            })
+          })
+          .collect(),
+        return_type: types::ANY,
+      },
+      body: vec![Some(Token::Return(Box::new(Some(Token::Invoke {
+        callee: Box::new(Some(Token::Native(String::from(native)))),
+        args: args
+          .iter()
+          .map(|arg| Some(Token::VariableCall(String::from(*arg))))
+          .collect(),
+      }))))],
+    };
+
+    self.set(&String::from(name), Some(Token::Function(function)));
+  }
+}
+
+fn field_name(field: &CtToken) -> Option<&String> {
+  match field {
+    Some(Token::Variable { name, .. }) => Some(name),
+    Some(Token::Binary { left, .. }) => match &**left {
+      Some(Token::Variable { name, .. }) => Some(name),
+      _ => None,
+    },
+    _ => None,
+  }
 }
 
 pub struct Structures {
   structs: HashMap<String, Struct>,
+  interfaces: HashMap<String, Vec<FunctionHeader>>,
 }
 
 impl Structures {
   pub fn new() -> Structures {
     Structures {
       structs: HashMap::new(),
+      interfaces: HashMap::new(),
     }
   }
 
-  pub fn append(&mut self, name: &str, structure: Struct) {
+  // Fails when the struct is missing a method of an interface it implements, or takes a different
+  // number of parameters for it
+  pub fn append(&mut self, name: &str, mut structure: Struct) -> Result<(), String> {
+    structure.name = String::from(name);
+
+    for interface in structure.interfaces.iter() {
+      let methods = match self.interfaces.get(interface) {
+        Some(methods) => methods,
+        None => return Err(format!("Unknown interface {} implemented by {}", interface, name)),
+      };
+
+      for method in methods {
+        match structure.get(&method.name) {
+          Some(Token::Function(function)) if function.header.args.len() == method.args.len() => (),
+          Some(Token::Function(function)) => {
+            return Err(format!(
+              "{} implements {} of interface {}, but it should be {}",
+              name,
+              function.header.signature(),
+              interface,
+              method.signature()
+            ))
+          }
+          _ => {
+            return Err(format!(
+              "{} does not implement {} of interface {}",
+              name,
+              method.signature(),
+              interface
+            ))
+          }
+        }
+      }
+    }
+
     self.structs.insert(String::from(name), structure);
+    Ok(())
+  }
+
+  pub fn append_interface(
+    &mut self,
+    name: &str,
+    methods: Vec<FunctionHeader>,
+  ) -> Result<(), String> {
+    if self.interfaces.contains_key(name) || self.structs.contains_key(name) {
+      return Err(format!("Type already exists: {}", name));
+    }
+
+    self.interfaces.insert(String::from(name), methods);
+    Ok(())
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Struct> {
+    self.structs.get(name)
   }
 
-  pub fn get(&self, name: &str) -> &Struct {
-    &self.structs[name]
+  pub fn contains(&self, name: &str) -> bool {
+    self.structs.contains_key(name) || self.interfaces.contains_key(name)
   }
 }
@@ -1,35 +1,184 @@
 mod ct;
 
-use ct::core::itp::Interpreter;
+use ct::core::itp::{Interpreter, MAX_CALL_DEPTH};
+use ct::modules::csv;
 use ct::modules::filestream;
 use ct::modules::iostream;
+use ct::modules::json;
+use ct::modules::os;
+use ct::modules::path;
+use ct::modules::process;
+use ct::modules::random;
+use ct::modules::regex;
 use ct::modules::string;
+use ct::modules::time;
 use ct::structs;
 use ct::syntax::std::Token;
+use ct::utils::limits::Limits;
 
 use std::env;
+use std::panic;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
+
+// Native stack reserved per script call, so hitting the call depth limit is an error, not a crash
+const CALL_STACK_SIZE: usize = 256 * 1024;
+const BASE_STACK_SIZE: usize = 8 * 1024 * 1024;
+// The most stack the runner reserves, deeper call depths are capped to fit
+const MAX_STACK_SIZE: usize = 1024 * 1024 * 1024;
+
+// Ctrl-C stops the script at its next step instead of killing the process, so the files it wrote
+// are still flushed. A second one kills it, in case the script is stuck in a native
+#[cfg(unix)]
+mod sigint {
+  use ct::utils::limits::Interrupt;
+
+  use std::sync::atomic::{AtomicBool, Ordering};
+  use std::thread;
+  use std::time::Duration;
+
+  const SIGINT: i32 = 2;
+  const SIG_DFL: usize = 0;
+
+  static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+  extern "C" {
+    fn signal(signum: i32, handler: usize) -> usize;
+  }
+
+  // Only an atomic store is safe in a signal handler, a watcher passes the signal on
+  extern "C" fn received(_: i32) {
+    RECEIVED.store(true, Ordering::SeqCst);
+  }
+
+  pub fn forward(interrupt: Interrupt) {
+    unsafe {
+      signal(SIGINT, received as extern "C" fn(i32) as usize);
+    }
+    thread::spawn(move || {
+      while !RECEIVED.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(10));
+      }
+      interrupt.interrupt();
+      unsafe {
+        signal(SIGINT, SIG_DFL);
+      }
+    });
+  }
+}
 
 fn main() {
-  if let Some(file_name) = env::args().nth(1) {
+  let limit = (MAX_STACK_SIZE - BASE_STACK_SIZE) / CALL_STACK_SIZE;
+  let mut max_call_depth = setting("CORTEN_MAX_CALL_DEPTH").unwrap_or(MAX_CALL_DEPTH);
+  if max_call_depth > limit {
+    eprintln!("CORTEN_MAX_CALL_DEPTH is capped at {}", limit);
+    max_call_depth = limit;
+  }
+  let stack_size = max_call_depth * CALL_STACK_SIZE + BASE_STACK_SIZE;
+
+  let runner = thread::Builder::new()
+    .stack_size(stack_size)
+    .spawn(move || run(max_call_depth));
+  match runner {
+    // The script's handles are dropped and flushed by the time its exit code comes back
+    Ok(runner) => match runner.join() {
+      Ok(0) => (),
+      Ok(code) => std::process::exit(code),
+      Err(_) => std::process::exit(1),
+    },
+    Err(e) => {
+      eprintln!("Unable to start the interpreter with a {} byte stack: {}", stack_size, e);
+      std::process::exit(1);
+    }
+  }
+}
+
+// Limits for running untrusted scripts come from the environment, like the call depth
+fn setting<T: FromStr>(name: &str) -> Option<T> {
+  env::var(name).ok().and_then(|value| value.parse().ok())
+}
+
+fn run(max_call_depth: usize) -> i32 {
+  let mut args = env::args().skip(1);
+
+  if let Some(file_name) = args.next() {
     match filestream::read_file(vec![Some(Token::String(file_name))]) {
       Some(file) => match file {
         Token::String(content) => {
           let mut interpreter = Interpreter::new(content);
+          interpreter.set_max_call_depth(max_call_depth);
+          interpreter.set_limits(Limits {
+            fuel: setting("CORTEN_FUEL"),
+            time: setting("CORTEN_TIMEOUT_MS").map(Duration::from_millis),
+            memory: setting("CORTEN_MAX_MEMORY"),
+            output: setting("CORTEN_MAX_OUTPUT"),
+          });
 
           interpreter.append_module(iostream::IOStream);
           interpreter.append_module(filestream::FileStream);
           interpreter.append_module(string::Str);
+          interpreter.append_module(random::Random);
+          interpreter.append_module(path::PathModule);
+          interpreter.append_module(os::Os::new(args.collect()));
+          interpreter.append_module(process::Process);
+          interpreter.append_module(json::Json);
+          interpreter.append_module(csv::Csv);
+          interpreter.append_module(time::Time);
+          interpreter.append_module(regex::RegexModule);
+
+          // Sandboxes name the natives scripts may not call, like `CORTEN_DENY=exit,sleep,run`
+          for name in env::var("CORTEN_DENY").unwrap_or_default().split(',') {
+            let name = name.trim();
+            if !name.is_empty() {
+              let message = format!("{} is not allowed here", name);
+              interpreter.replace_native(
+                name,
+                Box::new(move |_| Some(Token::Error(message.clone()))),
+              );
+            }
+          }
+
+          #[cfg(unix)]
+          sigint::forward(interpreter.interrupt_handle());
 
           interpreter.append_struct("String", structs::string::string_struct());
+          interpreter.append_struct("Array", structs::array::array_struct());
+          interpreter.append_struct("Iterator", structs::iterator::iterator_struct());
+          interpreter.append_struct("File", structs::file::file_struct());
+          interpreter.append_struct("DateTime", structs::datetime::datetime_struct());
+          interpreter.append_struct("Regex", structs::regex::regex_struct());
+
+          // Parse and runtime errors unwind with their message as payload
+          panic::set_hook(Box::new(|info| {
+            if let Some(message) = info.payload().downcast_ref::<String>() {
+              eprintln!("{}", message);
+            } else if let Some(message) = info.payload().downcast_ref::<&str>() {
+              eprintln!("{}", message);
+            } else {
+              eprintln!("{}", info);
+            }
+          }));
 
-          interpreter.execute();
+          match panic::catch_unwind(panic::AssertUnwindSafe(|| interpreter.execute())) {
+            Ok(Ok(())) => interpreter.exit_code().unwrap_or(0),
+            Ok(Err(err)) => {
+              eprintln!("{}", err);
+              1
+            }
+            Err(_) => 1,
+          }
+        }
+        Token::Error(err) => {
+          eprintln!("{}", err);
+          1
         }
-        Token::Error(err) => println!("{}", err),
-        _ => (),
+        _ => 0,
       },
-      _ => (),
+      _ => 0,
     }
   } else {
     println!("Command line interpreter is not yet implemented.");
+    0
   }
 }